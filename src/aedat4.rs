@@ -0,0 +1,378 @@
+// AEDAT 4.0 encoder (https://docs.inivation.com/software/software-advanced-usage/file-formats/aedat-4.0.html)
+// The file has an info header (XML description of the streams) followed by packets.
+// Headers and packets are size-prefixed flatbuffers, built by hand since the layouts are fixed.
+use std::io::Write;
+
+pub const FILE_EXTENSION: &str = ".aedat4";
+const SIGNATURE: &str = "#!AER-DAT4.0\r\n";
+
+const EVENTS_STREAM_ID: i32 = 0;
+const TRIGGERS_STREAM_ID: i32 = 1;
+
+// dv::TriggerType
+const EXTERNAL_SIGNAL_RISING_EDGE: u8 = 1;
+const EXTERNAL_SIGNAL_FALLING_EDGE: u8 = 2;
+
+// both dv::Event and dv::Trigger structs are 16 bytes long (8-byte alignment)
+const ELEMENT_LENGTH: usize = 16;
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// `ts_offset` is the difference between the (absolute) packet timestamps and the sensor timestamps.
+pub fn write_header(
+    output: &mut impl Write,
+    width: u16,
+    height: u16,
+    source: &str,
+    ts_offset: i64,
+) -> std::io::Result<()> {
+    let source = escape_xml(source);
+    let mut info = String::new();
+    info.push_str("<dv version=\"2.0\">\n");
+    info.push_str("    <node name=\"outInfo\" path=\"/mainloop/kairos/outInfo/\">\n");
+    for (stream_id, name, identifier, description, sizes) in [
+        (
+            EVENTS_STREAM_ID,
+            "events",
+            "EVTS",
+            "Array of events (polarity ON/OFF).",
+            true,
+        ),
+        (
+            TRIGGERS_STREAM_ID,
+            "triggers",
+            "TRIG",
+            "Array of triggers.",
+            false,
+        ),
+    ] {
+        info.push_str(&format!(
+            "        <node name=\"{stream_id}\" path=\"/mainloop/kairos/outInfo/{stream_id}/\">\n"
+        ));
+        info.push_str("            <attr key=\"compression\" type=\"string\">NONE</attr>\n");
+        info.push_str(
+            "            <attr key=\"originalModuleName\" type=\"string\">kairos</attr>\n",
+        );
+        info.push_str(&format!(
+            "            <attr key=\"originalOutputName\" type=\"string\">{name}</attr>\n"
+        ));
+        info.push_str(&format!(
+            "            <attr key=\"typeDescription\" type=\"string\">{description}</attr>\n"
+        ));
+        info.push_str(&format!(
+            "            <attr key=\"typeIdentifier\" type=\"string\">{identifier}</attr>\n"
+        ));
+        info.push_str(&format!(
+            "            <node name=\"info\" path=\"/mainloop/kairos/outInfo/{stream_id}/info/\">\n"
+        ));
+        if sizes {
+            info.push_str(&format!(
+                "                <attr key=\"sizeX\" type=\"int\">{width}</attr>\n"
+            ));
+            info.push_str(&format!(
+                "                <attr key=\"sizeY\" type=\"int\">{height}</attr>\n"
+            ));
+        }
+        info.push_str(&format!(
+            "                <attr key=\"source\" type=\"string\">{source}</attr>\n"
+        ));
+        info.push_str(&format!(
+            "                <attr key=\"tsOffset\" type=\"long\">{ts_offset}</attr>\n"
+        ));
+        info.push_str("            </node>\n");
+        info.push_str("        </node>\n");
+    }
+    info.push_str("    </node>\n");
+    info.push_str("</dv>\n");
+
+    // IOHeader table, compression (NONE) and dataTablePosition (-1) use default values
+    let string_length = info.len() + 1; // null-terminated
+    let padding = (4 - (string_length % 4)) % 4;
+    let mut buffer = Vec::with_capacity(32 + string_length + padding);
+    buffer.extend_from_slice(&0u32.to_le_bytes()); // 0: size prefix, set below
+    buffer.extend_from_slice(&16u32.to_le_bytes()); // 4: root table offset (20 - 4)
+    buffer.extend_from_slice(&10u16.to_le_bytes()); // 8: vtable length
+    buffer.extend_from_slice(&8u16.to_le_bytes()); // 10: table length
+    buffer.extend_from_slice(&0u16.to_le_bytes()); // 12: compression (default)
+    buffer.extend_from_slice(&0u16.to_le_bytes()); // 14: dataTablePosition (default)
+    buffer.extend_from_slice(&4u16.to_le_bytes()); // 16: infoNode
+    buffer.extend_from_slice(&[0u8; 2]); // 18: padding
+    buffer.extend_from_slice(&12i32.to_le_bytes()); // 20: table, vtable offset (20 - 8)
+    buffer.extend_from_slice(&4u32.to_le_bytes()); // 24: infoNode offset (28 - 24)
+    buffer.extend_from_slice(&(info.len() as u32).to_le_bytes()); // 28: string length
+    buffer.extend_from_slice(info.as_bytes()); // 32: string
+    buffer.resize(buffer.len() + 1 + padding, 0u8);
+    let size = (buffer.len() - 4) as u32;
+    buffer[0..4].copy_from_slice(&size.to_le_bytes());
+
+    output.write_all(SIGNATURE.as_bytes())?;
+    output.write_all(&buffer)?;
+    Ok(())
+}
+
+// Accumulates events or triggers and writes them as a size-prefixed EventPacket or TriggerPacket.
+pub struct Packet {
+    stream_id: i32,
+    identifier: &'static [u8; 4],
+    buffer: Vec<u8>,
+}
+
+impl Packet {
+    pub fn events() -> Self {
+        Self::new(EVENTS_STREAM_ID, b"EVTS")
+    }
+
+    pub fn triggers() -> Self {
+        Self::new(TRIGGERS_STREAM_ID, b"TRIG")
+    }
+
+    fn new(stream_id: i32, identifier: &'static [u8; 4]) -> Self {
+        let mut packet = Self {
+            stream_id,
+            identifier,
+            buffer: Vec::new(),
+        };
+        packet.clear();
+        packet
+    }
+
+    // The layout starts with the 8-byte AEDAT packet header (stream id and size),
+    // hence the flatbuffer (and its elements) are 8-byte aligned.
+    fn clear(&mut self) {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(&self.stream_id.to_le_bytes()); // stream id
+        self.buffer.extend_from_slice(&0i32.to_le_bytes()); // packet size, set in write_to
+        self.buffer.extend_from_slice(&0u32.to_le_bytes()); // 0: size prefix, set in write_to
+        self.buffer.extend_from_slice(&16u32.to_le_bytes()); // 4: root table offset (20 - 4)
+        self.buffer.extend_from_slice(self.identifier); // 8: file identifier
+        self.buffer.extend_from_slice(&6u16.to_le_bytes()); // 12: vtable length
+        self.buffer.extend_from_slice(&8u16.to_le_bytes()); // 14: table length
+        self.buffer.extend_from_slice(&4u16.to_le_bytes()); // 16: elements
+        self.buffer.extend_from_slice(&[0u8; 2]); // 18: padding
+        self.buffer.extend_from_slice(&8i32.to_le_bytes()); // 20: table, vtable offset (20 - 12)
+        self.buffer.extend_from_slice(&4u32.to_le_bytes()); // 24: elements offset (28 - 24)
+        self.buffer.extend_from_slice(&0u32.to_le_bytes()); // 28: elements length, set in write_to
+    }
+
+    fn len(&self) -> usize {
+        (self.buffer.len() - 40) / ELEMENT_LENGTH
+    }
+
+    pub fn push_event(&mut self, t: i64, x: u16, y: u16, on: bool) {
+        self.buffer.extend_from_slice(&t.to_le_bytes());
+        self.buffer.extend_from_slice(&(x as i16).to_le_bytes());
+        self.buffer.extend_from_slice(&(y as i16).to_le_bytes());
+        self.buffer.push(on as u8);
+        self.buffer.extend_from_slice(&[0u8; 3]);
+    }
+
+    pub fn push_trigger(&mut self, t: i64, rising: bool) {
+        self.buffer.extend_from_slice(&t.to_le_bytes());
+        self.buffer.push(if rising {
+            EXTERNAL_SIGNAL_RISING_EDGE
+        } else {
+            EXTERNAL_SIGNAL_FALLING_EDGE
+        });
+        self.buffer.extend_from_slice(&[0u8; 7]);
+    }
+
+    // Writes the packet if it is not empty and clears it.
+    pub fn write_to(&mut self, output: &mut impl Write) -> std::io::Result<()> {
+        let length = self.len();
+        if length > 0 {
+            let size = (self.buffer.len() - 8) as u32;
+            self.buffer[4..8].copy_from_slice(&(size as i32).to_le_bytes());
+            self.buffer[8..12].copy_from_slice(&(size - 4).to_le_bytes());
+            self.buffer[36..40].copy_from_slice(&(length as u32).to_le_bytes());
+            output.write_all(&self.buffer)?;
+            self.clear();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flatbuffers::{Follow, ForwardsUOffset, InvalidFlatbuffer, Table, Vector, Verifiable};
+
+    // Minimal readers for the dv schemas, modelled on flatc's generated code
+    struct IoHeader<'a>(Table<'a>);
+
+    impl<'a> Follow<'a> for IoHeader<'a> {
+        type Inner = Self;
+        unsafe fn follow(buffer: &'a [u8], location: usize) -> Self {
+            Self(unsafe { Table::new(buffer, location) })
+        }
+    }
+
+    impl Verifiable for IoHeader<'_> {
+        fn run_verifier(
+            verifier: &mut flatbuffers::Verifier,
+            position: usize,
+        ) -> Result<(), InvalidFlatbuffer> {
+            verifier
+                .visit_table(position)?
+                .visit_field::<i32>("compression", 4, false)?
+                .visit_field::<i64>("dataTablePosition", 6, false)?
+                .visit_field::<ForwardsUOffset<&str>>("infoNode", 8, false)?
+                .finish();
+            Ok(())
+        }
+    }
+
+    impl<'a> IoHeader<'a> {
+        fn compression(&self) -> i32 {
+            unsafe { self.0.get::<i32>(4, Some(0)) }.unwrap()
+        }
+
+        fn data_table_position(&self) -> i64 {
+            unsafe { self.0.get::<i64>(6, Some(-1)) }.unwrap()
+        }
+
+        fn info_node(&self) -> Option<&'a str> {
+            unsafe { self.0.get::<ForwardsUOffset<&str>>(8, None) }
+        }
+    }
+
+    // dv::Event and dv::Trigger structs (16 bytes, 8-byte aligned)
+    #[repr(C, align(8))]
+    struct Element([u8; ELEMENT_LENGTH]);
+
+    impl<'a> Follow<'a> for Element {
+        type Inner = [u8; ELEMENT_LENGTH];
+        unsafe fn follow(buffer: &'a [u8], location: usize) -> Self::Inner {
+            buffer[location..location + ELEMENT_LENGTH]
+                .try_into()
+                .unwrap()
+        }
+    }
+
+    impl flatbuffers::SimpleToVerifyInSlice for Element {}
+
+    // EventPacket and TriggerPacket tables
+    struct ElementsPacket<'a>(Table<'a>);
+
+    impl<'a> Follow<'a> for ElementsPacket<'a> {
+        type Inner = Self;
+        unsafe fn follow(buffer: &'a [u8], location: usize) -> Self {
+            Self(unsafe { Table::new(buffer, location) })
+        }
+    }
+
+    impl Verifiable for ElementsPacket<'_> {
+        fn run_verifier(
+            verifier: &mut flatbuffers::Verifier,
+            position: usize,
+        ) -> Result<(), InvalidFlatbuffer> {
+            verifier
+                .visit_table(position)?
+                .visit_field::<ForwardsUOffset<Vector<Element>>>("elements", 4, false)?
+                .finish();
+            Ok(())
+        }
+    }
+
+    impl<'a> ElementsPacket<'a> {
+        fn elements(&self) -> Vec<[u8; ELEMENT_LENGTH]> {
+            unsafe { self.0.get::<ForwardsUOffset<Vector<Element>>>(4, None) }
+                .map(|elements| elements.iter().collect())
+                .unwrap_or_default()
+        }
+    }
+
+    // Splits an AEDAT packet into its stream id and flatbuffer
+    fn split_packet(bytes: &[u8]) -> (i32, &[u8], &[u8]) {
+        let stream_id = i32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        let size = i32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        (stream_id, &bytes[8..8 + size], &bytes[8 + size..])
+    }
+
+    #[test]
+    fn flatbuffers_decode_header_and_packets() {
+        let mut bytes = Vec::new();
+        write_header(&mut bytes, 640, 480, "EVK4 <00000000>", 1_000).unwrap();
+        assert!(bytes.starts_with(SIGNATURE.as_bytes()));
+        let header = &bytes[SIGNATURE.len()..];
+        let size = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+        assert_eq!(size + 4, header.len());
+        let io_header = flatbuffers::size_prefixed_root::<IoHeader>(header).unwrap();
+        assert_eq!(io_header.compression(), 0);
+        assert_eq!(io_header.data_table_position(), -1);
+        let info_node = io_header.info_node().unwrap();
+        assert!(info_node.starts_with("<dv version=\"2.0\">"));
+        assert!(info_node.contains("<attr key=\"sizeX\" type=\"int\">640</attr>"));
+        assert!(info_node.contains("<attr key=\"sizeY\" type=\"int\">480</attr>"));
+        assert!(info_node.contains("EVK4 &lt;00000000&gt;"));
+        assert!(info_node.contains("<attr key=\"tsOffset\" type=\"long\">1000</attr>"));
+
+        let mut bytes = Vec::new();
+        let mut events = Packet::events();
+        events.write_to(&mut bytes).unwrap();
+        assert!(bytes.is_empty());
+        events.push_event(1_000_000_000_000, 639, 0, true);
+        events.push_event(1_000_000_000_200, 0, 479, false);
+        events.write_to(&mut bytes).unwrap();
+        let mut triggers = Packet::triggers();
+        triggers.push_trigger(1_000_000_000_100, true);
+        triggers.push_trigger(1_000_000_000_300, false);
+        triggers.push_trigger(1_000_000_000_500, true);
+        triggers.write_to(&mut bytes).unwrap();
+
+        let (stream_id, events, rest) = split_packet(&bytes);
+        assert_eq!(stream_id, EVENTS_STREAM_ID);
+        assert!(flatbuffers::buffer_has_identifier(events, "EVTS", true));
+        let events = flatbuffers::size_prefixed_root::<ElementsPacket>(events)
+            .unwrap()
+            .elements();
+        let events: Vec<(i64, i16, i16, bool)> = events
+            .iter()
+            .map(|event| {
+                (
+                    i64::from_le_bytes(event[0..8].try_into().unwrap()),
+                    i16::from_le_bytes(event[8..10].try_into().unwrap()),
+                    i16::from_le_bytes(event[10..12].try_into().unwrap()),
+                    event[12] == 1,
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (1_000_000_000_000, 639, 0, true),
+                (1_000_000_000_200, 0, 479, false)
+            ]
+        );
+
+        let (stream_id, triggers, rest) = split_packet(rest);
+        assert_eq!(stream_id, TRIGGERS_STREAM_ID);
+        assert!(rest.is_empty());
+        assert!(flatbuffers::buffer_has_identifier(triggers, "TRIG", true));
+        let triggers = flatbuffers::size_prefixed_root::<ElementsPacket>(triggers)
+            .unwrap()
+            .elements();
+        let triggers: Vec<(i64, u8)> = triggers
+            .iter()
+            .map(|trigger| {
+                (
+                    i64::from_le_bytes(trigger[0..8].try_into().unwrap()),
+                    trigger[8],
+                )
+            })
+            .collect();
+        assert_eq!(
+            triggers,
+            vec![
+                (1_000_000_000_100, EXTERNAL_SIGNAL_RISING_EDGE),
+                (1_000_000_000_300, EXTERNAL_SIGNAL_FALLING_EDGE),
+                (1_000_000_000_500, EXTERNAL_SIGNAL_RISING_EDGE)
+            ]
+        );
+    }
+}
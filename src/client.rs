@@ -1,8 +1,11 @@
 use crate::constants;
 use crate::device;
+use crate::manage;
 use crate::now_utc_string;
 use crate::protocol;
+use crate::recordings;
 use crate::stack;
+use crate::video;
 use anyhow::anyhow;
 
 use neuromorphic_drivers::UsbDevice;
@@ -117,6 +120,92 @@ fn spawn_stream(
     })
 }
 
+// recordings that are being written, converted, or managed cannot be deleted, renamed, or moved
+fn check_idle(context: &crate::Context, name: &str) -> Result<(), anyhow::Error> {
+    if context.busy_recordings.contains(name) {
+        return Err(anyhow!(
+            "the recording \"{}\" is being deleted, renamed, moved, or trimmed",
+            name
+        ));
+    }
+    if context.statistics_recording.as_deref() == Some(name) {
+        return Err(anyhow!("the statistics of \"{}\" are being computed", name));
+    }
+    let recordings = &context.shared_recordings_state.recordings;
+    match recordings
+        .binary_search_by(|recording| recording.name.as_str().cmp(name))
+        .map(|index| &recordings[index].state)
+    {
+        Ok(protocol::RecordingState::Ongoing) => {
+            Err(anyhow!("the recording \"{}\" is ongoing", name))
+        }
+        Ok(protocol::RecordingState::Queued { .. })
+        | Ok(protocol::RecordingState::Converting { .. }) => {
+            Err(anyhow!("the recording \"{}\" is being converted", name))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Marks the idle recordings as busy and returns their names (the other names are reported).
+// The names are released by spawn_manage once the operation completes.
+fn mark_busy(
+    context: &mut crate::Context,
+    names: Vec<String>,
+    action: &str,
+    errors: &mut Vec<String>,
+) -> Vec<String> {
+    let mut busy = Vec::with_capacity(names.len());
+    for name in names {
+        match check_idle(context, &name) {
+            Ok(()) => {
+                context.busy_recordings.insert(name.clone());
+                busy.push(name);
+            }
+            Err(error) => {
+                errors.push(format!("{} \"{}\" failed: {}", action, name, error));
+            }
+        }
+    }
+    busy
+}
+
+// file operations may copy recordings (moves to another file system), hence they run in
+// a blocking task and we do not wait for the result
+fn spawn_manage<Operation>(
+    context: &std::sync::Arc<tokio::sync::Mutex<crate::Context>>,
+    data_directory: String,
+    mut errors: Vec<String>,
+    busy: Vec<String>,
+    operation: Operation,
+) where
+    Operation: FnOnce(&std::path::Path, &mut Vec<String>) + Send + 'static,
+{
+    let context = context.clone();
+    tokio::spawn(async move {
+        errors = match tokio::task::spawn_blocking(move || {
+            operation(std::path::Path::new(&data_directory), &mut errors);
+            errors
+        })
+        .await
+        {
+            Ok(errors) => errors,
+            Err(error) => vec![format!("Managing recordings failed: {}", error)],
+        };
+        let mut context_guard = context.lock().await;
+        for name in busy {
+            context_guard.busy_recordings.remove(&name);
+        }
+        context_guard.notify_statistics.notify_one();
+        if !errors.is_empty() {
+            context_guard.shared_client_state.errors.extend(errors);
+            if let Err(error) = context_guard.broadcast_shared_client_state() {
+                println!("broadcast_shared_client_state error: {error:?}");
+            }
+        }
+    });
+}
+
 async fn handle_client_message(
     client_id: ClientId,
     incoming_session_id: usize,
@@ -207,7 +296,7 @@ async fn handle_client_message(
             }
             if let Some(device_proxy) = context_guard.id_to_device.get_mut(&device_id) {
                 match device_proxy.inner.as_ref() {
-                    neuromorphic_drivers::Device::InivationDavis346(device) => {
+                    device::Inner::Usb(neuromorphic_drivers::Device::InivationDavis346(device)) => {
                         match configuration {
                             neuromorphic_drivers::Configuration::InivationDavis346(
                                 configuration,
@@ -222,8 +311,9 @@ async fn handle_client_message(
                             ),
                         }
                     }
-                    neuromorphic_drivers::Device::InivationDvxplorer(device) => match configuration
-                    {
+                    device::Inner::Usb(neuromorphic_drivers::Device::InivationDvxplorer(
+                        device,
+                    )) => match configuration {
                         neuromorphic_drivers::Configuration::InivationDvxplorer(configuration) => {
                             device.update_configuration(configuration);
                             device_proxy
@@ -234,28 +324,42 @@ async fn handle_client_message(
                             println!("mismatch between the configuration type and the device type")
                         }
                     },
-                    neuromorphic_drivers::Device::PropheseeEvk3Hd(device) => match configuration {
-                        neuromorphic_drivers::Configuration::PropheseeEvk3Hd(configuration) => {
-                            device.update_configuration(configuration);
-                            device_proxy
-                                .configuration_changed
-                                .store(true, std::sync::atomic::Ordering::Release);
-                        }
-                        _ => {
-                            println!("mismatch between the configuration type and the device type")
-                        }
-                    },
-                    neuromorphic_drivers::Device::PropheseeEvk4(device) => match configuration {
-                        neuromorphic_drivers::Configuration::PropheseeEvk4(configuration) => {
-                            device.update_configuration(configuration);
-                            device_proxy
-                                .configuration_changed
-                                .store(true, std::sync::atomic::Ordering::Release);
+                    device::Inner::Usb(neuromorphic_drivers::Device::PropheseeEvk3Hd(device)) => {
+                        match configuration {
+                            neuromorphic_drivers::Configuration::PropheseeEvk3Hd(configuration) => {
+                                device.update_configuration(configuration);
+                                device_proxy
+                                    .configuration_changed
+                                    .store(true, std::sync::atomic::Ordering::Release);
+                            }
+                            _ => {
+                                println!(
+                                    "mismatch between the configuration type and the device type"
+                                )
+                            }
                         }
-                        _ => {
-                            println!("mismatch between the configuration type and the device type")
+                    }
+                    device::Inner::Usb(neuromorphic_drivers::Device::PropheseeEvk4(device)) => {
+                        match configuration {
+                            neuromorphic_drivers::Configuration::PropheseeEvk4(configuration) => {
+                                device.update_configuration(configuration);
+                                device_proxy
+                                    .configuration_changed
+                                    .store(true, std::sync::atomic::Ordering::Release);
+                            }
+                            _ => {
+                                println!(
+                                    "mismatch between the configuration type and the device type"
+                                )
+                            }
                         }
-                    },
+                    }
+                    device::Inner::Replay(_) => {
+                        println!("replay devices do not support configuration updates")
+                    }
+                    device::Inner::Synthetic(_) => {
+                        println!("synthetic devices do not support configuration updates")
+                    }
                 }
             } else {
                 println!("unknown device id {} in SetParameter message", device_id.0);
@@ -323,6 +427,64 @@ async fn handle_client_message(
             }
             Ok(())
         }
+        protocol::ClientMessage::UpdateDurability {
+            device_id,
+            durability,
+        } => {
+            let device_id = device::DeviceId(device_id);
+            let mut context_guard = context.lock().await;
+            if let Some(device) = context_guard.id_to_device.get_mut(&device_id) {
+                let mut record_configuration_guard = device
+                    .record_configuration
+                    .lock()
+                    .expect("record configuration mutex is poisoned");
+                record_configuration_guard.durability = durability;
+            } else {
+                println!(
+                    "unknown device id {} in UpdateDurability message",
+                    device_id.0
+                );
+            }
+            for context_device in context_guard.shared_client_state.devices.iter_mut() {
+                if context_device.id == device_id.0 {
+                    context_device.durability = durability;
+                    if let Err(error) = context_guard.broadcast_shared_client_state() {
+                        println!("broadcast_shared_client_state error: {error:?}");
+                    }
+                    break;
+                }
+            }
+            Ok(())
+        }
+        protocol::ClientMessage::UpdateSegmenting {
+            device_id,
+            segmenting,
+        } => {
+            let device_id = device::DeviceId(device_id);
+            let mut context_guard = context.lock().await;
+            if let Some(device) = context_guard.id_to_device.get_mut(&device_id) {
+                let mut record_configuration_guard = device
+                    .record_configuration
+                    .lock()
+                    .expect("record configuration mutex is poisoned");
+                record_configuration_guard.segmenting = segmenting;
+            } else {
+                println!(
+                    "unknown device id {} in UpdateSegmenting message",
+                    device_id.0
+                );
+            }
+            for context_device in context_guard.shared_client_state.devices.iter_mut() {
+                if context_device.id == device_id.0 {
+                    context_device.segmenting = segmenting;
+                    if let Err(error) = context_guard.broadcast_shared_client_state() {
+                        println!("broadcast_shared_client_state error: {error:?}");
+                    }
+                    break;
+                }
+            }
+            Ok(())
+        }
         protocol::ClientMessage::UpdateAutotrigger {
             device_id,
             autotrigger,
@@ -386,8 +548,21 @@ async fn handle_client_message(
             }
             Ok(())
         }
-        protocol::ClientMessage::Convert { mut names } => {
+        protocol::ClientMessage::Convert { mut names, options } => {
             let mut context_guard = context.lock().await;
+            let options = options.unwrap_or_else(|| context_guard.convert_options.clone());
+            if let Err(error) = recordings::check_variant(&options.variant)
+                .and_then(|_| video::check_options(&options.video))
+            {
+                context_guard
+                    .shared_client_state
+                    .errors
+                    .push(format!("Converting failed: {}", error));
+                if let Err(error) = context_guard.broadcast_shared_client_state() {
+                    println!("broadcast_shared_client_state error: {error:?}");
+                }
+                return Ok(());
+            }
             names.sort();
             let mut name_index = 0;
             let mut recording_index = 0;
@@ -401,12 +576,34 @@ async fn handle_client_message(
                         name_index += 1;
                     }
                     std::cmp::Ordering::Equal => {
-                        if let protocol::RecordingState::Complete { size_bytes, zip } =
-                            context_guard.shared_recordings_state.recordings[recording_index].state
+                        if context_guard.busy_recordings.contains(&names[name_index]) {
+                            let error = format!(
+                                "Converting \"{}\" failed: the recording is being deleted, renamed, moved, or trimmed",
+                                names[name_index]
+                            );
+                            context_guard.shared_client_state.errors.push(error);
+                            if let Err(error) = context_guard.broadcast_shared_client_state() {
+                                println!("broadcast_shared_client_state error: {error:?}");
+                            }
+                            name_index += 1;
+                            recording_index += 1;
+                            continue;
+                        }
+                        let recording =
+                            &mut context_guard.shared_recordings_state.recordings[recording_index];
+                        if let protocol::RecordingState::Complete {
+                            size_bytes,
+                            zip,
+                            ref mut variants,
+                        } = recording.state
                         {
-                            if !zip {
-                                context_guard.shared_recordings_state.recordings[recording_index]
-                                    .state = protocol::RecordingState::Queued { size_bytes, zip };
+                            if !options.is_converted(zip, variants) {
+                                recording.state = protocol::RecordingState::Queued {
+                                    size_bytes,
+                                    zip,
+                                    variants: std::mem::take(variants),
+                                    options: options.clone(),
+                                };
                             }
                         }
                         name_index += 1;
@@ -420,12 +617,66 @@ async fn handle_client_message(
             context_guard.notify_convert.notify_one();
             Ok(())
         }
-        protocol::ClientMessage::CancelConvert => {
+        protocol::ClientMessage::Trim {
+            name,
+            range,
+            new_name,
+        } => {
+            let mut context_guard = context.lock().await;
+            let mut errors = Vec::new();
+            // the new name is reserved as well if it is given (trim computes the default name)
+            let mut names = vec![name.clone()];
+            if !new_name.is_empty() && new_name != name {
+                names.push(new_name.clone());
+            }
+            let busy = mark_busy(&mut context_guard, names, "Trimming", &mut errors);
+            let idle = errors.is_empty();
+            let data_directory = context_guard.shared_recordings_state.data_directory.clone();
+            drop(context_guard);
+            spawn_manage(
+                context,
+                data_directory,
+                errors,
+                busy,
+                move |data_directory, errors| {
+                    if idle {
+                        match crate::trim::trim(data_directory, &name, &range, &new_name) {
+                            Ok(new_name) => {
+                                println!(
+                                    "{} | trimmed \"{}\" into \"{}\"",
+                                    now_utc_string(),
+                                    name,
+                                    new_name
+                                );
+                            }
+                            Err(error) => {
+                                errors.push(format!("Trimming \"{}\" failed: {}", name, error));
+                            }
+                        }
+                    }
+                },
+            );
+            Ok(())
+        }
+        protocol::ClientMessage::CancelConvert { names } => {
             let mut context_guard = context.lock().await;
             let mut changed = false;
             for recording in context_guard.shared_recordings_state.recordings.iter_mut() {
-                if let protocol::RecordingState::Queued { size_bytes, zip } = recording.state {
-                    recording.state = protocol::RecordingState::Complete { size_bytes, zip };
+                if !names.is_empty() && !names.contains(&recording.name) {
+                    continue;
+                }
+                if let protocol::RecordingState::Queued {
+                    size_bytes,
+                    zip,
+                    ref mut variants,
+                    ..
+                } = recording.state
+                {
+                    recording.state = protocol::RecordingState::Complete {
+                        size_bytes,
+                        zip,
+                        variants: std::mem::take(variants),
+                    };
                     changed = true;
                 }
             }
@@ -434,7 +685,241 @@ async fn handle_client_message(
                     println!("broadcast_recordings error: {error:?}");
                 }
             }
-            context_guard.notify_convert_cancel.notify_one();
+            for (name, cancelled) in context_guard.convert_cancellations.iter() {
+                if names.is_empty() || names.contains(name) {
+                    cancelled.store(true, std::sync::atomic::Ordering::Release);
+                }
+            }
+            Ok(())
+        }
+        protocol::ClientMessage::ComputeStatistics { names } => {
+            let mut context_guard = context.lock().await;
+            let names = context_guard
+                .shared_recordings_state
+                .recordings
+                .iter()
+                .filter(|recording| {
+                    (names.is_empty() || names.contains(&recording.name))
+                        && matches!(recording.state, protocol::RecordingState::Complete { .. })
+                })
+                .map(|recording| recording.name.clone())
+                .collect::<Vec<_>>();
+            for name in names {
+                if !context_guard.statistics_requests.contains(&name) {
+                    context_guard.statistics_requests.push_back(name);
+                }
+            }
+            context_guard.notify_statistics.notify_one();
+            Ok(())
+        }
+        protocol::ClientMessage::DeleteRecordings { names, trash } => {
+            let mut context_guard = context.lock().await;
+            let mut errors = Vec::new();
+            let names = mark_busy(&mut context_guard, names, "Deleting", &mut errors);
+            let data_directory = context_guard.shared_recordings_state.data_directory.clone();
+            drop(context_guard);
+            spawn_manage(
+                context,
+                data_directory,
+                errors,
+                names.clone(),
+                move |data_directory, errors| {
+                    for name in names {
+                        match manage::delete(data_directory, &name, trash) {
+                            Ok(Some(entry)) => {
+                                println!(
+                                    "{} | moved \"{}\" to the trash (\"{}\")",
+                                    now_utc_string(),
+                                    name,
+                                    entry
+                                );
+                            }
+                            Ok(None) => {
+                                println!("{} | deleted \"{}\"", now_utc_string(), name);
+                            }
+                            Err(error) => {
+                                errors.push(format!("Deleting \"{}\" failed: {}", name, error));
+                            }
+                        }
+                    }
+                },
+            );
+            Ok(())
+        }
+        protocol::ClientMessage::RenameRecording { name, new_name } => {
+            let mut context_guard = context.lock().await;
+            let mut errors = Vec::new();
+            // the new name is reserved as well (a recording may be renamed or moved to it)
+            let mut names = vec![name.clone()];
+            if new_name != name {
+                names.push(new_name.clone());
+            }
+            let busy = mark_busy(&mut context_guard, names, "Renaming", &mut errors);
+            let idle = errors.is_empty();
+            let data_directory = context_guard.shared_recordings_state.data_directory.clone();
+            drop(context_guard);
+            spawn_manage(
+                context,
+                data_directory,
+                errors,
+                busy,
+                move |data_directory, errors| {
+                    if idle {
+                        match manage::rename(data_directory, &name, &new_name) {
+                            Ok(()) => {
+                                println!(
+                                    "{} | renamed \"{}\" to \"{}\"",
+                                    now_utc_string(),
+                                    name,
+                                    new_name
+                                );
+                            }
+                            Err(error) => {
+                                errors.push(format!("Renaming \"{}\" failed: {}", name, error));
+                            }
+                        }
+                    }
+                },
+            );
+            Ok(())
+        }
+        protocol::ClientMessage::MoveRecordings {
+            names,
+            data_directory: new_data_directory,
+        } => {
+            let mut context_guard = context.lock().await;
+            let mut errors = Vec::new();
+            // clients may only move recordings to the directories listed on the command line
+            let names = if context_guard
+                .shared_recordings_state
+                .move_directories
+                .contains(&new_data_directory)
+            {
+                mark_busy(&mut context_guard, names, "Moving", &mut errors)
+            } else {
+                errors.extend(names.iter().map(|name| {
+                    format!(
+                        "Moving \"{}\" failed: \"{}\" is not a move directory (see --move-directory)",
+                        name, new_data_directory
+                    )
+                }));
+                Vec::new()
+            };
+            let data_directory = context_guard.shared_recordings_state.data_directory.clone();
+            drop(context_guard);
+            spawn_manage(
+                context,
+                data_directory,
+                errors,
+                names.clone(),
+                move |data_directory, errors| {
+                    let new_data_directory = std::path::PathBuf::from(new_data_directory);
+                    for name in names {
+                        match manage::move_to(data_directory, &name, &new_data_directory) {
+                            Ok(()) => {
+                                println!(
+                                    "{} | moved \"{}\" to {}",
+                                    now_utc_string(),
+                                    name,
+                                    new_data_directory.to_string_lossy()
+                                );
+                            }
+                            Err(error) => {
+                                errors.push(format!("Moving \"{}\" failed: {}", name, error));
+                            }
+                        }
+                    }
+                },
+            );
+            Ok(())
+        }
+        protocol::ClientMessage::RestoreRecordings { entries } => {
+            let mut context_guard = context.lock().await;
+            let mut errors = Vec::new();
+            let mut busy = Vec::new();
+            let mut restored_entries = Vec::with_capacity(entries.len());
+            for entry in entries {
+                // the restored name is reserved (unknown entries are reported by manage::restore)
+                if let Some(name) = context_guard
+                    .shared_recordings_state
+                    .trash
+                    .iter()
+                    .find(|trashed_recording| trashed_recording.entry == entry)
+                    .map(|trashed_recording| trashed_recording.name.clone())
+                {
+                    let names = mark_busy(&mut context_guard, vec![name], "Restoring", &mut errors);
+                    if names.is_empty() {
+                        continue;
+                    }
+                    busy.extend(names);
+                }
+                restored_entries.push(entry);
+            }
+            let data_directory = context_guard.shared_recordings_state.data_directory.clone();
+            drop(context_guard);
+            spawn_manage(
+                context,
+                data_directory,
+                errors,
+                busy,
+                move |data_directory, errors| {
+                    for entry in restored_entries {
+                        match manage::restore(data_directory, &entry) {
+                            Ok(name) => {
+                                println!(
+                                    "{} | restored \"{}\" from the trash",
+                                    now_utc_string(),
+                                    name
+                                );
+                            }
+                            Err(error) => {
+                                errors.push(format!("Restoring \"{}\" failed: {}", entry, error));
+                            }
+                        }
+                    }
+                },
+            );
+            Ok(())
+        }
+        protocol::ClientMessage::EmptyTrash { entries } => {
+            let context_guard = context.lock().await;
+            let entries = if entries.is_empty() {
+                context_guard
+                    .shared_recordings_state
+                    .trash
+                    .iter()
+                    .map(|trashed_recording| trashed_recording.entry.clone())
+                    .collect()
+            } else {
+                entries
+            };
+            let data_directory = context_guard.shared_recordings_state.data_directory.clone();
+            drop(context_guard);
+            spawn_manage(
+                context,
+                data_directory,
+                Vec::new(),
+                Vec::new(),
+                move |data_directory, errors| {
+                    for entry in entries {
+                        match manage::empty_trash(data_directory, &entry) {
+                            Ok(()) => {
+                                println!(
+                                    "{} | deleted \"{}\" from the trash",
+                                    now_utc_string(),
+                                    entry
+                                );
+                            }
+                            Err(error) => {
+                                errors.push(format!(
+                                    "Deleting \"{}\" from the trash failed: {}",
+                                    entry, error
+                                ));
+                            }
+                        }
+                    }
+                },
+            );
             Ok(())
         }
     }
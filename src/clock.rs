@@ -0,0 +1,201 @@
+// Model of the host clock (UTC, µs since the Unix epoch) as a function of the sensor clock (t).
+// Index entries pair the packets' sensor timestamps and reception times, the model is
+// piecewise-linear (one least-squares fit per SEGMENT_DURATION_US of sensor time) to follow
+// the drift between the clocks. Each segment starts at the previous segment's end value, hence
+// the model is continuous and monotonic. Reception times include the USB latency, hence so does
+// the model.
+use crate::recordings;
+
+pub const SEGMENT_DURATION_US: u64 = 60_000_000;
+
+// utc(t) = t + offset_us + drift * (t - begin_t) in [begin_t, end_t[
+// residuals are the differences between the packets' system times and the model
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Segment {
+    pub begin_t: u64,
+    pub end_t: u64,
+    pub offset_us: f64,
+    pub drift: f64,
+    pub packets: u64,
+    pub residuals_rms_us: f64,
+    pub residuals_maximum_us: f64,
+}
+
+impl Segment {
+    // `points` contains (t, system_time) pairs sorted by t.
+    // If `begin_offset_us` is not None, the segment's offset is fixed and only its drift is fitted.
+    fn fit(points: &[(u64, u64)], begin_offset_us: Option<f64>) -> Self {
+        let begin_t = points[0].0;
+        // offsets are relative to the first offset to preserve the sums' precision
+        let first_offset = match begin_offset_us {
+            Some(begin_offset_us) => begin_offset_us.round() as i128,
+            None => points[0].1 as i128 - begin_t as i128,
+        };
+        let n = points.len() as f64;
+        let xs_and_ys = points.iter().map(|(t, system_time)| {
+            (
+                (t - begin_t) as f64,
+                (*system_time as i128 - *t as i128 - first_offset) as f64,
+            )
+        });
+        let (x_sum, y_sum) = xs_and_ys
+            .clone()
+            .fold((0.0, 0.0), |(x_sum, y_sum), (x, y)| (x_sum + x, y_sum + y));
+        // the line goes through (x_origin, y_origin), the means or the fixed offset
+        let (x_origin, y_origin) = match begin_offset_us {
+            Some(begin_offset_us) => (0.0, begin_offset_us - first_offset as f64),
+            None => (x_sum / n, y_sum / n),
+        };
+        let (xx_sum, xy_sum) = xs_and_ys
+            .clone()
+            .fold((0.0, 0.0), |(xx_sum, xy_sum), (x, y)| {
+                (
+                    xx_sum + (x - x_origin) * (x - x_origin),
+                    xy_sum + (x - x_origin) * (y - y_origin),
+                )
+            });
+        // a single packet (or packets with the same t) cannot measure the drift
+        // the host clock cannot run backwards (utc is monotonic if the drift is at least -1)
+        let drift = if xx_sum > 0.0 {
+            (xy_sum / xx_sum).max(-1.0)
+        } else {
+            0.0
+        };
+        let relative_offset_us = y_origin - drift * x_origin;
+        let (squares_sum, residuals_maximum_us) =
+            xs_and_ys.fold((0.0, 0.0f64), |(squares_sum, maximum), (x, y)| {
+                let residual = y - (relative_offset_us + drift * x);
+                (
+                    squares_sum + residual * residual,
+                    maximum.max(residual.abs()),
+                )
+            });
+        Self {
+            begin_t,
+            end_t: points[points.len() - 1].0 + 1,
+            offset_us: first_offset as f64 + relative_offset_us,
+            drift,
+            packets: points.len() as u64,
+            residuals_rms_us: (squares_sum / n).sqrt(),
+            residuals_maximum_us,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Model {
+    pub segment_duration_us: u64,
+    pub segments: Vec<Segment>,
+}
+
+impl Model {
+    // Reads all the index entries (the reader's range and position are not used).
+    pub fn fit(reader: &mut recordings::Reader) -> Result<Self, anyhow::Error> {
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut points = Vec::new();
+        for index in 0..reader.index_entries {
+            let index_entry = reader.index_entry(index)?;
+            let t = index_entry.state.t;
+            if let Some((first_t, _)) = points.first() {
+                if t >= first_t + SEGMENT_DURATION_US {
+                    segments.push(Self::fit_segment(&segments, &points));
+                    points.clear();
+                }
+            }
+            points.push((t, index_entry.system_time));
+        }
+        if !points.is_empty() {
+            segments.push(Self::fit_segment(&segments, &points));
+        }
+        // segments are contiguous (each segment ends where the next one begins)
+        for index in 1..segments.len() {
+            segments[index - 1].end_t = segments[index].begin_t;
+        }
+        Ok(Self {
+            segment_duration_us: SEGMENT_DURATION_US,
+            segments,
+        })
+    }
+
+    // Each segment starts at the previous segment's value at the segment's first t.
+    fn fit_segment(segments: &[Segment], points: &[(u64, u64)]) -> Segment {
+        Segment::fit(
+            points,
+            segments.last().map(|segment| {
+                segment.offset_us + segment.drift * (points[0].0 - segment.begin_t) as f64
+            }),
+        )
+    }
+
+    // Timestamps outside the recording are extrapolated with the first or last segment.
+    pub fn utc(&self, t: u64) -> u64 {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.begin_t <= t)
+            .saturating_sub(1);
+        match self.segments.get(index) {
+            Some(segment) => {
+                let x = t as f64 - segment.begin_t as f64;
+                (t as f64 + segment.offset_us + segment.drift * x)
+                    .round()
+                    .max(0.0) as u64
+            }
+            None => t,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: u64 = 1_745_000_000_000_000;
+
+    #[test]
+    fn segment_fits_a_line() {
+        let points = (0..100u64)
+            .map(|index| (index * 1000, START + index * 1001 + 7))
+            .collect::<Vec<_>>();
+        let segment = Segment::fit(&points, None);
+        assert!((segment.drift - 0.001).abs() < 1e-9);
+        assert!((segment.offset_us - (START + 7) as f64).abs() < 1e-3);
+        assert!(segment.residuals_maximum_us < 1e-3);
+        assert_eq!(segment.end_t, 99_001);
+        let single = Segment::fit(&[(5, START)], None);
+        assert_eq!(single.drift, 0.0);
+        assert_eq!(single.offset_us, (START - 5) as f64);
+    }
+
+    #[test]
+    fn segments_are_continuous_and_monotonic() {
+        // the host clock jumps by 5 ms between the segments, and the second segment runs
+        // backwards relative to the sensor clock
+        let first_points = (0..60u64)
+            .map(|index| (index * 1_000_000, START + index * 1_000_000))
+            .collect::<Vec<_>>();
+        let second_points = (60..120u64)
+            .map(|index| {
+                (
+                    index * 1_000_000,
+                    START + 5_000 + index * 1_000_000 - index * 10_000_000 / 6,
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut segments = vec![Model::fit_segment(&[], &first_points)];
+        segments.push(Model::fit_segment(&segments, &second_points));
+        assert_eq!(segments[1].drift, -1.0);
+        segments[0].end_t = segments[1].begin_t;
+        let model = Model {
+            segment_duration_us: SEGMENT_DURATION_US,
+            segments,
+        };
+        let boundary_t = 60_000_000;
+        assert_eq!(model.utc(boundary_t - 1) + 1, model.utc(boundary_t));
+        let mut previous_utc = 0;
+        for t in (0..130_000_000).step_by(100_000) {
+            let utc = model.utc(t);
+            assert!(utc >= previous_utc);
+            previous_utc = utc;
+        }
+    }
+}
@@ -0,0 +1,409 @@
+// Apache Arrow IPC files (one per table) for columnar tools such as Polars and DuckDB.
+// The recording metadata is stored as JSON in each schema's metadata ("kairos" key).
+use crate::filters;
+use crate::recordings;
+
+use arrow_array::ArrayRef;
+use std::io::Read;
+
+pub const FILE_EXTENSION: &str = ".arrow";
+
+// number of rows per record batch
+const BATCH_LENGTH: usize = 1 << 16;
+
+fn schema(
+    fields: Vec<arrow_schema::Field>,
+    table: &str,
+    metadata: &str,
+) -> std::sync::Arc<arrow_schema::Schema> {
+    std::sync::Arc::new(arrow_schema::Schema::new_with_metadata(
+        fields,
+        std::collections::HashMap::from([
+            ("kairos".to_owned(), metadata.to_owned()),
+            ("table".to_owned(), table.to_owned()),
+        ]),
+    ))
+}
+
+fn field(name: &str, data_type: arrow_schema::DataType) -> arrow_schema::Field {
+    arrow_schema::Field::new(name, data_type, false)
+}
+
+#[derive(Default)]
+struct Events {
+    t: Vec<u64>,
+    x: Vec<u16>,
+    y: Vec<u16>,
+    on: Vec<bool>,
+    // empty if the utc option is not set
+    utc: Vec<u64>,
+}
+
+impl Events {
+    fn len(&self) -> usize {
+        self.t.len()
+    }
+
+    fn batch(
+        &mut self,
+        schema: &std::sync::Arc<arrow_schema::Schema>,
+    ) -> Result<arrow_array::RecordBatch, arrow_schema::ArrowError> {
+        let mut columns = vec![
+            std::sync::Arc::new(arrow_array::UInt64Array::from(std::mem::take(&mut self.t)))
+                as ArrayRef,
+            std::sync::Arc::new(arrow_array::UInt16Array::from(std::mem::take(&mut self.x))),
+            std::sync::Arc::new(arrow_array::UInt16Array::from(std::mem::take(&mut self.y))),
+            std::sync::Arc::new(arrow_array::BooleanArray::from(std::mem::take(
+                &mut self.on,
+            ))),
+        ];
+        if !self.utc.is_empty() {
+            columns.push(std::sync::Arc::new(arrow_array::UInt64Array::from(
+                std::mem::take(&mut self.utc),
+            )));
+        }
+        arrow_array::RecordBatch::try_new(schema.clone(), columns)
+    }
+}
+
+#[derive(Default)]
+struct Triggers {
+    system_time: Vec<u64>,
+    system_timestamp: Vec<u64>,
+    t: Vec<u64>,
+    id: Vec<u8>,
+    rising: Vec<bool>,
+    // empty if the utc option is not set
+    utc: Vec<u64>,
+}
+
+impl Triggers {
+    fn len(&self) -> usize {
+        self.t.len()
+    }
+
+    fn batch(
+        &mut self,
+        schema: &std::sync::Arc<arrow_schema::Schema>,
+    ) -> Result<arrow_array::RecordBatch, arrow_schema::ArrowError> {
+        let mut columns = vec![
+            std::sync::Arc::new(arrow_array::UInt64Array::from(std::mem::take(
+                &mut self.system_time,
+            ))) as ArrayRef,
+            std::sync::Arc::new(arrow_array::UInt64Array::from(std::mem::take(
+                &mut self.system_timestamp,
+            ))),
+            std::sync::Arc::new(arrow_array::UInt64Array::from(std::mem::take(&mut self.t))),
+            std::sync::Arc::new(arrow_array::UInt8Array::from(std::mem::take(&mut self.id))),
+            std::sync::Arc::new(arrow_array::BooleanArray::from(std::mem::take(
+                &mut self.rising,
+            ))),
+        ];
+        if !self.utc.is_empty() {
+            columns.push(std::sync::Arc::new(arrow_array::UInt64Array::from(
+                std::mem::take(&mut self.utc),
+            )));
+        }
+        arrow_array::RecordBatch::try_new(schema.clone(), columns)
+    }
+}
+
+#[derive(Default)]
+struct Index {
+    system_time: Vec<u64>,
+    system_timestamp: Vec<u64>,
+    first_after_overflow: Vec<bool>,
+    t: Vec<u64>,
+    first_event: Vec<u64>,
+}
+
+impl Index {
+    fn len(&self) -> usize {
+        self.t.len()
+    }
+
+    fn batch(
+        &mut self,
+        schema: &std::sync::Arc<arrow_schema::Schema>,
+    ) -> Result<arrow_array::RecordBatch, arrow_schema::ArrowError> {
+        arrow_array::RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                std::sync::Arc::new(arrow_array::UInt64Array::from(std::mem::take(
+                    &mut self.system_time,
+                ))) as ArrayRef,
+                std::sync::Arc::new(arrow_array::UInt64Array::from(std::mem::take(
+                    &mut self.system_timestamp,
+                ))),
+                std::sync::Arc::new(arrow_array::BooleanArray::from(std::mem::take(
+                    &mut self.first_after_overflow,
+                ))),
+                std::sync::Arc::new(arrow_array::UInt64Array::from(std::mem::take(&mut self.t))),
+                std::sync::Arc::new(arrow_array::UInt64Array::from(std::mem::take(
+                    &mut self.first_event,
+                ))),
+            ],
+        )
+    }
+}
+
+#[derive(Default)]
+struct Samples {
+    system_time: Vec<u64>,
+    system_timestamp: Vec<u64>,
+    illuminance: Vec<f32>,
+    temperature: Vec<f32>,
+}
+
+impl Samples {
+    fn len(&self) -> usize {
+        self.system_time.len()
+    }
+
+    fn batch(
+        &mut self,
+        schema: &std::sync::Arc<arrow_schema::Schema>,
+    ) -> Result<arrow_array::RecordBatch, arrow_schema::ArrowError> {
+        arrow_array::RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                std::sync::Arc::new(arrow_array::UInt64Array::from(std::mem::take(
+                    &mut self.system_time,
+                ))) as ArrayRef,
+                std::sync::Arc::new(arrow_array::UInt64Array::from(std::mem::take(
+                    &mut self.system_timestamp,
+                ))),
+                std::sync::Arc::new(arrow_array::Float32Array::from(std::mem::take(
+                    &mut self.illuminance,
+                ))),
+                std::sync::Arc::new(arrow_array::Float32Array::from(std::mem::take(
+                    &mut self.temperature,
+                ))),
+            ],
+        )
+    }
+}
+
+// Writes the events, triggers, index, and samples tables of `name` to the zip archive.
+// The triggers and index tables are spilled to temporary files (named after `part_path`)
+// while the events are written, every table is written in batches of BATCH_LENGTH rows.
+// Returns false if the conversion was cancelled.
+pub fn write<W: std::io::Write + std::io::Seek>(
+    zip: &mut zip::ZipWriter<W>,
+    reader: &mut recordings::Reader,
+    pass: &recordings::Pass,
+    part_path: &std::path::Path,
+) -> Result<bool, anyhow::Error> {
+    let name = pass.name;
+    let options = pass.options;
+    let convert_options = pass.convert_options;
+    let selection = &pass.selection;
+    let metadata = serde_json::to_string(pass.metadata)?;
+    reader.rewind()?;
+    let mut events_fields = vec![
+        field("t", arrow_schema::DataType::UInt64),
+        field("x", arrow_schema::DataType::UInt16),
+        field("y", arrow_schema::DataType::UInt16),
+        field("on", arrow_schema::DataType::Boolean),
+    ];
+    let mut triggers_fields = vec![
+        field("system_time", arrow_schema::DataType::UInt64),
+        field("system_timestamp", arrow_schema::DataType::UInt64),
+        field("t", arrow_schema::DataType::UInt64),
+        field("id", arrow_schema::DataType::UInt8),
+        field("rising", arrow_schema::DataType::Boolean),
+    ];
+    if convert_options.utc {
+        events_fields.push(field("utc", arrow_schema::DataType::UInt64));
+        triggers_fields.push(field("utc", arrow_schema::DataType::UInt64));
+    }
+    let events_schema = schema(events_fields, "events", &metadata);
+    let triggers_schema = schema(triggers_fields, "triggers", &metadata);
+    // first_event is the row in the events table of the packet's first event
+    let index_schema = schema(
+        vec![
+            field("system_time", arrow_schema::DataType::UInt64),
+            field("system_timestamp", arrow_schema::DataType::UInt64),
+            field("first_after_overflow", arrow_schema::DataType::Boolean),
+            field("t", arrow_schema::DataType::UInt64),
+            field("first_event", arrow_schema::DataType::UInt64),
+        ],
+        "index",
+        &metadata,
+    );
+
+    // events (triggers and index are spilled)
+    zip.start_file(format!("{name}/{name}_events{FILE_EXTENSION}"), options)?;
+    let mut triggers_spill = recordings::Spill::create(part_path, "triggers")?;
+    let mut index_spill = recordings::Spill::create(part_path, "index")?;
+    {
+        let mut writer = arrow_ipc::writer::FileWriter::try_new(&mut *zip, &events_schema)?;
+        let mut triggers_writer =
+            arrow_ipc::writer::FileWriter::try_new(&mut triggers_spill, &triggers_schema)?;
+        let mut index_writer =
+            arrow_ipc::writer::FileWriter::try_new(&mut index_spill, &index_schema)?;
+        let mut events = Events::default();
+        let mut triggers = Triggers::default();
+        let mut index = Index::default();
+        let mut chain = filters::Chain::new(&convert_options.filters, reader.width, reader.height)?;
+        let mut first_event = 0u64;
+        while let Some(index_entry) = reader.next_packet()? {
+            pass.progress.add(index_entry.raw_length);
+            if convert_options.index {
+                index.system_time.push(index_entry.system_time);
+                index.system_timestamp.push(index_entry.system_timestamp);
+                index
+                    .first_after_overflow
+                    .push(index_entry.first_after_overflow);
+                index.t.push(index_entry.state.t);
+                index.first_event.push(first_event);
+            }
+            reader.convert(
+                |dvs_event| {
+                    let t = dvs_event.t; // unpack field
+                    let x = dvs_event.x; // unpack field
+                    let y = dvs_event.y; // unpack field
+                    let on = dvs_event.polarity as u8 == 1; // unpack field
+                    if !selection.contains_t(t) {
+                        return;
+                    }
+                    if let Some((x, y)) = chain.filter(t, x, y, on) {
+                        events.t.push(t);
+                        events.x.push(x);
+                        events.y.push(y);
+                        events.on.push(on);
+                        if convert_options.utc {
+                            events.utc.push(pass.clock.utc(t));
+                        }
+                        first_event += 1;
+                    }
+                },
+                |trigger_event| {
+                    let t = trigger_event.t; // unpack field
+                    let id = trigger_event.id; // unpack field
+                    let rising = trigger_event.polarity as u8 == 1; // unpack field
+                    if !convert_options.triggers || !selection.contains_t(t) {
+                        return;
+                    }
+                    triggers.system_time.push(index_entry.system_time);
+                    triggers.system_timestamp.push(index_entry.system_timestamp);
+                    triggers.t.push(t);
+                    triggers.id.push(id);
+                    triggers.rising.push(rising);
+                    if convert_options.utc {
+                        triggers.utc.push(pass.clock.utc(t));
+                    }
+                },
+            );
+            if events.len() >= BATCH_LENGTH {
+                writer.write(&events.batch(&events_schema)?)?;
+            }
+            if triggers.len() >= BATCH_LENGTH {
+                triggers_writer.write(&triggers.batch(&triggers_schema)?)?;
+            }
+            if index.len() >= BATCH_LENGTH {
+                index_writer.write(&index.batch(&index_schema)?)?;
+            }
+            if pass.cancelled() {
+                return Ok(false);
+            }
+        }
+        if !events.t.is_empty() {
+            writer.write(&events.batch(&events_schema)?)?;
+        }
+        if !triggers.t.is_empty() {
+            triggers_writer.write(&triggers.batch(&triggers_schema)?)?;
+        }
+        if !index.t.is_empty() {
+            index_writer.write(&index.batch(&index_schema)?)?;
+        }
+        writer.finish()?;
+        triggers_writer.finish()?;
+        index_writer.finish()?;
+    }
+
+    // triggers
+    if convert_options.triggers {
+        zip.start_file(format!("{name}/{name}_triggers{FILE_EXTENSION}"), options)?;
+        triggers_spill.copy_to(zip)?;
+    }
+    drop(triggers_spill);
+
+    // index
+    if convert_options.index {
+        zip.start_file(format!("{name}/{name}_index{FILE_EXTENSION}"), options)?;
+        index_spill.copy_to(zip)?;
+    }
+    drop(index_spill);
+    if pass.cancelled() {
+        return Ok(false);
+    }
+
+    // samples
+    if !convert_options.samples {
+        return Ok(true);
+    }
+    let mut samples_file = std::io::BufReader::new(std::fs::File::open(
+        pass.data_directory
+            .join(recordings::RECORDINGS_DIRECTORY_NAME)
+            .join(format!("{}{}", name, recordings::SAMPLES_FILE_EXTENSION)),
+    )?);
+    let samples_header = recordings::read_header(
+        &mut Vec::new(),
+        &mut samples_file,
+        recordings::SAMPLES_FILE_SIGNATURE.as_bytes(),
+    )?;
+    match samples_header.file_type {
+        // Prophesee EVK4
+        0 => {
+            let samples_schema = schema(
+                vec![
+                    field("system_time", arrow_schema::DataType::UInt64),
+                    field("system_timestamp", arrow_schema::DataType::UInt64),
+                    field("illuminance_lux", arrow_schema::DataType::Float32),
+                    field("temperature_celsius", arrow_schema::DataType::Float32),
+                ],
+                "samples",
+                &metadata,
+            );
+            zip.start_file(format!("{name}/{name}_samples{FILE_EXTENSION}"), options)?;
+            let mut writer = arrow_ipc::writer::FileWriter::try_new(&mut *zip, &samples_schema)?;
+            let mut samples = Samples::default();
+            let mut sample_data = [0u8; recordings::EVK4_SAMPLE_LENGTH];
+            loop {
+                match samples_file.read_exact(&mut sample_data) {
+                    Ok(()) => {
+                        let sample = recordings::Evk4Sample::from_bytes(&sample_data);
+                        if !selection.contains_system_time(sample.system_time) {
+                            continue;
+                        }
+                        samples.system_time.push(sample.system_time);
+                        samples.system_timestamp.push(sample.system_timestamp);
+                        samples.illuminance.push(sample.illuminance);
+                        samples.temperature.push(sample.temperature);
+                        if samples.len() >= BATCH_LENGTH {
+                            writer.write(&samples.batch(&samples_schema)?)?;
+                        }
+                    }
+                    Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                        break;
+                    }
+                    Err(error) => {
+                        return Err(error.into());
+                    }
+                }
+            }
+            if !samples.system_time.is_empty() {
+                writer.write(&samples.batch(&samples_schema)?)?;
+            }
+            writer.finish()?;
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "unsupported samples file type {}",
+                samples_header.file_type
+            ));
+        }
+    }
+    Ok(true)
+}
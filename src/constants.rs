@@ -16,3 +16,4 @@ pub const STACK_MINIMUM_SAMPLES: usize = 10;
 pub const SAMPLE_STACK_LENGTH: usize = 256;
 pub const RECORD_STATE_STACK_LENGTH: usize = 256;
 pub const AUTOTRIGGER_MAXIMUM_WINDOW_SIZE: usize = 600;
+pub const CONVERT_PROGRESS_PERIOD: std::time::Duration = std::time::Duration::from_millis(500);
@@ -0,0 +1,57 @@
+// CRC-32C (Castagnoli), the checksum used by iSCSI, ext4, and many storage formats
+const POLYNOMIAL: u32 = 0x82F63B78; // reversed 0x1EDC6F41
+
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+};
+
+// update(update(0, a), b) == checksum(a + b)
+pub fn update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc = TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+pub fn checksum(data: &[u8]) -> u32 {
+    update(0, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(checksum(b"123456789"), 0xE3069283);
+        assert_eq!(checksum(b""), 0);
+    }
+
+    #[test]
+    fn incremental_update() {
+        let data = b"The quick brown fox jumps over the lazy dog";
+        for split in 0..data.len() {
+            assert_eq!(
+                update(update(0, &data[..split]), &data[split..]),
+                checksum(data)
+            );
+        }
+    }
+}
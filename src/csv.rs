@@ -0,0 +1,98 @@
+// Allocation-free CSV formatting for conversions (format! allocates a String per event)
+// Integers are written two digits at a time from a lookup table.
+
+const DIGITS_PAIRS: &[u8; 200] = b"0001020304050607080910111213141516171819202122232425262728293031323334353637383940414243444546474849\
+5051525354555657585960616263646566676869707172737475767778798081828384858687888990919293949596979899";
+
+pub fn push_u64(buffer: &mut Vec<u8>, mut value: u64) {
+    // u64::MAX has 20 digits
+    let mut digits = [0u8; 20];
+    let mut index = digits.len();
+    while value >= 100 {
+        let pair = (value % 100) as usize * 2;
+        value /= 100;
+        index -= 2;
+        digits[index..index + 2].copy_from_slice(&DIGITS_PAIRS[pair..pair + 2]);
+    }
+    if value >= 10 {
+        let pair = value as usize * 2;
+        index -= 2;
+        digits[index..index + 2].copy_from_slice(&DIGITS_PAIRS[pair..pair + 2]);
+    } else {
+        index -= 1;
+        digits[index] = b'0' + value as u8;
+    }
+    buffer.extend_from_slice(&digits[index..]);
+}
+
+// Appends the values separated by commas, followed by a line feed.
+pub fn push_row(buffer: &mut Vec<u8>, values: &[u64]) {
+    for (index, value) in values.iter().enumerate() {
+        if index > 0 {
+            buffer.push(b',');
+        }
+        push_u64(buffer, *value);
+    }
+    buffer.push(b'\n');
+}
+
+// Appends the shortest representation that round-trips (the same digits as to_string).
+// Floats are formatted on the stack, writing to a Vec cannot fail.
+pub fn push_f32(buffer: &mut Vec<u8>, value: f32) {
+    let _ = std::io::Write::write_fmt(buffer, format_args!("{value}"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_u64_matches_to_string() {
+        let mut values = vec![0, 1, 9, 10, 99, 100, 101, 999, 1000, u64::MAX - 1, u64::MAX];
+        for power in 1..20 {
+            values.push(10u64.pow(power) - 1);
+            values.push(10u64.pow(power));
+        }
+        // xorshift64 values spread over all the digit counts
+        let mut state = 0x4B4149524F53u64;
+        for _ in 0..10_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            values.push(state >> (state % 64));
+        }
+        let mut buffer = Vec::new();
+        for value in values {
+            buffer.clear();
+            push_u64(&mut buffer, value);
+            assert_eq!(buffer, value.to_string().as_bytes());
+        }
+    }
+
+    #[test]
+    fn push_f32_matches_to_string() {
+        let mut buffer = Vec::new();
+        for value in [
+            0.0f32,
+            -0.0,
+            1.0,
+            25.0,
+            0.1,
+            1234.5677,
+            f32::MAX,
+            f32::MIN_POSITIVE,
+        ] {
+            buffer.clear();
+            push_f32(&mut buffer, value);
+            assert_eq!(buffer, value.to_string().as_bytes());
+        }
+    }
+
+    #[test]
+    fn push_row_separates_values() {
+        let mut buffer = Vec::new();
+        push_row(&mut buffer, &[1745000000000000, 0, 42, 1]);
+        push_row(&mut buffer, &[]);
+        assert_eq!(buffer, b"1745000000000000,0,42,1\n\n");
+    }
+}
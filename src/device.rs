@@ -2,6 +2,8 @@ use crate::constants;
 use crate::device;
 use crate::protocol;
 use crate::recordings;
+use crate::replay;
+use crate::synthetic;
 
 use neuromorphic_drivers::UsbDevice;
 use std::io::Write;
@@ -40,6 +42,8 @@ pub struct RecordConfiguration {
     pub lookback: protocol::Lookback,
     pub autostop: protocol::Autostop,
     pub autotrigger: protocol::Autotrigger,
+    pub durability: protocol::Durability,
+    pub segmenting: protocol::Segmenting,
 }
 
 #[derive(Default, Clone)]
@@ -55,6 +59,9 @@ struct FileState {
     name: String,
     duration_us: u64,
     size_bytes: u64,
+    durable_raw_file_offset: u64,
+    durability: protocol::Durability,
+    segment: u32,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -110,6 +117,57 @@ struct EventThreadState {
 // 4. The "sampler thread" (the loop that calls `illuminance`) periodically checks for changes to `event_thread_state`
 // and updates its recording state accordingly.
 
+// Replays and synthetic cameras are boxed, the USB variant is matched by device type in place
+// (there is one Inner per device, behind an Arc, hence its size does not matter)
+#[allow(clippy::large_enum_variant)]
+pub enum Inner {
+    Usb(neuromorphic_drivers::Device),
+    Replay(Box<replay::Replay>),
+    Synthetic(Box<synthetic::Synthetic>),
+}
+
+impl Inner {
+    pub fn current_configuration(&self) -> neuromorphic_drivers::Configuration {
+        match self {
+            Inner::Usb(device) => device.current_configuration(),
+            Inner::Replay(replay) => replay.configuration.clone(),
+            Inner::Synthetic(synthetic) => synthetic.configuration.clone(),
+        }
+    }
+
+    fn create_adapter(&self) -> neuromorphic_drivers::Adapter {
+        match self {
+            Inner::Usb(device) => device.create_adapter(),
+            Inner::Replay(replay) => replay.create_adapter(),
+            Inner::Synthetic(synthetic) => synthetic.create_adapter(),
+        }
+    }
+
+    fn buffer_length(&self) -> usize {
+        match self.current_configuration() {
+            neuromorphic_drivers::Configuration::InivationDavis346(_) => {
+                neuromorphic_drivers::inivation_davis346::DEFAULT_USB_CONFIGURATION.buffer_length
+            }
+            neuromorphic_drivers::Configuration::InivationDvxplorer(_) => {
+                neuromorphic_drivers::inivation_davis346::DEFAULT_USB_CONFIGURATION.buffer_length
+            }
+            neuromorphic_drivers::Configuration::PropheseeEvk3Hd(_) => {
+                neuromorphic_drivers::prophesee_evk3_hd::DEFAULT_USB_CONFIGURATION.buffer_length
+            }
+            neuromorphic_drivers::Configuration::PropheseeEvk4(_) => {
+                neuromorphic_drivers::prophesee_evk4::DEFAULT_USB_CONFIGURATION.buffer_length
+            }
+        }
+    }
+}
+
+struct BufferView<'a> {
+    system_time: std::time::SystemTime,
+    instant: std::time::Instant,
+    first_after_overflow: bool,
+    slice: &'a [u8],
+}
+
 #[derive(serde::Serialize, Clone)]
 pub struct Properties {
     pub name: String,
@@ -121,9 +179,10 @@ pub struct Properties {
 pub struct Device {
     id: DeviceId,
     properties: Properties,
-    inner: std::sync::Arc<neuromorphic_drivers::Device>,
-    flag:
+    inner: std::sync::Arc<Inner>,
+    flag: Option<
         neuromorphic_drivers::Flag<neuromorphic_drivers::Error, neuromorphic_drivers::UsbOverflow>,
+    >,
     record_configuration: std::sync::Arc<std::sync::Mutex<RecordConfiguration>>,
     event_thread_state: std::sync::Arc<std::sync::Mutex<EventThreadState>>,
     running: std::sync::Arc<std::sync::atomic::AtomicBool>,
@@ -132,7 +191,7 @@ pub struct Device {
 
 pub struct DeviceSampler {
     id: DeviceId,
-    inner: std::sync::Arc<neuromorphic_drivers::Device>,
+    inner: std::sync::Arc<Inner>,
     event_thread_state: std::sync::Arc<std::sync::Mutex<EventThreadState>>,
     running: std::sync::Arc<std::sync::atomic::AtomicBool>,
 }
@@ -140,7 +199,7 @@ pub struct DeviceSampler {
 pub struct DeviceProxy {
     pub id: DeviceId,
     pub properties: Properties,
-    pub inner: std::sync::Arc<neuromorphic_drivers::Device>,
+    pub inner: std::sync::Arc<Inner>,
     pub record_configuration: std::sync::Arc<std::sync::Mutex<RecordConfiguration>>,
     pub configuration_changed: std::sync::Arc<std::sync::atomic::AtomicBool>,
 }
@@ -152,6 +211,8 @@ pub struct Index<'a> {
     raw_file_offset: u64,
     raw_length: u64,
     state: &'a neuromorphic_drivers::adapters::State,
+    // CRC32C of the raw packet, None if the index format does not have checksums
+    checksum: Option<u32>,
 }
 
 impl<'a> Index<'a> {
@@ -163,7 +224,7 @@ impl<'a> Index<'a> {
             neuromorphic_drivers::adapters::State::Davis346(state) => todo!(),
             neuromorphic_drivers::adapters::State::Dvxplorer(state) => todo!(),
             neuromorphic_drivers::adapters::State::Evt3(state) => {
-                let mut data = [0u8; 54];
+                let mut data = [0u8; 58];
                 data[0..8].copy_from_slice(&self.system_time.to_le_bytes()); // 8
                 data[8..16].copy_from_slice(&self.system_timestamp.to_le_bytes()); // 8
                 data[16] = self.first_after_overflow as u8; // 1
@@ -176,7 +237,13 @@ impl<'a> Index<'a> {
                 data[49..51].copy_from_slice(&state.x.to_le_bytes()); // 2
                 data[51..53].copy_from_slice(&state.y.to_le_bytes()); // 2
                 data[53] = state.polarity as u8; // 1
-                write_all_count(index_file, &data)
+                match self.checksum {
+                    Some(checksum) => {
+                        data[54..58].copy_from_slice(&checksum.to_le_bytes()); // 4
+                        write_all_count(index_file, &data)
+                    }
+                    None => write_all_count(index_file, &data[0..54]),
+                }
             }
         }
     }
@@ -548,10 +615,17 @@ impl SamplerLookback {
     }
 }
 
+struct RecordingRecordState {
+    name: String,
+    duration_us: u64,
+    size_bytes: u64,
+    durable_raw_file_offset: u64,
+}
+
 fn serialize_record_state_to(
     device_id: DeviceId,
     lookback_state: &Option<LookbackState>,
-    name_and_duration_us_and_size_bytes: &Option<(String, u64, u64)>,
+    recording_state: &Option<RecordingRecordState>,
     buffer: &mut Vec<u8>,
 ) {
     buffer.extend_from_slice(&device_id.0.to_le_bytes()); // 4
@@ -568,24 +642,46 @@ fn serialize_record_state_to(
             buffer.extend_from_slice(&(0u64).to_le_bytes()); // 8
         }
     }
-    match name_and_duration_us_and_size_bytes {
-        Some(name_and_duration_us_and_size_bytes) => {
+    match recording_state {
+        Some(recording_state) => {
             buffer.push(1); // 1
-            buffer.extend_from_slice(&name_and_duration_us_and_size_bytes.1.to_le_bytes()); // 8
-            buffer.extend_from_slice(&name_and_duration_us_and_size_bytes.2.to_le_bytes()); // 8
-            buffer.extend_from_slice(name_and_duration_us_and_size_bytes.0.as_bytes());
+            buffer.extend_from_slice(&recording_state.duration_us.to_le_bytes()); // 8
+            buffer.extend_from_slice(&recording_state.size_bytes.to_le_bytes()); // 8
+            buffer.extend_from_slice(&recording_state.durable_raw_file_offset.to_le_bytes()); // 8
+            buffer.extend_from_slice(recording_state.name.as_bytes());
         }
         None => {
             buffer.push(0); // 1
             buffer.extend_from_slice(&(0u64).to_le_bytes()); // 8
             buffer.extend_from_slice(&(0u64).to_le_bytes()); // 8
+            buffer.extend_from_slice(&(0u64).to_le_bytes()); // 8
         }
     }
 }
 
+// Server-wide recording options (command-line arguments)
+#[derive(Debug, Clone, Copy)]
+pub struct RecordingOptions {
+    // system time (µs since the Unix epoch) of the server's time reference
+    pub time_reference_system_time: u64,
+    pub packet_checksums: bool,
+    pub raw_compression: bool,
+}
+
+// Parameters of a new recording (segments inherit them from the previous segment)
+struct RecordingParameters {
+    // the first segment's name
+    session: String,
+    segment: u32,
+    durability: protocol::Durability,
+    options: RecordingOptions,
+}
+
 struct Recording {
     directory: std::path::PathBuf,
     name: String,
+    session: String,
+    segment: u32,
     raw_file: Option<std::io::BufWriter<std::fs::File>>,
     raw_file_error: bool,
     raw_file_offset: u64,
@@ -594,7 +690,15 @@ struct Recording {
     metadata_file: Option<std::io::BufWriter<std::fs::File>>,
     metadata_file_error: bool,
     start_t: u64,
+    options: RecordingOptions,
+    // compressed packet (if options.raw_compression is true)
+    frame: Vec<u8>,
     size_bytes: u64,
+    durability: protocol::Durability,
+    syncer: Syncer,
+    synced_size_bytes: u64,
+    synced_instant: std::time::Instant,
+    flush_error: bool,
 }
 
 struct SamplerRecording {
@@ -603,6 +707,124 @@ struct SamplerRecording {
     samples_file: Option<std::io::BufWriter<std::fs::File>>,
     samples_file_error: bool,
     size_bytes: u64,
+    synced_size_bytes: u64,
+    synced_instant: std::time::Instant,
+    sync_error: bool,
+}
+
+fn sync_is_due(
+    durability: &protocol::Durability,
+    synced_instant: &std::time::Instant,
+    unsynced_bytes: u64,
+) -> bool {
+    (durability.period_ms > 0
+        && synced_instant.elapsed() >= std::time::Duration::from_millis(durability.period_ms))
+        || (durability.size_bytes > 0 && unsynced_bytes >= durability.size_bytes)
+}
+
+fn flush_file(file: &mut Option<std::io::BufWriter<std::fs::File>>) -> std::io::Result<()> {
+    if let Some(file) = file.as_mut() {
+        file.flush()?;
+    }
+    Ok(())
+}
+
+// flush writes the BufWriter's buffer to the OS, sync_data waits until the OS has written the data to disk
+fn sync_file(file: &mut Option<std::io::BufWriter<std::fs::File>>) -> std::io::Result<()> {
+    if let Some(file) = file.as_mut() {
+        file.flush()?;
+        file.get_ref().sync_data()?;
+    }
+    Ok(())
+}
+
+// A syncer waits until the OS has written a recording's files to disk, on its own thread
+// (sync_data may block for a long time and the camera thread must keep reading buffers).
+// The camera thread flushes the files' buffers to the OS and sends the raw file offset
+// that must become durable, the sync thread syncs duplicated file handles (raw data first
+// so that the index never points to raw data that is not on disk) and publishes the offset.
+struct Syncer {
+    sender: Option<std::sync::mpsc::Sender<u64>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+    durable_raw_file_offset: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    // first sync error, set by the sync thread and reported by the camera thread
+    error: std::sync::Arc<std::sync::Mutex<Option<String>>>,
+}
+
+impl Syncer {
+    fn new(files: Vec<(std::path::PathBuf, std::fs::File)>, durable_raw_file_offset: u64) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel::<u64>();
+        let durable_raw_file_offset =
+            std::sync::Arc::new(std::sync::atomic::AtomicU64::new(durable_raw_file_offset));
+        let error = std::sync::Arc::new(std::sync::Mutex::new(None));
+        let thread = {
+            let durable_raw_file_offset = durable_raw_file_offset.clone();
+            let error = error.clone();
+            std::thread::spawn(move || {
+                let mut sync_error = false;
+                while let Ok(mut raw_file_offset) = receiver.recv() {
+                    // requests received during the previous sync are merged
+                    while let Ok(next_raw_file_offset) = receiver.try_recv() {
+                        raw_file_offset = next_raw_file_offset;
+                    }
+                    match files.iter().try_for_each(|(path, file)| {
+                        file.sync_data().map_err(|error| (path, error))
+                    }) {
+                        Ok(()) => {
+                            durable_raw_file_offset
+                                .store(raw_file_offset, std::sync::atomic::Ordering::Release);
+                        }
+                        Err((path, sync_data_error)) => {
+                            if !sync_error {
+                                sync_error = true;
+                                let _ = error
+                                    .lock()
+                                    .expect("sync error mutex is poisoned")
+                                    .replace(format!(
+                                        "Syncing \"{}\" failed ({})",
+                                        path.to_string_lossy(),
+                                        sync_data_error
+                                    ));
+                            }
+                        }
+                    }
+                }
+            })
+        };
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+            durable_raw_file_offset,
+            error,
+        }
+    }
+
+    // The data before raw_file_offset must have been flushed to the OS
+    fn sync(&self, raw_file_offset: u64) {
+        if let Some(sender) = self.sender.as_ref() {
+            let _ = sender.send(raw_file_offset);
+        }
+    }
+
+    fn durable_raw_file_offset(&self) -> u64 {
+        self.durable_raw_file_offset
+            .load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    fn take_error(&self) -> Option<String> {
+        self.error
+            .lock()
+            .expect("sync error mutex is poisoned")
+            .take()
+    }
+
+    // Waits for the pending sync (if any) and closes the duplicated file handles
+    fn stop(&mut self) {
+        let _ = self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
 }
 
 fn write_all_count(
@@ -687,6 +909,14 @@ struct Autotrigger {
 enum Trigger {
     Manual(String),
     Auto(Autotrigger),
+    Segment(String),
+}
+
+// All the segments of a recording share the same session id (the first segment's name)
+#[derive(serde::Serialize)]
+struct Session<'a> {
+    id: &'a str,
+    segment: u32,
 }
 
 macro_rules! register {
@@ -726,6 +956,25 @@ macro_rules! register {
                 }
             }
 
+            pub fn configuration_from_toml(
+                device_name: &str,
+                configuration: toml::Value,
+            ) -> Result<neuromorphic_drivers::Configuration, anyhow::Error> {
+                $(
+                    if device_name == neuromorphic_drivers::devices::Type::[<$module:camel>].name() {
+                        return Ok(neuromorphic_drivers::Configuration::[<$module:camel>](
+                            configuration.try_into()?,
+                        ));
+                    }
+                )+
+                if device_name == synthetic::DEVICE_NAME {
+                    return Ok(neuromorphic_drivers::Configuration::PropheseeEvk4(
+                        configuration.try_into()?,
+                    ));
+                }
+                Err(anyhow::anyhow!("unsupported device type \"{}\"", device_name))
+            }
+
             fn configuration_update_to_toml(
                 datetime: &chrono::DateTime<chrono::Local>,
                 configuration: &neuromorphic_drivers::Configuration
@@ -767,39 +1016,51 @@ impl Recording {
         state: &neuromorphic_drivers::adapters::State,
         properties: &Properties,
         configuration: &neuromorphic_drivers::Configuration,
+        parameters: RecordingParameters,
     ) -> Result<Recording, std::io::Error> {
+        let RecordingParameters {
+            session,
+            segment,
+            durability,
+            options,
+        } = parameters;
         let mut size_bytes = 0;
         let mut raw_file = std::io::BufWriter::new(std::fs::File::create(raw_file_path(
             &directory, &name, true,
         ))?);
-        // 0 is the file version number (not a string terminator)
-        raw_file.write_all(format!("{}\0", recordings::RAW_FILE_SIGNATURE).as_bytes())?;
-        match adapter {
+        let (file_type, description) = match adapter {
             neuromorphic_drivers::adapters::Adapter::Davis346(adapter) => todo!(),
             neuromorphic_drivers::adapters::Adapter::Dvxplorer(adapter) => todo!(),
-            neuromorphic_drivers::adapters::Adapter::Evt3(adapter) => {
-                // format id (0 is EVT3)
-                raw_file.write_all(b"\0")?;
-                raw_file.write_all(&(adapter.width().to_le_bytes()))?; // 2
-                raw_file.write_all(&(adapter.height().to_le_bytes()))?; // 2
-            }
-        }
-        let raw_file_offset = recordings::RAW_FILE_SIGNATURE.len() as u64 + 6;
+            neuromorphic_drivers::adapters::Adapter::Evt3(adapter) => (
+                // format id (0 is EVT3, 1 is LZ4-compressed EVT3)
+                if options.raw_compression { 1u8 } else { 0u8 },
+                recordings::Description {
+                    device_name: properties.name.clone(),
+                    device_serial: properties.serial.clone(),
+                    width: adapter.width(),
+                    height: adapter.height(),
+                    start_system_time: datetime.timestamp_micros().max(0) as u64,
+                    time_reference_system_time: options.time_reference_system_time,
+                },
+            ),
+        };
+        let raw_file_offset = recordings::write_header(
+            &mut raw_file,
+            recordings::RAW_FILE_SIGNATURE,
+            file_type,
+            &description,
+        )?;
         size_bytes += raw_file_offset;
         let mut index_file = std::io::BufWriter::new(std::fs::File::create(index_file_path(
             &directory, &name, true,
         ))?);
-        // 0 is the file version number (not a string terminator)
-        index_file.write_all(format!("{}\0", recordings::INDEX_FILE_SIGNATURE).as_bytes())?;
-        match adapter {
-            neuromorphic_drivers::adapters::Adapter::Davis346(_) => todo!(),
-            neuromorphic_drivers::adapters::Adapter::Dvxplorer(_) => todo!(),
-            neuromorphic_drivers::adapters::Adapter::Evt3(_) => {
-                // format id (0 is EVT3)
-                index_file.write_all(b"\0")?;
-            }
-        }
-        size_bytes += 14;
+        size_bytes += recordings::write_header(
+            &mut index_file,
+            recordings::INDEX_FILE_SIGNATURE,
+            // index format id (0 is EVT3, 1 is EVT3 with checksums)
+            if options.packet_checksums { 1u8 } else { 0u8 },
+            &description,
+        )?;
         let mut metadata_file = std::io::BufWriter::new(std::fs::File::create(
             metadata_file_path(&directory, &name, true),
         )?);
@@ -808,15 +1069,29 @@ impl Recording {
             metadata_file.write_all(datetime_string.as_bytes())?;
             size_bytes += datetime_string.len() as u64;
         }
+        {
+            let session_string = format!(
+                "[session]\n{}\n",
+                toml::to_string(&Session {
+                    id: &session,
+                    segment,
+                })
+                .expect("TOML serialization failed")
+            );
+            metadata_file.write_all(session_string.as_bytes())?;
+            size_bytes += session_string.len() as u64;
+        }
         {
             let trigger_string = format!(
                 "[trigger]\nmode = \"{}\"\n{}\n",
                 match trigger {
                     Trigger::Manual(_) => "manual",
                     Trigger::Auto(_) => "auto",
+                    Trigger::Segment(_) => "segment",
                 },
                 match trigger {
-                    Trigger::Manual(datetime) => format!("timestamp = \"{datetime}\"\n"),
+                    Trigger::Manual(datetime) | Trigger::Segment(datetime) =>
+                        format!("timestamp = \"{datetime}\"\n"),
                     Trigger::Auto(autotrigger) =>
                         toml::to_string(&autotrigger).expect("TOML serialization failed"),
                 },
@@ -837,9 +1112,28 @@ impl Recording {
             metadata_file.write_all(configuration_string.as_bytes())?;
             size_bytes += configuration_string.len() as u64;
         }
+        let syncer = Syncer::new(
+            vec![
+                (
+                    raw_file_path(&directory, &name, true),
+                    raw_file.get_ref().try_clone()?,
+                ),
+                (
+                    index_file_path(&directory, &name, true),
+                    index_file.get_ref().try_clone()?,
+                ),
+                (
+                    metadata_file_path(&directory, &name, true),
+                    metadata_file.get_ref().try_clone()?,
+                ),
+            ],
+            raw_file_offset,
+        );
         Ok(Recording {
             directory,
             name,
+            session,
+            segment,
             raw_file: Some(raw_file),
             raw_file_error: false,
             raw_file_offset,
@@ -848,10 +1142,124 @@ impl Recording {
             metadata_file: Some(metadata_file),
             metadata_file_error: false,
             start_t: state.current_t(),
+            options,
+            frame: Vec::new(),
             size_bytes,
+            durability,
+            syncer,
+            synced_size_bytes: 0,
+            synced_instant: std::time::Instant::now(),
+            flush_error: false,
         })
     }
 
+    // Flushes the files and requests a sync (see Syncer) if required by the durability policy.
+    // Returns an error message the first time flushing or syncing fails.
+    fn sync_if_due(&mut self) -> Option<String> {
+        if let Some(error) = self.syncer.take_error() {
+            return Some(error);
+        }
+        if !sync_is_due(
+            &self.durability,
+            &self.synced_instant,
+            self.size_bytes - self.synced_size_bytes,
+        ) {
+            return None;
+        }
+        self.synced_instant = std::time::Instant::now();
+        self.synced_size_bytes = self.size_bytes;
+        let result = flush_file(&mut self.raw_file)
+            .map_err(|error| (raw_file_path(&self.directory, &self.name, true), error))
+            .and_then(|_| {
+                flush_file(&mut self.index_file)
+                    .map_err(|error| (index_file_path(&self.directory, &self.name, true), error))
+            })
+            .and_then(|_| {
+                flush_file(&mut self.metadata_file)
+                    .map_err(|error| (metadata_file_path(&self.directory, &self.name, true), error))
+            });
+        match result {
+            Ok(()) => {
+                self.syncer.sync(self.raw_file_offset);
+                None
+            }
+            Err((path, error)) => {
+                if self.flush_error {
+                    None
+                } else {
+                    self.flush_error = true;
+                    Some(format!(
+                        "Flushing \"{}\" failed ({})",
+                        path.to_string_lossy(),
+                        error
+                    ))
+                }
+            }
+        }
+    }
+
+    // Writes a camera buffer to the raw file and its index entry to the index file.
+    // `state` must be the adapter state before the buffer.
+    // Error messages are pushed to `errors` the first time writing to each file fails.
+    fn write_buffer(
+        &mut self,
+        system_time: u64,
+        system_timestamp: u64,
+        first_after_overflow: bool,
+        state: &neuromorphic_drivers::adapters::State,
+        buffer: &[u8],
+        errors: &mut Vec<String>,
+    ) {
+        if let Some(raw_file) = self.raw_file.as_mut() {
+            if let Some(index_file) = self.index_file.as_mut() {
+                let raw_file_offset = self.raw_file_offset;
+                let packet: &[u8] = if self.options.raw_compression {
+                    recordings::compress_packet(buffer, &mut self.frame);
+                    &self.frame
+                } else {
+                    buffer
+                };
+                let (count, result) = write_all_count(raw_file, packet);
+                self.raw_file_offset += count;
+                self.size_bytes += count;
+                if let Err(error) = result {
+                    if !self.raw_file_error {
+                        self.raw_file_error = true;
+                        errors.push(format!(
+                            "Writing to \"{}\" failed ({})",
+                            raw_file_path(&self.directory, &self.name, true).to_string_lossy(),
+                            error
+                        ));
+                    }
+                }
+                let (count, result) = Index {
+                    system_time,
+                    system_timestamp,
+                    first_after_overflow,
+                    raw_file_offset,
+                    raw_length: count,
+                    state,
+                    checksum: self
+                        .options
+                        .packet_checksums
+                        .then(|| crate::crc32c::checksum(&packet[0..count as usize])),
+                }
+                .write_to(index_file);
+                self.size_bytes += count;
+                if let Err(error) = result {
+                    if !self.index_file_error {
+                        self.index_file_error = true;
+                        errors.push(format!(
+                            "Writing to \"{}\" failed ({})",
+                            index_file_path(&self.directory, &self.name, true).to_string_lossy(),
+                            error
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
     fn update_file_state(&self, current_t: u64, file_state: &mut Option<FileState>) {
         match file_state {
             Some(file_state) => {
@@ -863,6 +1271,9 @@ impl Recording {
                 }
                 file_state.duration_us = current_t.max(self.start_t) - self.start_t;
                 file_state.size_bytes = self.size_bytes;
+                file_state.durable_raw_file_offset = self.syncer.durable_raw_file_offset();
+                file_state.durability = self.durability;
+                file_state.segment = self.segment;
             }
             None => {
                 let _ = file_state.replace(FileState {
@@ -870,6 +1281,9 @@ impl Recording {
                     name: self.name.clone(),
                     duration_us: current_t.max(self.start_t) - self.start_t,
                     size_bytes: self.size_bytes,
+                    durable_raw_file_offset: self.syncer.durable_raw_file_offset(),
+                    durability: self.durability,
+                    segment: self.segment,
                 });
             }
         }
@@ -878,6 +1292,8 @@ impl Recording {
 
 impl Drop for Recording {
     fn drop(&mut self) {
+        // the sync thread's file handles are closed before renaming the files
+        self.syncer.stop();
         let _ = self.raw_file.take();
         let _ = std::fs::rename(
             raw_file_path(&self.directory, &self.name, true),
@@ -904,13 +1320,13 @@ fn create_new_recording(
     adapter: &neuromorphic_drivers::Adapter,
     properties: &Properties,
     new_configuration: &Option<neuromorphic_drivers::Configuration>,
-    device: &neuromorphic_drivers::Device,
+    device: &Inner,
     event_thread_state: &std::sync::Arc<std::sync::Mutex<EventThreadState>>,
     context: &std::sync::Arc<tokio::sync::Mutex<crate::Context>>,
     autostop_reference_t: &mut u64,
     trigger: Trigger,
 ) -> Option<Recording> {
-    let directory = {
+    let (directory, options) = {
         let mut context_guard = context.blocking_lock();
         let directory = std::path::PathBuf::from(&context_guard.shared_client_state.data_directory)
             .join("recordings");
@@ -922,7 +1338,7 @@ fn create_new_recording(
             ));
             return None;
         }
-        directory
+        (directory, context_guard.recording_options)
     };
     let datetime = match unwrap_non_empty_lookback(lookback) {
         Some(lookback) => &lookback.items[lookback.read_index].datetime,
@@ -944,6 +1360,12 @@ fn create_new_recording(
             &lookback.items[lookback.read_index].state,
             properties,
             &lookback.items[lookback.read_index].configuration,
+            RecordingParameters {
+                session: name.clone(),
+                segment: 0,
+                durability: protocol::Durability::default(),
+                options,
+            },
         ),
         None => Recording::new(
             directory.clone(),
@@ -957,6 +1379,12 @@ fn create_new_recording(
                 Some(new_configuration) => new_configuration.clone(),
                 None => device.current_configuration(),
             },
+            RecordingParameters {
+                session: name.clone(),
+                segment: 0,
+                durability: protocol::Durability::default(),
+                options,
+            },
         ),
     };
     match new_recording {
@@ -979,8 +1407,16 @@ fn create_new_recording(
                             let mut index = lookback.read_index;
                             while index != lookback.write_index {
                                 let raw_file_offset = new_recording.raw_file_offset;
-                                let (count, result) =
-                                    write_all_count(raw_file, &lookback.items[index].raw);
+                                let packet: &[u8] = if new_recording.options.raw_compression {
+                                    recordings::compress_packet(
+                                        &lookback.items[index].raw,
+                                        &mut new_recording.frame,
+                                    );
+                                    &new_recording.frame
+                                } else {
+                                    &lookback.items[index].raw
+                                };
+                                let (count, result) = write_all_count(raw_file, packet);
                                 new_recording.raw_file_offset += count;
                                 new_recording.size_bytes += count;
                                 if let Err(error) = result {
@@ -1008,6 +1444,9 @@ fn create_new_recording(
                                     raw_file_offset,
                                     raw_length: count,
                                     state: &lookback.items[index].state,
+                                    checksum: new_recording.options.packet_checksums.then(|| {
+                                        crate::crc32c::checksum(&packet[0..count as usize])
+                                    }),
                                 }
                                 .write_to(index_file);
                                 new_recording.size_bytes += count;
@@ -1087,6 +1526,58 @@ fn create_new_recording(
     }
 }
 
+fn segment_is_due(
+    segmenting: &protocol::Segmenting,
+    recording: &Recording,
+    current_t: u64,
+) -> bool {
+    segmenting.enabled
+        && ((segmenting.maximum_duration_us > 0
+            && current_t.max(recording.start_t) - recording.start_t
+                >= segmenting.maximum_duration_us)
+            || (segmenting.maximum_size_bytes > 0
+                && recording.size_bytes >= segmenting.maximum_size_bytes))
+}
+
+// Unlike create_new_recording, the new segment does not include lookback data
+// (the lookback packets are already in the previous segments).
+// `state` must be the adapter state before the next buffer.
+// Returns an error message if the segment's files cannot be created.
+fn create_next_segment(
+    recording: &Recording,
+    now: &chrono::DateTime<chrono::Local>,
+    adapter: &neuromorphic_drivers::Adapter,
+    state: &neuromorphic_drivers::adapters::State,
+    properties: &Properties,
+    configuration: &neuromorphic_drivers::Configuration,
+) -> Result<Recording, String> {
+    let segment = recording.segment + 1;
+    let name = format!("{}_segment-{:04}", recording.session, segment);
+    match Recording::new(
+        recording.directory.clone(),
+        name.clone(),
+        now,
+        Trigger::Segment(crate::utc_string(now)),
+        adapter,
+        state,
+        properties,
+        configuration,
+        RecordingParameters {
+            session: recording.session.clone(),
+            segment,
+            durability: recording.durability,
+            options: recording.options,
+        },
+    ) {
+        Ok(new_recording) => Ok(new_recording),
+        Err(error) => Err(format!(
+            "Creating \"{}\" failed ({})",
+            recording.directory.join(name).to_string_lossy(),
+            error
+        )),
+    }
+}
+
 impl SamplerRecording {
     fn new(
         directory: std::path::PathBuf,
@@ -1107,8 +1598,40 @@ impl SamplerRecording {
             samples_file: Some(samples_file),
             samples_file_error: false,
             size_bytes,
+            synced_size_bytes: 0,
+            synced_instant: std::time::Instant::now(),
+            sync_error: false,
         })
     }
+
+    // Flushes and syncs the samples file if required by the durability policy.
+    // Returns an error message the first time syncing fails.
+    fn sync_if_due(&mut self, durability: &protocol::Durability) -> Option<String> {
+        if !sync_is_due(
+            durability,
+            &self.synced_instant,
+            self.size_bytes - self.synced_size_bytes,
+        ) {
+            return None;
+        }
+        self.synced_instant = std::time::Instant::now();
+        self.synced_size_bytes = self.size_bytes;
+        match sync_file(&mut self.samples_file) {
+            Ok(()) => None,
+            Err(error) => {
+                if self.sync_error {
+                    None
+                } else {
+                    self.sync_error = true;
+                    Some(format!(
+                        "Syncing \"{}\" failed ({})",
+                        samples_file_path(&self.directory, &self.name, true).to_string_lossy(),
+                        error
+                    ))
+                }
+            }
+        }
+    }
 }
 
 impl Drop for SamplerRecording {
@@ -1172,12 +1695,55 @@ pub fn create_device_and_proxies(
         neuromorphic_drivers::Error,
         neuromorphic_drivers::UsbOverflow,
     >,
+) -> (Device, DeviceSampler, DeviceProxy) {
+    let properties = Properties {
+        name: listed_device.device_type.name().to_owned(),
+        serial: listed_device
+            .serial
+            .expect("Device::new is given a valid listed_device"),
+        speed: listed_device.speed.to_string(),
+        bus_number: listed_device.bus_number,
+        address: listed_device.address,
+    };
+    create_device_and_proxies_from_inner(id, properties, Inner::Usb(device), Some(flag))
+}
+
+pub fn create_replay_device_and_proxies(
+    id: DeviceId,
+    replay: replay::Replay,
+) -> (Device, DeviceSampler, DeviceProxy) {
+    let properties = replay.properties.clone();
+    create_device_and_proxies_from_inner(id, properties, Inner::Replay(Box::new(replay)), None)
+}
+
+pub fn create_synthetic_device_and_proxies(
+    id: DeviceId,
+    synthetic: synthetic::Synthetic,
+) -> (Device, DeviceSampler, DeviceProxy) {
+    let properties = synthetic.properties.clone();
+    create_device_and_proxies_from_inner(
+        id,
+        properties,
+        Inner::Synthetic(Box::new(synthetic)),
+        None,
+    )
+}
+
+fn create_device_and_proxies_from_inner(
+    id: DeviceId,
+    properties: Properties,
+    inner: Inner,
+    flag: Option<
+        neuromorphic_drivers::Flag<neuromorphic_drivers::Error, neuromorphic_drivers::UsbOverflow>,
+    >,
 ) -> (Device, DeviceSampler, DeviceProxy) {
     let record_configuration = std::sync::Arc::new(std::sync::Mutex::new(RecordConfiguration {
         action: RecordAction::Continue,
         lookback: protocol::Lookback::default(),
         autostop: protocol::Autostop::default(),
         autotrigger: protocol::Autotrigger::default(),
+        durability: protocol::Durability::default(),
+        segmenting: protocol::Segmenting::default(),
     }));
     let event_thread_state = std::sync::Arc::new(std::sync::Mutex::new(EventThreadState {
         on_event_rate: 0.0,
@@ -1188,18 +1754,9 @@ pub fn create_device_and_proxies(
         file_state: None,
         autotrigger_state: None,
     }));
-    let inner = std::sync::Arc::new(device);
+    let inner = std::sync::Arc::new(inner);
     let running = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
     let configuration_changed = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
-    let properties = Properties {
-        name: listed_device.device_type.name().to_owned(),
-        serial: listed_device
-            .serial
-            .expect("Device::new is given a valid listed_device"),
-        speed: listed_device.speed.to_string(),
-        bus_number: listed_device.bus_number,
-        address: listed_device.address,
-    };
     (
         Device {
             id,
@@ -1244,7 +1801,8 @@ impl Device {
         let mut data_buffer_start_state = adapter.state();
         let mut data_buffer_on_event_count = 0;
         let mut data_buffer_off_event_count = 0;
-        let mut next_packet_index: u64 = 1;
+        let mut next_packet_index: u64 =
+            (adapter.current_t() as f64 * constants::PACKET_FREQUENCY / 1e6).floor() as u64 + 1;
         let mut data_buffer_end_t =
             (next_packet_index as f64 * 1e6 / constants::PACKET_FREQUENCY).round() as u64;
 
@@ -1257,10 +1815,15 @@ impl Device {
         let mut lookback: Option<Lookback> = None;
         let mut autostop_reference_t: u64 = 0;
         let mut autotrigger_moving_window = AutotriggerMovingWindow::new();
+        let mut virtual_buffer = Vec::new();
 
         loop {
             // break on error
-            if let Err(error) = self.flag.load_error() {
+            if let Some(error) = self
+                .flag
+                .as_ref()
+                .and_then(|flag| flag.load_error().err())
+            {
                 self.running
                     .store(false, std::sync::atomic::Ordering::Relaxed);
                 let mut context_guard = context.blocking_lock();
@@ -1274,13 +1837,39 @@ impl Device {
             }
 
             // read the next camera buffer
-            let buffer_view = self
-                .inner
-                .next_with_timeout(&std::time::Duration::from_millis(100));
+            let usb_buffer_view;
+            let buffer_view = match self.inner.as_ref() {
+                Inner::Usb(device) => {
+                    usb_buffer_view =
+                        device.next_with_timeout(&std::time::Duration::from_millis(100));
+                    usb_buffer_view.as_ref().map(|buffer_view| BufferView {
+                        system_time: buffer_view.system_time,
+                        instant: buffer_view.instant,
+                        first_after_overflow: buffer_view.first_after_overflow,
+                        slice: buffer_view.slice,
+                    })
+                }
+                Inner::Replay(replay) => replay
+                    .next_with_timeout(&std::time::Duration::from_millis(100), &mut virtual_buffer)
+                    .map(|(system_time, instant, first_after_overflow)| BufferView {
+                        system_time,
+                        instant,
+                        first_after_overflow,
+                        slice: &virtual_buffer,
+                    }),
+                Inner::Synthetic(synthetic) => synthetic
+                    .next_with_timeout(&std::time::Duration::from_millis(100), &mut virtual_buffer)
+                    .map(|(system_time, instant)| BufferView {
+                        system_time,
+                        instant,
+                        first_after_overflow: false,
+                        slice: &virtual_buffer,
+                    }),
+            };
 
             // read the record configuration
             let now = chrono::Local::now();
-            let (record_action, new_lookback, autostop, autotrigger) = {
+            let (record_action, new_lookback, autostop, autotrigger, durability, segmenting) = {
                 let mut record_configuration_guard = self
                     .record_configuration
                     .lock()
@@ -1292,6 +1881,8 @@ impl Device {
                     record_configuration_guard.lookback,
                     record_configuration_guard.autostop,
                     record_configuration_guard.autotrigger,
+                    record_configuration_guard.durability,
+                    record_configuration_guard.segmenting,
                 )
             };
 
@@ -1337,6 +1928,18 @@ impl Device {
                 }
             }
 
+            // flush and sync the recording files (durability policy)
+            if let Some(recording) = recording.as_mut() {
+                recording.durability = durability;
+                if let Some(error) = recording.sync_if_due() {
+                    context
+                        .blocking_lock()
+                        .shared_client_state
+                        .errors
+                        .push(error);
+                }
+            }
+
             // update the lookback configuration if it changed
             if new_lookback.enabled {
                 match lookback.as_mut() {
@@ -1352,20 +1955,7 @@ impl Device {
                         let _ = lookback.replace(Lookback::new(
                                 new_lookback.maximum_duration_us,
                                 new_lookback.maximum_size_bytes,
-                                match self.inner.as_ref() {
-                                    neuromorphic_drivers::Device::InivationDavis346(_) => {
-                                        neuromorphic_drivers::inivation_davis346::DEFAULT_USB_CONFIGURATION.buffer_length
-                                    },
-                                    neuromorphic_drivers::Device::InivationDvxplorer(_) => {
-                                        neuromorphic_drivers::inivation_davis346::DEFAULT_USB_CONFIGURATION.buffer_length
-                                    },
-                                    neuromorphic_drivers::Device::PropheseeEvk3Hd(_) => {
-                                        neuromorphic_drivers::prophesee_evk3_hd::DEFAULT_USB_CONFIGURATION.buffer_length
-                                    },
-                                    neuromorphic_drivers::Device::PropheseeEvk4(_) => {
-                                        neuromorphic_drivers::prophesee_evk4::DEFAULT_USB_CONFIGURATION.buffer_length
-                                    },
-                                },
+                                self.inner.buffer_length(),
                                 adapter.state(),
                                 self.inner.current_configuration(),
                             ));
@@ -1576,62 +2166,54 @@ impl Device {
                     }
                 }
 
-                // write raw event data to the recording
-                if let Some(recording) = recording.as_mut() {
-                    if let Some(raw_file) = recording.raw_file.as_mut() {
-                        if let Some(index_file) = recording.index_file.as_mut() {
-                            let raw_file_offset = recording.raw_file_offset;
-                            let (count, result) = write_all_count(raw_file, buffer_view.slice);
-                            recording.raw_file_offset += count;
-                            recording.size_bytes += count;
-                            if let Err(error) = result {
-                                if !recording.raw_file_error {
-                                    recording.raw_file_error = true;
-                                    context.blocking_lock().shared_client_state.errors.push(
-                                        format!(
-                                            "Writing to \"{}\" failed ({})",
-                                            raw_file_path(
-                                                &recording.directory,
-                                                &recording.name,
-                                                true
-                                            )
-                                            .to_string_lossy(),
-                                            error
-                                        ),
-                                    );
-                                }
-                            }
-                            let (count, result) = Index {
-                                system_time,
-                                system_timestamp,
-                                first_after_overflow: buffer_view.first_after_overflow,
-                                raw_file_offset,
-                                raw_length: count,
-                                state: &previous_state,
+                // start a new segment (the current buffer is written to the new segment)
+                if let Some(current_recording) = recording.as_ref() {
+                    if segment_is_due(&segmenting, current_recording, previous_state.current_t()) {
+                        let next_recording = create_next_segment(
+                            current_recording,
+                            &now,
+                            &adapter,
+                            &previous_state,
+                            &self.properties,
+                            &self.inner.current_configuration(),
+                        );
+                        // dropping the current segment renames its files
+                        let _ = recording.take();
+                        match next_recording {
+                            Ok(next_recording) => {
+                                let _ = recording.replace(next_recording);
                             }
-                            .write_to(index_file);
-                            recording.size_bytes += count;
-                            if let Err(error) = result {
-                                if !recording.index_file_error {
-                                    recording.index_file_error = true;
-                                    context.blocking_lock().shared_client_state.errors.push(
-                                        format!(
-                                            "Writing to \"{}\" failed ({})",
-                                            index_file_path(
-                                                &recording.directory,
-                                                &recording.name,
-                                                true
-                                            )
-                                            .to_string_lossy(),
-                                            error
-                                        ),
-                                    );
-                                }
+                            Err(error) => {
+                                context
+                                    .blocking_lock()
+                                    .shared_client_state
+                                    .errors
+                                    .push(error);
                             }
                         }
                     }
                 }
 
+                // write raw event data to the recording
+                if let Some(recording) = recording.as_mut() {
+                    let mut errors = Vec::new();
+                    recording.write_buffer(
+                        system_time,
+                        system_timestamp,
+                        buffer_view.first_after_overflow,
+                        &previous_state,
+                        buffer_view.slice,
+                        &mut errors,
+                    );
+                    if !errors.is_empty() {
+                        context
+                            .blocking_lock()
+                            .shared_client_state
+                            .errors
+                            .extend(errors);
+                    }
+                }
+
                 // push data to the lookback buffer
                 if let Some(lookback) = lookback.as_mut() {
                     lookback.push(
@@ -1702,6 +2284,7 @@ enum SamplerRecordingAction {
         name: String,
         duration_us: u64,
         size_bytes: u64,
+        segment: u32,
     },
     Stop,
 }
@@ -1739,24 +2322,31 @@ impl DeviceSampler {
             }
 
             // sample camera data
+            let system_time = std::time::SystemTime::now()
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                .unwrap_or(std::time::Duration::default())
+                .as_micros() as u64;
+            let system_timestamp = std::time::Instant::now()
+                .duration_since(time_reference)
+                .as_micros() as u64;
             let mut ui_sample = match self.inner.as_ref() {
-                neuromorphic_drivers::Device::InivationDavis346(_) => {
+                Inner::Usb(neuromorphic_drivers::Device::InivationDavis346(_)) => {
                     todo!()
                 }
-                neuromorphic_drivers::Device::InivationDvxplorer(_) => {
+                Inner::Usb(neuromorphic_drivers::Device::InivationDvxplorer(_)) => {
                     todo!()
                 }
-                neuromorphic_drivers::Device::PropheseeEvk3Hd(_) => {
+                Inner::Usb(neuromorphic_drivers::Device::PropheseeEvk3Hd(_)) => {
                     todo!()
                 }
-                neuromorphic_drivers::Device::PropheseeEvk4(device) => {
+                Inner::Usb(neuromorphic_drivers::Device::PropheseeEvk4(device)) => {
                     let illuminance = device.illuminance().unwrap_or(u32::MAX);
                     let temperature = device
                         .temperature_celsius()
                         .map_or(f32::NAN, |temperature_celsius| temperature_celsius.0);
                     UiSample::Evk4(Evk4UiSample {
-                        system_time: 0,
-                        system_timestamp: 0,
+                        system_time,
+                        system_timestamp,
                         on_event_rate: 0.0,
                         off_event_rate: 0.0,
                         rising_trigger_count: 0,
@@ -1768,21 +2358,42 @@ impl DeviceSampler {
                         autotrigger_state: previous_autotrigger_state,
                     })
                 }
+                Inner::Replay(replay) => {
+                    let (illuminance, temperature) = replay.sample();
+                    UiSample::Evk4(Evk4UiSample {
+                        system_time,
+                        system_timestamp,
+                        on_event_rate: 0.0,
+                        off_event_rate: 0.0,
+                        rising_trigger_count: 0,
+                        falling_trigger_count: 0,
+                        illuminance,
+                        temperature,
+                        autotrigger_state: previous_autotrigger_state,
+                    })
+                }
+                Inner::Synthetic(synthetic) => {
+                    let (illuminance, temperature) = synthetic.sample();
+                    UiSample::Evk4(Evk4UiSample {
+                        system_time,
+                        system_timestamp,
+                        on_event_rate: 0.0,
+                        off_event_rate: 0.0,
+                        rising_trigger_count: 0,
+                        falling_trigger_count: 0,
+                        illuminance,
+                        temperature,
+                        autotrigger_state: previous_autotrigger_state,
+                    })
+                }
             };
 
             // receive data from the event thread
-            let (sampler_recording_action, lookback_state) = {
+            let (sampler_recording_action, lookback_state, durable_raw_file_offset_and_durability) = {
                 let mut event_thread_state_guard = self
                     .event_thread_state
                     .lock()
                     .expect("event thread state mutex is poisoned");
-                let system_time = std::time::SystemTime::now()
-                    .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                    .unwrap_or(std::time::Duration::default())
-                    .as_micros() as u64;
-                let system_timestamp = std::time::Instant::now()
-                    .duration_since(time_reference)
-                    .as_micros() as u64;
                 match &mut ui_sample {
                     UiSample::Davis346(davis346_ui_sample) => {
                         todo!()
@@ -1791,8 +2402,6 @@ impl DeviceSampler {
                         todo!()
                     }
                     UiSample::Evk4(evk4_ui_sample) => {
-                        evk4_ui_sample.system_time = system_time;
-                        evk4_ui_sample.system_timestamp = system_timestamp;
                         evk4_ui_sample.on_event_rate = event_thread_state_guard.on_event_rate;
                         evk4_ui_sample.off_event_rate = event_thread_state_guard.off_event_rate;
                         evk4_ui_sample.rising_trigger_count =
@@ -1825,6 +2434,7 @@ impl DeviceSampler {
                                     name: file_state.name.clone(),
                                     duration_us: file_state.duration_us,
                                     size_bytes: file_state.size_bytes,
+                                    segment: file_state.segment,
                                 }
                             }
                         }
@@ -1833,6 +2443,7 @@ impl DeviceSampler {
                             name: file_state.name.clone(),
                             duration_us: file_state.duration_us,
                             size_bytes: file_state.size_bytes,
+                            segment: file_state.segment,
                         },
                     },
                     None => SamplerRecordingAction::Stop,
@@ -1840,6 +2451,12 @@ impl DeviceSampler {
                 (
                     sampler_recording_action,
                     event_thread_state_guard.lookback_state.clone(),
+                    event_thread_state_guard
+                        .file_state
+                        .as_ref()
+                        .map(|file_state| {
+                            (file_state.durable_raw_file_offset, file_state.durability)
+                        }),
                 )
             };
 
@@ -1883,6 +2500,7 @@ impl DeviceSampler {
                         name,
                         duration_us,
                         size_bytes,
+                        segment,
                     } => {
                         let _ = sampler_recording.take();
                         // 0 is Prophesee EVK4
@@ -1891,7 +2509,10 @@ impl DeviceSampler {
                                 if let Some(samples_file) =
                                     new_sampler_recording.samples_file.as_mut()
                                 {
-                                    if let Some(sampler_lookback) = sampler_lookback.as_ref() {
+                                    // segments after the first one continue the previous segment's samples
+                                    if let Some(sampler_lookback) =
+                                        sampler_lookback.as_ref().filter(|_| segment == 0)
+                                    {
                                         let mut index = sampler_lookback.read_index;
                                         let mut lookback_count = 0;
                                         let mut lookback_result = Ok(());
@@ -1981,7 +2602,26 @@ impl DeviceSampler {
                         name_and_duration_us_and_size_bytes.2 += sampler_recording.size_bytes;
                     }
                 }
+                if let Some((_, durability)) = durable_raw_file_offset_and_durability.as_ref() {
+                    if let Some(error) = sampler_recording.sync_if_due(durability) {
+                        context
+                            .blocking_lock()
+                            .shared_client_state
+                            .errors
+                            .push(error);
+                    }
+                }
             }
+            let recording_state =
+                name_and_duration_us_and_size_bytes.map(|(name, duration_us, size_bytes)| {
+                    RecordingRecordState {
+                        name,
+                        duration_us,
+                        size_bytes,
+                        durable_raw_file_offset: durable_raw_file_offset_and_durability
+                            .map_or(0, |(durable_raw_file_offset, _)| durable_raw_file_offset),
+                    }
+                });
 
             // send data to the UI
             {
@@ -2023,16 +2663,16 @@ impl DeviceSampler {
                         };
                         if let Some(mut buffer) = buffer {
                             buffer.clear();
-                            let total_length = 42
-                                + name_and_duration_us_and_size_bytes
+                            let total_length = 50
+                                + recording_state
                                     .as_ref()
-                                    .map_or(0, |(name, _, _)| name.len());
+                                    .map_or(0, |recording_state| recording_state.name.len());
                             buffer.reserve_exact(total_length);
                             buffer.extend(&(total_length as u32).to_le_bytes()); // 4
                             serialize_record_state_to(
                                 self.id,
                                 &lookback_state,
-                                &name_and_duration_us_and_size_bytes,
+                                &recording_state,
                                 &mut buffer,
                             );
                             if let Ok(permit) = sender.try_reserve() {
@@ -2059,3 +2699,262 @@ impl DeviceSampler {
             .remove(&stream_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recordings::tests as recordings_tests;
+
+    fn adapter() -> neuromorphic_drivers::Adapter {
+        neuromorphic_drivers::Adapter::Evt3(
+            neuromorphic_drivers::adapters::evt3::Adapter::from_dimensions_and_state(
+                recordings_tests::WIDTH,
+                recordings_tests::HEIGHT,
+                recordings_tests::initial_state(),
+            ),
+        )
+    }
+
+    fn properties() -> Properties {
+        Properties {
+            name: neuromorphic_drivers::devices::Type::PropheseeEvk4
+                .name()
+                .to_owned(),
+            serial: "00000000".to_owned(),
+            speed: "Test".to_owned(),
+            bus_number: 0,
+            address: 0,
+        }
+    }
+
+    fn new_recording(
+        data_directory: &std::path::Path,
+        name: &str,
+        adapter: &neuromorphic_drivers::Adapter,
+        durability: protocol::Durability,
+    ) -> Recording {
+        let now = chrono::Local::now();
+        Recording::new(
+            data_directory.join(recordings::RECORDINGS_DIRECTORY_NAME),
+            name.to_owned(),
+            &now,
+            Trigger::Manual(crate::utc_string(&now)),
+            adapter,
+            &adapter.state(),
+            &properties(),
+            &neuromorphic_drivers::Configuration::PropheseeEvk4(
+                neuromorphic_drivers::prophesee_evk4::DEFAULT_CONFIGURATION,
+            ),
+            RecordingParameters {
+                session: name.to_owned(),
+                segment: 0,
+                durability,
+                options: RecordingOptions {
+                    time_reference_system_time: recordings_tests::START_SYSTEM_TIME,
+                    packet_checksums: true,
+                    raw_compression: false,
+                },
+            },
+        )
+        .unwrap()
+    }
+
+    // Writes test packets [begin, end[ to the recording and decodes them with the adapter
+    fn write_packets(
+        recording: &mut Recording,
+        adapter: &mut neuromorphic_drivers::Adapter,
+        begin: u64,
+        end: u64,
+    ) {
+        for index in begin..end {
+            let packet = recordings_tests::packet(index);
+            let state = adapter.state();
+            let mut errors = Vec::new();
+            recording.write_buffer(
+                recordings_tests::START_SYSTEM_TIME + index * recordings_tests::PACKET_DURATION_US,
+                index * recordings_tests::PACKET_DURATION_US,
+                false,
+                &state,
+                &packet,
+                &mut errors,
+            );
+            assert!(errors.is_empty(), "{errors:?}");
+            let _ = adapter.events_lengths_until(&packet, u64::MAX);
+        }
+    }
+
+    fn wait_for_durable_raw_file_offset(recording: &Recording, raw_file_offset: u64) {
+        let start = std::time::Instant::now();
+        while recording.syncer.durable_raw_file_offset() != raw_file_offset {
+            assert!(
+                start.elapsed() < std::time::Duration::from_secs(10),
+                "the durable offset is {} instead of {}",
+                recording.syncer.durable_raw_file_offset(),
+                raw_file_offset
+            );
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn sync_thread_reports_the_durable_offset() {
+        let data_directory = recordings_tests::DataDirectory::new("durable-offset");
+        let mut adapter = adapter();
+        let mut recording = new_recording(
+            &data_directory.path,
+            "recording",
+            &adapter,
+            protocol::Durability {
+                period_ms: 0,
+                size_bytes: 1,
+            },
+        );
+        let header_length = recording.raw_file_offset;
+        assert_eq!(recording.syncer.durable_raw_file_offset(), header_length);
+        write_packets(&mut recording, &mut adapter, 0, 4);
+        // the data is not durable until the next sync
+        assert_eq!(recording.syncer.durable_raw_file_offset(), header_length);
+        assert!(recording.sync_if_due().is_none());
+        let raw_file_offset = recording.raw_file_offset;
+        wait_for_durable_raw_file_offset(&recording, raw_file_offset);
+        assert!(
+            std::fs::metadata(raw_file_path(&recording.directory, "recording", true))
+                .unwrap()
+                .len()
+                >= raw_file_offset
+        );
+        let mut file_state = None;
+        recording.update_file_state(0, &mut file_state);
+        assert_eq!(file_state.unwrap().durable_raw_file_offset, raw_file_offset);
+
+        // without a durability policy, the files are not synced
+        recording.durability = protocol::Durability::default();
+        write_packets(&mut recording, &mut adapter, 4, 8);
+        assert!(recording.sync_if_due().is_none());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(recording.syncer.durable_raw_file_offset(), raw_file_offset);
+
+        // dropping the recording stops the sync thread and renames the files
+        drop(recording);
+        let mut reader = recordings::Reader::open(&data_directory.path, "recording").unwrap();
+        for index in 0..8 {
+            reader.next_packet().unwrap().unwrap();
+            assert_eq!(reader.raw(), recordings_tests::packet(index));
+        }
+        assert!(reader.next_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn segments_roll_over() {
+        let data_directory = recordings_tests::DataDirectory::new("segments");
+        let mut adapter = adapter();
+        let mut recording = new_recording(
+            &data_directory.path,
+            "session",
+            &adapter,
+            protocol::Durability::default(),
+        );
+        let by_duration = protocol::Segmenting {
+            enabled: true,
+            maximum_duration_us: 2500,
+            maximum_size_bytes: 0,
+        };
+        assert!(!segment_is_due(&by_duration, &recording, 2499));
+        assert!(segment_is_due(&by_duration, &recording, 2500));
+        assert!(!segment_is_due(
+            &protocol::Segmenting {
+                enabled: false,
+                ..by_duration
+            },
+            &recording,
+            2500
+        ));
+        let by_size = protocol::Segmenting {
+            enabled: true,
+            maximum_duration_us: 0,
+            maximum_size_bytes: recording.size_bytes + 1,
+        };
+        assert!(!segment_is_due(&by_size, &recording, u64::MAX));
+        write_packets(&mut recording, &mut adapter, 0, 3);
+        assert!(segment_is_due(&by_size, &recording, 0));
+
+        // the next segments continue the session and the decoder state
+        let configuration = neuromorphic_drivers::Configuration::PropheseeEvk4(
+            neuromorphic_drivers::prophesee_evk4::DEFAULT_CONFIGURATION,
+        );
+        let mut states = Vec::new();
+        for (segment, (begin, end)) in [(3, 5), (5, 8)].into_iter().enumerate() {
+            let state = adapter.state();
+            if let neuromorphic_drivers::adapters::State::Evt3(evt3_state) = state {
+                states.push(evt3_state);
+            }
+            let next_recording = create_next_segment(
+                &recording,
+                &chrono::Local::now(),
+                &adapter,
+                &state,
+                &properties(),
+                &configuration,
+            )
+            .unwrap();
+            assert_eq!(
+                next_recording.name,
+                format!("session_segment-{:04}", segment + 1)
+            );
+            assert_eq!(next_recording.session, "session");
+            assert_eq!(next_recording.segment, segment as u32 + 1);
+            assert_eq!(next_recording.start_t, state.current_t());
+            recording = next_recording;
+            write_packets(&mut recording, &mut adapter, begin, end);
+        }
+        drop(recording);
+
+        recordings_tests::write_recording(&data_directory.path, "continuous", 8, false, false);
+        let mut continuous_reader =
+            recordings::Reader::open(&data_directory.path, "continuous").unwrap();
+        for (segment, name, begin, end) in [
+            (0, "session", 0, 3),
+            (1, "session_segment-0001", 3, 5),
+            (2, "session_segment-0002", 5, 8),
+        ] {
+            let mut reader = recordings::Reader::open(&data_directory.path, name).unwrap();
+            assert_eq!(reader.index_entries, end - begin);
+            if segment > 0 {
+                // the first packet stores the decoder state at the rollover
+                assert_eq!(reader.index_entry(0).unwrap().state, states[segment - 1]);
+            }
+            // the packets of each segment decode to the same events as a continuous recording
+            continuous_reader.seek_index(begin).unwrap();
+            for _ in begin..end {
+                reader.next_packet().unwrap().unwrap();
+                continuous_reader.next_packet().unwrap().unwrap();
+                let mut events = Vec::new();
+                reader.convert(|dvs_event| events.push(dvs_event), |_| {});
+                let mut continuous_events = Vec::new();
+                continuous_reader.convert(|dvs_event| continuous_events.push(dvs_event), |_| {});
+                assert_eq!(events.len(), 4);
+                assert_eq!(events, continuous_events);
+            }
+            assert!(reader.next_packet().unwrap().is_none());
+            let metadata = toml::from_str::<toml::Table>(
+                &std::fs::read_to_string(recordings::recording_path(
+                    &data_directory.path,
+                    name,
+                    recordings::METADATA_FILE_EXTENSION,
+                    false,
+                ))
+                .unwrap(),
+            )
+            .unwrap();
+            assert_eq!(metadata["session"]["id"].as_str(), Some("session"));
+            assert_eq!(
+                metadata["session"]["segment"].as_integer(),
+                Some(segment as i64)
+            );
+            assert_eq!(
+                metadata["trigger"]["mode"].as_str(),
+                Some(if segment == 0 { "manual" } else { "segment" })
+            );
+        }
+    }
+}
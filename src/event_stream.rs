@@ -0,0 +1,81 @@
+// Event Stream 2.0.0 encoder (https://github.com/neuromorphic-paris/event_stream)
+// Each file contains a single event type, timestamps are encoded as deltas in µs.
+use std::io::Write;
+
+pub const FILE_EXTENSION: &str = ".es";
+const SIGNATURE: &str = "Event Stream";
+const VERSION: [u8; 3] = [2, 0, 0];
+
+const GENERIC_TYPE: u8 = 0;
+const DVS_TYPE: u8 = 1;
+
+pub fn write_generic_header(output: &mut impl Write) -> std::io::Result<()> {
+    output.write_all(SIGNATURE.as_bytes())?;
+    output.write_all(&VERSION)?;
+    output.write_all(&[GENERIC_TYPE])?;
+    Ok(())
+}
+
+pub fn write_dvs_header(output: &mut impl Write, width: u16, height: u16) -> std::io::Result<()> {
+    output.write_all(SIGNATURE.as_bytes())?;
+    output.write_all(&VERSION)?;
+    output.write_all(&[DVS_TYPE])?;
+    output.write_all(&width.to_le_bytes())?;
+    output.write_all(&height.to_le_bytes())?;
+    Ok(())
+}
+
+// Timestamps must be monotonic, smaller timestamps are clamped to the previous one.
+pub struct DvsEncoder {
+    previous_t: u64,
+}
+
+impl DvsEncoder {
+    pub fn new() -> Self {
+        Self { previous_t: 0 }
+    }
+
+    pub fn encode(&mut self, t: u64, x: u16, y: u16, on: bool, bytes: &mut Vec<u8>) {
+        let mut relative_t = t.saturating_sub(self.previous_t);
+        // 0b11111111 is an overflow (the timestamp increases by 0b1111111)
+        if relative_t >= 0b1111111 {
+            let overflows = relative_t / 0b1111111;
+            bytes.resize(bytes.len() + overflows as usize, 0b11111111);
+            relative_t -= overflows * 0b1111111;
+        }
+        bytes.push(((relative_t as u8) << 1) | (on as u8));
+        bytes.extend_from_slice(&x.to_le_bytes());
+        bytes.extend_from_slice(&y.to_le_bytes());
+        self.previous_t = self.previous_t.max(t);
+    }
+}
+
+// Generic events carry arbitrary bytes, the payload must not be empty.
+pub struct GenericEncoder {
+    previous_t: u64,
+}
+
+impl GenericEncoder {
+    pub fn new() -> Self {
+        Self { previous_t: 0 }
+    }
+
+    pub fn encode(&mut self, t: u64, payload: &[u8], bytes: &mut Vec<u8>) {
+        let mut relative_t = t.saturating_sub(self.previous_t);
+        // 0b11111111 is an overflow (the timestamp increases by 0b11111110)
+        if relative_t >= 0b11111110 {
+            let overflows = relative_t / 0b11111110;
+            bytes.resize(bytes.len() + overflows as usize, 0b11111111);
+            relative_t -= overflows * 0b11111110;
+        }
+        bytes.push(relative_t as u8);
+        // variable-length size, 7 bits per byte, the lowest bit flags a continuation
+        let mut size = payload.len();
+        while size > 0 {
+            bytes.push((((size & 0b1111111) << 1) as u8) | ((size >> 7) > 0) as u8);
+            size >>= 7;
+        }
+        bytes.extend_from_slice(payload);
+        self.previous_t = self.previous_t.max(t);
+    }
+}
@@ -0,0 +1,267 @@
+// Polarity events filters applied during conversions, in the order of the chain.
+// Each pass over the raw file uses its own chain since noise filters are stateful.
+use crate::protocol;
+
+// u64::MAX marks pixels without events
+enum Stage {
+    Roi {
+        left: u16,
+        top: u16,
+        width: u16,
+        height: u16,
+    },
+    Polarity {
+        on: bool,
+    },
+    Time {
+        begin_t: u64,
+        end_t: u64,
+    },
+    BackgroundActivity {
+        tau_us: u64,
+        width: u16,
+        height: u16,
+        ts: Vec<u64>,
+    },
+    Refractory {
+        period_us: u64,
+        width: u16,
+        height: u16,
+        ts: Vec<u64>,
+    },
+}
+
+// `width` and `height` are the dimensions of the filtered events (after cropping)
+pub struct Chain {
+    stages: Vec<Stage>,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Chain {
+    pub fn new(
+        filters: &[protocol::Filter],
+        width: u16,
+        height: u16,
+    ) -> Result<Self, anyhow::Error> {
+        let mut chain = Self {
+            stages: Vec::with_capacity(filters.len()),
+            width,
+            height,
+        };
+        for filter in filters {
+            chain.stages.push(match *filter {
+                protocol::Filter::Roi {
+                    left,
+                    top,
+                    width,
+                    height,
+                } => {
+                    if width == 0
+                        || height == 0
+                        || left as u32 + width as u32 > chain.width as u32
+                        || top as u32 + height as u32 > chain.height as u32
+                    {
+                        return Err(anyhow::anyhow!(
+                            "the region of interest {}x{}+{}+{} does not fit in {}x{}",
+                            width,
+                            height,
+                            left,
+                            top,
+                            chain.width,
+                            chain.height
+                        ));
+                    }
+                    chain.width = width;
+                    chain.height = height;
+                    Stage::Roi {
+                        left,
+                        top,
+                        width,
+                        height,
+                    }
+                }
+                protocol::Filter::Polarity { on } => Stage::Polarity { on },
+                protocol::Filter::Time { begin_t, end_t } => {
+                    if begin_t >= end_t {
+                        return Err(anyhow::anyhow!(
+                            "the time filter's begin_t ({begin_t}) must be smaller than its end_t ({end_t})"
+                        ));
+                    }
+                    Stage::Time { begin_t, end_t }
+                }
+                protocol::Filter::BackgroundActivity { tau_us } => Stage::BackgroundActivity {
+                    tau_us,
+                    width: chain.width,
+                    height: chain.height,
+                    ts: vec![u64::MAX; chain.width as usize * chain.height as usize],
+                },
+                protocol::Filter::Refractory { period_us } => Stage::Refractory {
+                    period_us,
+                    width: chain.width,
+                    height: chain.height,
+                    ts: vec![u64::MAX; chain.width as usize * chain.height as usize],
+                },
+            });
+        }
+        Ok(chain)
+    }
+
+    // Returns the event's coordinates (relative to the last region of interest)
+    // or None if the event is filtered out.
+    pub fn filter(&mut self, t: u64, mut x: u16, mut y: u16, on: bool) -> Option<(u16, u16)> {
+        for stage in self.stages.iter_mut() {
+            match stage {
+                Stage::Roi {
+                    left,
+                    top,
+                    width,
+                    height,
+                } => {
+                    if x < *left || y < *top || x - *left >= *width || y - *top >= *height {
+                        return None;
+                    }
+                    x -= *left;
+                    y -= *top;
+                }
+                Stage::Polarity { on: kept_on } => {
+                    if on != *kept_on {
+                        return None;
+                    }
+                }
+                Stage::Time { begin_t, end_t } => {
+                    if t < *begin_t || t >= *end_t {
+                        return None;
+                    }
+                }
+                // an event is kept if one of its 8 neighbours had an event less than tau_us before
+                // (each event writes its timestamp to its neighbours' support map)
+                Stage::BackgroundActivity {
+                    tau_us,
+                    width,
+                    height,
+                    ts,
+                } => {
+                    if x >= *width || y >= *height {
+                        return None;
+                    }
+                    let support_t = ts[x as usize + y as usize * *width as usize];
+                    for neighbour_y in y.saturating_sub(1)..(y + 2).min(*height) {
+                        for neighbour_x in x.saturating_sub(1)..(x + 2).min(*width) {
+                            if neighbour_x != x || neighbour_y != y {
+                                ts[neighbour_x as usize + neighbour_y as usize * *width as usize] =
+                                    t;
+                            }
+                        }
+                    }
+                    if support_t == u64::MAX || t.saturating_sub(support_t) > *tau_us {
+                        return None;
+                    }
+                }
+                // an event is kept if the pixel's previous kept event is at least period_us old
+                Stage::Refractory {
+                    period_us,
+                    width,
+                    height,
+                    ts,
+                } => {
+                    if x >= *width || y >= *height {
+                        return None;
+                    }
+                    let index = x as usize + y as usize * *width as usize;
+                    if ts[index] != u64::MAX && t.saturating_sub(ts[index]) < *period_us {
+                        return None;
+                    }
+                    ts[index] = t;
+                }
+            }
+        }
+        Some((x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roi_translates_and_crops() {
+        let mut chain = Chain::new(
+            &[protocol::Filter::Roi {
+                left: 10,
+                top: 20,
+                width: 30,
+                height: 40,
+            }],
+            640,
+            480,
+        )
+        .unwrap();
+        assert_eq!((chain.width, chain.height), (30, 40));
+        assert_eq!(chain.filter(0, 10, 20, true), Some((0, 0)));
+        assert_eq!(chain.filter(0, 39, 59, false), Some((29, 39)));
+        assert_eq!(chain.filter(0, 9, 20, true), None);
+        assert_eq!(chain.filter(0, 40, 20, true), None);
+        assert_eq!(chain.filter(0, 10, 60, true), None);
+        assert!(Chain::new(
+            &[protocol::Filter::Roi {
+                left: 600,
+                top: 0,
+                width: 41,
+                height: 1,
+            }],
+            640,
+            480,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn stages_apply_in_order() {
+        let mut chain = Chain::new(
+            &[
+                protocol::Filter::Polarity { on: true },
+                protocol::Filter::Time {
+                    begin_t: 100,
+                    end_t: 200,
+                },
+                protocol::Filter::Refractory { period_us: 10 },
+            ],
+            4,
+            4,
+        )
+        .unwrap();
+        assert_eq!(chain.filter(150, 1, 1, false), None);
+        assert_eq!(chain.filter(99, 1, 1, true), None);
+        assert_eq!(chain.filter(200, 1, 1, true), None);
+        assert_eq!(chain.filter(150, 1, 1, true), Some((1, 1)));
+        assert_eq!(chain.filter(159, 1, 1, true), None);
+        assert_eq!(chain.filter(159, 2, 1, true), Some((2, 1)));
+        assert_eq!(chain.filter(160, 1, 1, true), Some((1, 1)));
+        assert!(Chain::new(
+            &[protocol::Filter::Time {
+                begin_t: 200,
+                end_t: 200,
+            }],
+            4,
+            4,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn background_activity_requires_a_recent_neighbour() {
+        let mut chain = Chain::new(
+            &[protocol::Filter::BackgroundActivity { tau_us: 100 }],
+            4,
+            4,
+        )
+        .unwrap();
+        assert_eq!(chain.filter(0, 1, 1, true), None);
+        // the first event supports its neighbours, not itself
+        assert_eq!(chain.filter(50, 1, 1, true), None);
+        assert_eq!(chain.filter(100, 2, 2, true), Some((2, 2)));
+        assert_eq!(chain.filter(300, 3, 3, true), None);
+        assert_eq!(chain.filter(301, 0, 0, true), None);
+    }
+}
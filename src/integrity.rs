@@ -0,0 +1,290 @@
+use crate::recordings;
+
+use std::io::Read;
+
+fn check_header(
+    path: &std::path::Path,
+    signature: &str,
+) -> Result<(std::io::BufReader<std::fs::File>, recordings::Header), anyhow::Error> {
+    let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut file_buffer = Vec::new();
+    let header = recordings::read_header(&mut file_buffer, &mut file, signature.as_bytes())?;
+    Ok((file, header))
+}
+
+// Returns a list of problems (empty if the recording is valid).
+// The raw and index checks are the same as those performed during conversion,
+// but they do not stop at the first problem in other files.
+pub fn verify(data_directory: &std::path::Path, name: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    // metadata file
+    match std::fs::read_to_string(recordings::recording_path(
+        data_directory,
+        name,
+        recordings::METADATA_FILE_EXTENSION,
+        false,
+    )) {
+        Ok(content) => {
+            if let Err(error) = toml::from_str::<toml::Value>(&content) {
+                problems.push(format!("the metadata file is not valid TOML ({})", error));
+            }
+        }
+        Err(error) => {
+            problems.push(format!("reading the metadata file failed ({})", error));
+        }
+    }
+
+    // raw and index files
+    match recordings::Reader::open(data_directory, name) {
+        Ok(mut reader) => {
+            let index_path = recordings::recording_path(
+                data_directory,
+                name,
+                recordings::INDEX_FILE_EXTENSION,
+                false,
+            );
+            if let Ok(metadata) = index_path.metadata() {
+                let trailing_bytes = metadata.len().saturating_sub(reader.index_header.length)
+                    % reader.index_entry_length() as u64;
+                if trailing_bytes > 0 {
+                    problems.push(format!(
+                        "the index file ends with a partial entry ({} bytes)",
+                        trailing_bytes
+                    ));
+                }
+            }
+            let mut raw_end = reader.raw_header.length;
+            let mut index = 0u64;
+            loop {
+                match reader.next_packet() {
+                    Ok(Some(index_entry)) => {
+                        reader.convert(|_| {}, |_| {});
+                        raw_end = index_entry.raw_file_offset + index_entry.raw_length;
+                        index += 1;
+                    }
+                    Ok(None) => break,
+                    // corrupted packets are reported individually since they do not
+                    // prevent reading the next packets
+                    Err(error) if error.is::<recordings::ChecksumMismatch>() => {
+                        problems.push(format!("packet {} is corrupted ({})", index, error));
+                        raw_end = reader.raw_file_position();
+                        index += 1;
+                    }
+                    Err(error) => {
+                        problems.push(format!("index entry {} is invalid ({})", index, error));
+                        break;
+                    }
+                }
+            }
+            if index == reader.index_entries {
+                let raw_path = recordings::recording_path(
+                    data_directory,
+                    name,
+                    recordings::RAW_FILE_EXTENSION,
+                    false,
+                );
+                if let Ok(metadata) = raw_path.metadata() {
+                    if metadata.len() > raw_end {
+                        problems.push(format!(
+                            "the raw file ends with {} bytes that are not in the index",
+                            metadata.len() - raw_end
+                        ));
+                    }
+                }
+            }
+        }
+        Err(error) => {
+            problems.push(format!(
+                "opening the raw and index files failed ({})",
+                error
+            ));
+        }
+    }
+
+    // samples file
+    let samples_path = recordings::recording_path(
+        data_directory,
+        name,
+        recordings::SAMPLES_FILE_EXTENSION,
+        false,
+    );
+    match check_header(&samples_path, recordings::SAMPLES_FILE_SIGNATURE) {
+        Ok((samples_file, samples_header)) => match samples_header.file_type {
+            // Prophesee EVK4
+            0 => {
+                let length = samples_file
+                    .get_ref()
+                    .metadata()
+                    .map(|metadata| metadata.len())
+                    .unwrap_or(0)
+                    .saturating_sub(samples_header.length);
+                if length % recordings::EVK4_SAMPLE_LENGTH as u64 != 0 {
+                    problems.push(format!(
+                        "the samples file length ({} bytes after the header) is not a multiple of {}",
+                        length,
+                        recordings::EVK4_SAMPLE_LENGTH
+                    ));
+                }
+            }
+            _ => {
+                problems.push(format!(
+                    "unsupported samples file type {}",
+                    samples_header.file_type
+                ));
+            }
+        },
+        Err(error) => {
+            problems.push(format!("opening the samples file failed ({})", error));
+        }
+    }
+
+    problems
+}
+
+fn truncate(path: &std::path::Path, length: u64) -> Result<Option<u64>, anyhow::Error> {
+    let file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let previous_length = file.metadata()?.len();
+    if previous_length > length {
+        file.set_len(length)?;
+        file.sync_all()?;
+        Ok(Some(previous_length - length))
+    } else {
+        Ok(None)
+    }
+}
+
+// Truncates torn records at the end of the index, raw, and samples files,
+// for instance after a power cut during a recording.
+// Index entries that point beyond the end of the raw file are removed, and so are
+// raw bytes that are not referenced by the index.
+// Problems that are not at the end of a file (position mismatches) are not repaired.
+// Returns a description of the changes.
+pub fn repair(data_directory: &std::path::Path, name: &str) -> Result<Vec<String>, anyhow::Error> {
+    let mut changes = Vec::new();
+    let raw_path =
+        recordings::recording_path(data_directory, name, recordings::RAW_FILE_EXTENSION, false);
+    let index_path = recordings::recording_path(
+        data_directory,
+        name,
+        recordings::INDEX_FILE_EXTENSION,
+        false,
+    );
+    let samples_path = recordings::recording_path(
+        data_directory,
+        name,
+        recordings::SAMPLES_FILE_EXTENSION,
+        false,
+    );
+
+    // raw and index files
+    let mut raw_file = std::io::BufReader::new(std::fs::File::open(&raw_path)?);
+    let (raw_header, _, _) = recordings::read_raw_header(&mut Vec::new(), &mut raw_file)?;
+    let (mut index_file, index_header) =
+        check_header(&index_path, recordings::INDEX_FILE_SIGNATURE)?;
+    let index_entry_length =
+        recordings::index_entry_length(index_header.file_type, raw_header.file_type).ok_or(
+            anyhow::anyhow!(
+                "the index and raw types are incompatible (raw is {}, index is {})",
+                raw_header.file_type,
+                index_header.file_type,
+            ),
+        )?;
+    match raw_header.file_type {
+        // EVT3 and LZ4-compressed EVT3
+        0 | 1 => {
+            let raw_length = raw_file.get_ref().metadata()?.len();
+            drop(raw_file);
+            let mut index_data = vec![0u8; index_entry_length];
+            let mut raw_file_position = raw_header.length;
+            let mut valid_entries = 0u64;
+            let mut raw_end = raw_header.length;
+            let mut entries = 0u64;
+            loop {
+                match index_file.read_exact(&mut index_data) {
+                    Ok(()) => {
+                        let index_entry = recordings::Evt3IndexEntry::from_bytes(&index_data);
+                        if index_entry.raw_file_offset != raw_file_position {
+                            return Err(anyhow::anyhow!(
+                                "Position mismatch at index entry {} (the raw file is at position {} but the index points to {}), this recording cannot be repaired",
+                                entries,
+                                raw_file_position,
+                                index_entry.raw_file_offset
+                            ));
+                        }
+                        raw_file_position += index_entry.raw_length;
+                        entries += 1;
+                        if raw_file_position <= raw_length && valid_entries + 1 == entries {
+                            valid_entries = entries;
+                            raw_end = raw_file_position;
+                        }
+                    }
+                    Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                        break;
+                    }
+                    Err(error) => {
+                        return Err(error.into());
+                    }
+                }
+            }
+            drop(index_file);
+            if let Some(removed) = truncate(
+                &index_path,
+                index_header.length + valid_entries * index_entry_length as u64,
+            )? {
+                changes.push(format!(
+                    "removed {} bytes from the index file ({} entries point beyond the end of the raw file)",
+                    removed,
+                    entries - valid_entries
+                ));
+            }
+
+            if let Some(removed) = truncate(&raw_path, raw_end)? {
+                changes.push(format!(
+                    "removed {} bytes that are not in the index from the raw file",
+                    removed
+                ));
+            }
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "unsupported raw file type {}",
+                raw_header.file_type
+            ));
+        }
+    }
+
+    // samples file
+    let (samples_file, samples_header) =
+        check_header(&samples_path, recordings::SAMPLES_FILE_SIGNATURE)?;
+    match samples_header.file_type {
+        // Prophesee EVK4
+        0 => {
+            let length = samples_file
+                .get_ref()
+                .metadata()?
+                .len()
+                .saturating_sub(samples_header.length);
+            drop(samples_file);
+            if let Some(removed) = truncate(
+                &samples_path,
+                samples_header.length
+                    + length / recordings::EVK4_SAMPLE_LENGTH as u64
+                        * recordings::EVK4_SAMPLE_LENGTH as u64,
+            )? {
+                changes.push(format!(
+                    "removed {} bytes from the samples file (partial sample)",
+                    removed
+                ));
+            }
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "unsupported samples file type {}",
+                samples_header.file_type
+            ));
+        }
+    }
+
+    Ok(changes)
+}
@@ -1,9 +1,25 @@
+mod aedat4;
 mod client;
+mod clock;
+mod columnar;
 mod constants;
+mod crc32c;
+mod csv;
 mod device;
+mod event_stream;
+mod filters;
+mod integrity;
+mod manage;
+mod metavision;
+mod npz;
 mod protocol;
 mod recordings;
+mod replay;
 mod stack;
+mod statistics;
+mod synthetic;
+mod trim;
+mod video;
 
 use clap::Parser;
 
@@ -49,8 +65,437 @@ struct Args {
     #[arg(short = 's', long, default_value_t = 1usize << 30)]
     maximum_clients_buffering_memory: usize,
 
-    #[arg(short = 'd', long, default_value = data_directory_default_value())]
+    #[arg(short = 'd', long, default_value = data_directory_default_value(), global = true)]
     data_directory: std::path::PathBuf,
+
+    /// Data directory to which clients may move recordings (can be repeated)
+    #[arg(long)]
+    move_directory: Vec<std::path::PathBuf>,
+
+    /// Name of a recording to replay as a virtual device (can be repeated)
+    #[arg(short = 'r', long)]
+    replay: Vec<String>,
+
+    /// Replay speed relative to real-time (0 replays as fast as possible)
+    #[arg(long, default_value_t = 1.0)]
+    replay_speed: f64,
+
+    /// Add a synthetic camera that generates procedural EVT3 events
+    #[arg(long)]
+    synthetic: bool,
+
+    #[arg(long, default_value_t = 1280)]
+    synthetic_width: u16,
+
+    #[arg(long, default_value_t = 720)]
+    synthetic_height: u16,
+
+    /// Total synthetic event rate (events per second)
+    #[arg(long, default_value_t = 1e6)]
+    synthetic_event_rate: f64,
+
+    #[arg(long, value_enum, value_delimiter = ',', default_values = ["bars", "disc", "noise"])]
+    synthetic_patterns: Vec<synthetic::Pattern>,
+
+    /// Period of the synthetic trigger signal (0 disables triggers)
+    #[arg(long, default_value_t = 1_000_000)]
+    synthetic_trigger_period_us: u64,
+
+    /// Flush and sync recordings to disk every N milliseconds (0 disables periodic syncs)
+    #[arg(long, default_value_t = 0)]
+    durability_period_ms: u64,
+
+    /// Flush and sync recordings to disk every N megabytes (0 disables size-based syncs)
+    #[arg(long, default_value_t = 0)]
+    durability_size_mb: u64,
+
+    /// Store a CRC32C of each raw packet in the index file to detect corruption
+    #[arg(long)]
+    packet_checksums: bool,
+
+    /// Event files included in converted recordings
+    #[arg(long, value_enum, value_delimiter = ',', default_values = ["csv"])]
+    convert_formats: Vec<recordings::Format>,
+
+    /// Frame duration of rendered videos (y4m and png formats, at least 1000 µs)
+    #[arg(
+        long,
+        default_value_t = 20_000,
+        value_parser = clap::value_parser!(u64).range(video::MINIMUM_FRAME_DURATION_US..)
+    )]
+    convert_video_frame_duration_us: u64,
+
+    /// Decay time constant of rendered videos
+    #[arg(long, default_value_t = 200_000)]
+    convert_video_tau_us: u64,
+
+    /// Decay style of rendered videos
+    #[arg(long, value_enum, default_value_t = protocol::VideoStyle::ExponentialDecay)]
+    convert_video_style: protocol::VideoStyle,
+
+    /// Add a UTC column (µs since the Unix epoch) to converted events and triggers
+    #[arg(long)]
+    convert_utc: bool,
+
+    /// Number of recordings converted in parallel (0 uses one worker per CPU core)
+    #[arg(long, default_value_t = 1)]
+    convert_workers: usize,
+
+    /// Deflate level of converted recordings (0 disables compression, 9 is the slowest)
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u8).range(0..=9))]
+    convert_compression_level: u8,
+
+    /// Compress raw packets with LZ4 as they are written (smaller files, more CPU)
+    #[arg(long)]
+    raw_compression: bool,
+
+    /// Compute the statistics of finalized recordings in the background
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    statistics: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// List the recordings and their conversions
+    List,
+
+    /// Convert recordings without starting the server (all complete recordings if no names are given)
+    Convert {
+        names: Vec<String>,
+
+        /// Event files included in converted recordings (defaults to --convert-formats)
+        #[arg(long = "format", value_enum, value_delimiter = ',')]
+        formats: Vec<recordings::Format>,
+
+        /// Conversion variant (converted-recordings/VARIANT/NAME.zip, the default conversion if empty)
+        #[arg(long, default_value = "")]
+        variant: String,
+    },
+
+    /// Check the integrity of recordings (all recordings if no names are given)
+    Verify { names: Vec<String> },
+
+    /// Truncate torn records left by an interrupted recording (all recordings if no names are given)
+    Repair { names: Vec<String> },
+
+    /// Compute and print the statistics of recordings (all complete recordings if no names are given)
+    Statistics { names: Vec<String> },
+
+    /// Copy a time range of a recording into a new recording
+    Trim {
+        name: String,
+
+        /// Range begin, sensor timestamp in µs or UTC time (RFC 3339, for instance 2025-01-01T00:00:00Z)
+        #[arg(long)]
+        begin: String,
+
+        /// Range end, sensor timestamp in µs or UTC time (RFC 3339, for instance 2025-01-01T00:00:30Z)
+        #[arg(long)]
+        end: String,
+
+        /// Name of the new recording (defaults to NAME_trim_BEGIN-END)
+        #[arg(long, default_value = "")]
+        output: String,
+    },
+}
+
+fn read_recordings(
+    data_directory: &std::path::PathBuf,
+) -> Result<Vec<protocol::Recording>, anyhow::Error> {
+    let mut recordings = Vec::new();
+    let mut first_error = None;
+    recordings::read_recordings(data_directory, &mut recordings, |error| {
+        if first_error.is_none() {
+            first_error = Some(error);
+        }
+    });
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(recordings),
+    }
+}
+
+fn recordings_names(
+    data_directory: &std::path::PathBuf,
+    names: &[String],
+) -> Result<Vec<String>, anyhow::Error> {
+    if !names.is_empty() {
+        return Ok(names.to_vec());
+    }
+    Ok(read_recordings(data_directory)?
+        .into_iter()
+        .map(|recording| recording.name)
+        .collect())
+}
+
+fn summary_string(summary: &protocol::Summary) -> String {
+    format!(
+        "{} on events, {} off events, {} triggers, {:.3} s ({:.3} s wall), {} overflows",
+        summary.on_events,
+        summary.off_events,
+        summary.triggers,
+        summary.duration_us as f64 / 1e6,
+        summary.wall_duration_us as f64 / 1e6,
+        summary.overflows
+    )
+}
+
+fn convert_options(args: &Args) -> protocol::ConvertOptions {
+    protocol::ConvertOptions {
+        variant: String::new(),
+        formats: args.convert_formats.clone(),
+        compression_level: args.convert_compression_level,
+        index: true,
+        samples: true,
+        triggers: true,
+        range: None,
+        video: protocol::VideoOptions {
+            frame_duration_us: args.convert_video_frame_duration_us,
+            tau_us: args.convert_video_tau_us,
+            style: args.convert_video_style,
+        },
+        filters: Vec::new(),
+        utc: args.convert_utc,
+    }
+}
+
+fn run_command(args: &Args, command: &Command) -> Result<(), anyhow::Error> {
+    let data_directory = &args.data_directory;
+    match command {
+        Command::List => {
+            for recording in read_recordings(data_directory)? {
+                match recording.state {
+                    protocol::RecordingState::Ongoing => {
+                        println!("{}: ongoing", recording.name);
+                    }
+                    protocol::RecordingState::Incomplete { size_bytes } => {
+                        println!("{}: incomplete, {} B", recording.name, size_bytes);
+                    }
+                    protocol::RecordingState::Complete {
+                        size_bytes,
+                        zip,
+                        variants,
+                    }
+                    | protocol::RecordingState::Queued {
+                        size_bytes,
+                        zip,
+                        variants,
+                        ..
+                    }
+                    | protocol::RecordingState::Converting {
+                        size_bytes,
+                        zip,
+                        variants,
+                        ..
+                    } => {
+                        println!(
+                            "{}: complete, {} B{}{}{}",
+                            recording.name,
+                            size_bytes,
+                            if zip { ", converted" } else { "" },
+                            if variants.is_empty() {
+                                String::new()
+                            } else {
+                                format!(", variants {}", variants.join(", "))
+                            },
+                            match recording.summary {
+                                Some(ref summary) => format!(", {}", summary_string(summary)),
+                                None => String::new(),
+                            }
+                        );
+                    }
+                }
+            }
+        }
+        Command::Convert {
+            names,
+            formats,
+            variant,
+        } => {
+            let mut options = convert_options(args);
+            if !formats.is_empty() {
+                options.formats = formats.clone();
+            }
+            options.variant = variant.clone();
+            recordings::check_variant(&options.variant)?;
+            let all_recordings = read_recordings(data_directory)?;
+            let names = if names.is_empty() {
+                all_recordings
+                    .iter()
+                    .filter(|recording| {
+                        matches!(recording.state, protocol::RecordingState::Complete { .. })
+                    })
+                    .map(|recording| recording.name.clone())
+                    .collect()
+            } else {
+                names.clone()
+            };
+            let mut failed = 0;
+            for name in names {
+                match all_recordings
+                    .binary_search_by(|recording| recording.name.as_str().cmp(&name))
+                    .map(|index| &all_recordings[index].state)
+                {
+                    Ok(protocol::RecordingState::Complete { zip, variants, .. }) => {
+                        if options.is_converted(*zip, variants) {
+                            println!("{}: already converted", name);
+                            continue;
+                        }
+                    }
+                    Ok(_) => {
+                        failed += 1;
+                        println!("{}: the recording is not complete", name);
+                        continue;
+                    }
+                    Err(_) => {
+                        failed += 1;
+                        println!("{}: the recording does not exist", name);
+                        continue;
+                    }
+                }
+                let begin = std::time::Instant::now();
+                match recordings::convert(
+                    data_directory,
+                    &name,
+                    &options,
+                    std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+                    std::sync::Arc::new(recordings::Progress::default()),
+                ) {
+                    Ok(()) => {
+                        println!(
+                            "{}: converted in {:.1} s",
+                            name,
+                            begin.elapsed().as_secs_f64()
+                        );
+                    }
+                    Err(error) => {
+                        failed += 1;
+                        println!("{}: {}", name, error);
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} recording(s) could not be converted",
+                    failed
+                ));
+            }
+        }
+        Command::Verify { names } => {
+            let mut invalid = 0;
+            for name in recordings_names(data_directory, names)? {
+                let problems = integrity::verify(data_directory, &name);
+                if problems.is_empty() {
+                    println!("{}: ok", name);
+                } else {
+                    invalid += 1;
+                    for problem in problems {
+                        println!("{}: {}", name, problem);
+                    }
+                }
+            }
+            if invalid > 0 {
+                return Err(anyhow::anyhow!("{} invalid recording(s)", invalid));
+            }
+        }
+        Command::Repair { names } => {
+            let mut failed = 0;
+            for name in recordings_names(data_directory, names)? {
+                match integrity::repair(data_directory, &name) {
+                    Ok(changes) => {
+                        if changes.is_empty() {
+                            println!("{}: nothing to repair", name);
+                        }
+                        for change in changes {
+                            println!("{}: {}", name, change);
+                        }
+                    }
+                    Err(error) => {
+                        failed += 1;
+                        println!("{}: {}", name, error);
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} recording(s) could not be repaired",
+                    failed
+                ));
+            }
+        }
+        Command::Statistics { names } => {
+            let all_recordings = read_recordings(data_directory)?;
+            let names = if names.is_empty() {
+                all_recordings
+                    .iter()
+                    .filter(|recording| {
+                        matches!(recording.state, protocol::RecordingState::Complete { .. })
+                    })
+                    .map(|recording| recording.name.clone())
+                    .collect()
+            } else {
+                names.clone()
+            };
+            let mut failed = 0;
+            for name in names {
+                match all_recordings
+                    .binary_search_by(|recording| recording.name.as_str().cmp(&name))
+                    .map(|index| &all_recordings[index].state)
+                {
+                    Ok(protocol::RecordingState::Complete { .. }) => {}
+                    Ok(_) => {
+                        failed += 1;
+                        println!("{}: the recording is not complete", name);
+                        continue;
+                    }
+                    Err(_) => {
+                        failed += 1;
+                        println!("{}: the recording does not exist", name);
+                        continue;
+                    }
+                }
+                match statistics::compute(data_directory, &name, &recordings::Progress::default())
+                    .and_then(|statistics| {
+                        statistics::write(data_directory, &name, &statistics)?;
+                        Ok(statistics)
+                    }) {
+                    Ok(statistics) => {
+                        println!("{}: {}", name, summary_string(&statistics.summary()));
+                    }
+                    Err(error) => {
+                        failed += 1;
+                        println!("{}: {}", name, error);
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow::anyhow!(
+                    "the statistics of {} recording(s) could not be computed",
+                    failed
+                ));
+            }
+        }
+        Command::Trim {
+            name,
+            begin,
+            end,
+            output,
+        } => {
+            let range = match (begin.parse::<u64>(), end.parse::<u64>()) {
+                (Ok(begin_t), Ok(end_t)) => protocol::TrimRange::Timestamp { begin_t, end_t },
+                _ => protocol::TrimRange::Utc {
+                    begin: begin.clone(),
+                    end: end.clone(),
+                },
+            };
+            let new_name = trim::trim(data_directory, name, &range, output)?;
+            println!("{}: created \"{}\"", name, new_name);
+        }
+    }
+    Ok(())
 }
 
 #[derive(Clone)]
@@ -86,6 +531,8 @@ type Router = std::collections::HashMap<
 
 struct Context {
     time_reference: std::time::Instant,
+    recording_options: device::RecordingOptions,
+    convert_options: protocol::ConvertOptions,
     host_to_endpoint: std::collections::HashMap<String, Endpoint>,
     next_transport_port: u16,
     maximum_client_buffer_count: usize,
@@ -99,7 +546,17 @@ struct Context {
     sample_stack: std::sync::Arc<std::sync::Mutex<stack::Stack>>,
     record_state_stack: std::sync::Arc<std::sync::Mutex<stack::Stack>>,
     notify_convert: std::sync::Arc<tokio::sync::Notify>,
-    notify_convert_cancel: std::sync::Arc<tokio::sync::Notify>,
+    // cancellation flags of the recordings being converted
+    convert_cancellations:
+        std::collections::HashMap<String, std::sync::Arc<std::sync::atomic::AtomicBool>>,
+    notify_statistics: std::sync::Arc<tokio::sync::Notify>,
+    // recordings whose statistics must be (re)computed, before the recordings without statistics
+    statistics_requests: std::collections::VecDeque<String>,
+    // recording whose statistics are being computed by the background task
+    statistics_recording: Option<String>,
+    // recordings being deleted, renamed, moved, or trimmed (and the names being created)
+    busy_recordings: std::collections::HashSet<String>,
+    durability: protocol::Durability,
 }
 
 impl Context {
@@ -141,17 +598,21 @@ impl Context {
                     speed: device.properties.speed.to_string(),
                     bus_number: device.properties.bus_number,
                     address: device.properties.address,
-                    streams: match *device.inner {
-                        neuromorphic_drivers::Device::InivationDavis346(_) => Vec::new(),
-                        neuromorphic_drivers::Device::InivationDvxplorer(_) => Vec::new(),
-                        neuromorphic_drivers::Device::PropheseeEvk3Hd(_) => {
+                    streams: match device.inner.as_ref() {
+                        device::Inner::Usb(neuromorphic_drivers::Device::InivationDavis346(_)) => {
+                            Vec::new()
+                        }
+                        device::Inner::Usb(neuromorphic_drivers::Device::InivationDvxplorer(_)) => {
+                            Vec::new()
+                        }
+                        device::Inner::Usb(neuromorphic_drivers::Device::PropheseeEvk3Hd(_)) => {
                             use neuromorphic_drivers::devices::prophesee_evk3_hd;
                             vec![protocol::Stream::Evt3 {
                                 width: prophesee_evk3_hd::PROPERTIES.width,
                                 height: prophesee_evk3_hd::PROPERTIES.height,
                             }]
                         }
-                        neuromorphic_drivers::Device::PropheseeEvk4(_) => {
+                        device::Inner::Usb(neuromorphic_drivers::Device::PropheseeEvk4(_)) => {
                             use neuromorphic_drivers::devices::prophesee_evk4;
                             vec![
                                 protocol::Stream::Evt3 {
@@ -161,11 +622,27 @@ impl Context {
                                 protocol::Stream::Evk4Samples,
                             ]
                         }
+                        device::Inner::Replay(replay) => vec![
+                            protocol::Stream::Evt3 {
+                                width: replay.width,
+                                height: replay.height,
+                            },
+                            protocol::Stream::Evk4Samples,
+                        ],
+                        device::Inner::Synthetic(synthetic) => vec![
+                            protocol::Stream::Evt3 {
+                                width: synthetic.parameters.width,
+                                height: synthetic.parameters.height,
+                            },
+                            protocol::Stream::Evk4Samples,
+                        ],
                     },
                     configuration: device.inner.current_configuration(),
                     lookback: record_configuration_guard.lookback,
                     autostop: record_configuration_guard.autostop,
                     autotrigger: record_configuration_guard.autotrigger,
+                    durability: record_configuration_guard.durability,
+                    segmenting: record_configuration_guard.segmenting,
                 }
             })
             .collect();
@@ -178,12 +655,52 @@ impl Context {
             );
         }
     }
+
+    fn insert_device(
+        &mut self,
+        context: &std::sync::Arc<tokio::sync::Mutex<Context>>,
+        device: device::Device,
+        device_sampler: device::DeviceSampler,
+        device_proxy: device::DeviceProxy,
+    ) {
+        // devices start with the server's durability policy, clients may override it per device
+        device_proxy
+            .record_configuration
+            .lock()
+            .expect("record configuration mutex is poisoned")
+            .durability = self.durability;
+        {
+            let mut router_guard = self.router.write().expect("router mutex is poisoned");
+            router_guard.insert(device::StreamId::new(device_proxy.id, 0), Vec::new());
+            router_guard.insert(device::StreamId::new(device_proxy.id, 1), Vec::new());
+        }
+        {
+            let context = context.clone();
+            tokio::task::spawn_blocking(move || {
+                device.run(context);
+            });
+        }
+        {
+            let context = context.clone();
+            tokio::task::spawn_blocking(move || {
+                device_sampler.run(context);
+            });
+        }
+        self.id_to_device.insert(device_proxy.id, device_proxy);
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     let time_reference = std::time::Instant::now();
+    let time_reference_system_time = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
     let args = Args::parse();
+    if let Some(command) = args.command.as_ref() {
+        return run_command(&args, command);
+    }
     println!(
         "{} | Listening for HTTP requests on port {}",
         now_utc_string(),
@@ -194,24 +711,52 @@ async fn main() -> Result<(), anyhow::Error> {
             .await?;
 
     let mut errors = Vec::new();
-    if let Err(error) = recordings::process_write_files(
+    match recordings::process_write_files(
         &args
             .data_directory
             .join(recordings::RECORDINGS_DIRECTORY_NAME),
         recordings::Action::Rename,
     ) {
-        errors.push(format!(
-            "Renaming partial recordings from {} raised an error: {}",
-            args.data_directory.to_string_lossy(),
-            error
-        ));
+        Ok(file_names) => {
+            // partial recordings may end with torn records (interrupted writes)
+            // they are only verified here since repairing truncates files (see the repair command)
+            let mut names = file_names
+                .iter()
+                .filter_map(|file_name| recordings::recording_name(file_name))
+                .collect::<Vec<_>>();
+            names.sort();
+            names.dedup();
+            for name in names {
+                for problem in integrity::verify(&args.data_directory, name) {
+                    println!(
+                        "{} | the partial recording \"{}\" is invalid ({}), use the repair command to fix it",
+                        now_utc_string(),
+                        name,
+                        problem
+                    );
+                }
+            }
+        }
+        Err(error) => {
+            errors.push(format!(
+                "Renaming partial recordings from {} raised an error: {}",
+                args.data_directory.to_string_lossy(),
+                error
+            ));
+        }
     }
     if let Err(error) = recordings::process_write_files(
         &args
             .data_directory
             .join(recordings::CONVERTED_RECORDINGS_DIRECTORY_NAME),
         recordings::Action::Delete,
-    ) {
+    )
+    .and_then(|_| {
+        for (_, directory) in recordings::variants_directories(&args.data_directory)? {
+            recordings::process_write_files(&directory, recordings::Action::Delete)?;
+        }
+        Ok(())
+    }) {
         errors.push(format!(
             "Deleting partially converted recordings from {} raised an error: {}",
             args.data_directory.to_string_lossy(),
@@ -226,13 +771,27 @@ async fn main() -> Result<(), anyhow::Error> {
             error
         ));
     });
+    let mut trash = Vec::new();
+    manage::read_trash(&args.data_directory, &mut trash, |error| {
+        errors.push(format!(
+            "Reading the trash of {} raised an error: {}",
+            args.data_directory.to_string_lossy(),
+            error
+        ));
+    });
 
     let notify_convert = std::sync::Arc::new(tokio::sync::Notify::new());
-    let notify_convert_cancel = std::sync::Arc::new(tokio::sync::Notify::new());
     let notify_recordings_changed = std::sync::Arc::new(tokio::sync::Notify::new());
+    let notify_statistics = std::sync::Arc::new(tokio::sync::Notify::new());
 
     let context = std::sync::Arc::new(tokio::sync::Mutex::new(Context {
         time_reference,
+        recording_options: device::RecordingOptions {
+            time_reference_system_time,
+            packet_checksums: args.packet_checksums,
+            raw_compression: args.raw_compression,
+        },
+        convert_options: convert_options(&args),
         host_to_endpoint: std::collections::HashMap::new(),
         next_transport_port: args.transport_port,
         maximum_client_buffer_count: args.maximum_client_buffer_count,
@@ -246,7 +805,13 @@ async fn main() -> Result<(), anyhow::Error> {
         },
         shared_recordings_state: protocol::SharedRecordingsState {
             data_directory: args.data_directory.to_string_lossy().to_string(),
+            move_directories: args
+                .move_directory
+                .iter()
+                .map(|move_directory| move_directory.to_string_lossy().to_string())
+                .collect(),
             recordings,
+            trash,
         },
         id_to_device: std::collections::HashMap::new(),
         router: std::sync::Arc::new(std::sync::RwLock::new(std::collections::HashMap::from([(
@@ -269,13 +834,71 @@ async fn main() -> Result<(), anyhow::Error> {
             constants::STACK_MINIMUM_SAMPLES,
         ))),
         notify_convert: notify_convert.clone(),
-        notify_convert_cancel: notify_convert_cancel.clone(),
+        convert_cancellations: std::collections::HashMap::new(),
+        notify_statistics: notify_statistics.clone(),
+        statistics_requests: std::collections::VecDeque::new(),
+        statistics_recording: None,
+        busy_recordings: std::collections::HashSet::new(),
+        durability: protocol::Durability {
+            period_ms: args.durability_period_ms,
+            size_bytes: args.durability_size_mb * 1_000_000,
+        },
     }));
 
+    // create virtual devices (replays and synthetic camera)
+    let mut next_device_id = 0;
+    if !args.replay.is_empty() || args.synthetic {
+        let mut context_guard = context.lock().await;
+        for name in args.replay.iter() {
+            match replay::Replay::open(&args.data_directory, name, args.replay_speed) {
+                Ok(replay) => {
+                    let (device, device_sampler, device_proxy) =
+                        device::create_replay_device_and_proxies(
+                            device::DeviceId(next_device_id),
+                            replay,
+                        );
+                    next_device_id += 1;
+                    context_guard.insert_device(&context, device, device_sampler, device_proxy);
+                }
+                Err(error) => {
+                    context_guard
+                        .shared_client_state
+                        .errors
+                        .push(format!("Opening the replay \"{}\" failed ({})", name, error));
+                }
+            }
+        }
+        if args.synthetic {
+            match synthetic::Synthetic::new(synthetic::Parameters {
+                width: args.synthetic_width,
+                height: args.synthetic_height,
+                event_rate: args.synthetic_event_rate,
+                patterns: args.synthetic_patterns.clone(),
+                trigger_period_us: args.synthetic_trigger_period_us,
+            }) {
+                Ok(synthetic) => {
+                    let (device, device_sampler, device_proxy) =
+                        device::create_synthetic_device_and_proxies(
+                            device::DeviceId(next_device_id),
+                            synthetic,
+                        );
+                    next_device_id += 1;
+                    context_guard.insert_device(&context, device, device_sampler, device_proxy);
+                }
+                Err(error) => {
+                    context_guard
+                        .shared_client_state
+                        .errors
+                        .push(format!("Creating the synthetic camera failed ({})", error));
+                }
+            }
+        }
+        context_guard.update_shared_client_state_devices();
+    }
+
     {
         let context = context.clone();
         tokio::task::spawn_blocking(move || {
-            let mut next_device_id = 0;
             loop {
                 if let Ok(listed_devices) = neuromorphic_drivers::list_devices() {
                     let new_listed_devices: Vec<_> = {
@@ -286,7 +909,11 @@ async fn main() -> Result<(), anyhow::Error> {
                                 device.serial.is_ok()
                                     && context_guard.id_to_device.iter().all(
                                         |(_, listed_device)| {
-                                            device.bus_number != listed_device.properties.bus_number
+                                            !matches!(
+                                                listed_device.inner.as_ref(),
+                                                device::Inner::Usb(_)
+                                            ) || device.bus_number
+                                                != listed_device.properties.bus_number
                                                 || device.address
                                                     != listed_device.properties.address
                                         },
@@ -315,31 +942,12 @@ async fn main() -> Result<(), anyhow::Error> {
                         }
                         let mut context_guard = context.blocking_lock();
                         for (device, device_sampler, device_proxy) in devices_and_proxies {
-                            {
-                                let mut router_guard = context_guard
-                                    .router
-                                    .write()
-                                    .expect("router mutex is poisoned");
-                                router_guard
-                                    .insert(device::StreamId::new(device_proxy.id, 0), Vec::new());
-                                router_guard
-                                    .insert(device::StreamId::new(device_proxy.id, 1), Vec::new());
-                            }
-                            {
-                                let context = context.clone();
-                                tokio::task::spawn_blocking(move || {
-                                    device.run(context);
-                                });
-                            }
-                            {
-                                let context = context.clone();
-                                tokio::task::spawn_blocking(move || {
-                                    device_sampler.run(context);
-                                });
-                            }
-                            context_guard
-                                .id_to_device
-                                .insert(device_proxy.id, device_proxy);
+                            context_guard.insert_device(
+                                &context,
+                                device,
+                                device_sampler,
+                                device_proxy,
+                            );
                         }
                         context_guard.update_shared_client_state_devices();
                     }
@@ -475,6 +1083,7 @@ async fn main() -> Result<(), anyhow::Error> {
         let notify_recordings_changed = notify_recordings_changed.clone();
         tokio::spawn(async move {
             let mut recordings = Vec::new();
+            let mut trash = Vec::new();
             let mut errors = Vec::new();
             loop {
                 let force_send = tokio::select! {
@@ -486,6 +1095,7 @@ async fn main() -> Result<(), anyhow::Error> {
                     },
                 };
                 recordings.clear();
+                trash.clear();
                 errors.clear();
                 let data_directory = context
                     .lock()
@@ -504,9 +1114,50 @@ async fn main() -> Result<(), anyhow::Error> {
                         ));
                     },
                 );
+                manage::read_trash(std::path::Path::new(&data_directory), &mut trash, |error| {
+                    errors.push(format!(
+                        "Reading the trash of {} raised an error: {}",
+                        args.data_directory.to_string_lossy(),
+                        error
+                    ));
+                });
                 {
                     let mut context_guard = context.lock().await;
                     if context_guard.shared_recordings_state.data_directory == data_directory {
+                        // recordings that became complete since the previous scan (finalized,
+                        // repaired, or trimmed) get statistics, the others only on request
+                        if args.statistics {
+                            let previous_recordings =
+                                &context_guard.shared_recordings_state.recordings;
+                            let completed = recordings
+                                .iter()
+                                .filter(|recording| {
+                                    recording.summary.is_none()
+                                        && matches!(
+                                            recording.state,
+                                            protocol::RecordingState::Complete { .. }
+                                        )
+                                        && match previous_recordings.binary_search_by(
+                                            |previous_recording| {
+                                                previous_recording.name.cmp(&recording.name)
+                                            },
+                                        ) {
+                                            Ok(index) => matches!(
+                                                previous_recordings[index].state,
+                                                protocol::RecordingState::Ongoing
+                                                    | protocol::RecordingState::Incomplete { .. }
+                                            ),
+                                            Err(_) => true,
+                                        }
+                                })
+                                .map(|recording| recording.name.clone())
+                                .collect::<Vec<_>>();
+                            for name in completed {
+                                if !context_guard.statistics_requests.contains(&name) {
+                                    context_guard.statistics_requests.push_back(name);
+                                }
+                            }
+                        }
                         let mut new_recording_index = 0;
                         let mut recording_index = 0;
                         while new_recording_index < recordings.len()
@@ -521,25 +1172,44 @@ async fn main() -> Result<(), anyhow::Error> {
                                     new_recording_index += 1;
                                 }
                                 std::cmp::Ordering::Equal => {
-                                    if let protocol::RecordingState::Complete { size_bytes, zip } =
-                                        recordings[new_recording_index].state
+                                    if let protocol::RecordingState::Complete {
+                                        size_bytes,
+                                        zip,
+                                        ref mut variants,
+                                    } = recordings[new_recording_index].state
                                     {
                                         match context_guard.shared_recordings_state.recordings
                                             [recording_index]
                                             .state
                                         {
-                                            protocol::RecordingState::Queued { .. } => {
+                                            protocol::RecordingState::Queued {
+                                                ref options,
+                                                ..
+                                            } => {
                                                 recordings[new_recording_index].state =
                                                     protocol::RecordingState::Queued {
                                                         size_bytes,
                                                         zip,
+                                                        variants: std::mem::take(variants),
+                                                        options: options.clone(),
                                                     };
                                             }
-                                            protocol::RecordingState::Converting { .. } => {
+                                            protocol::RecordingState::Converting {
+                                                ref options,
+                                                processed_bytes,
+                                                total_bytes,
+                                                remaining_ms,
+                                                ..
+                                            } => {
                                                 recordings[new_recording_index].state =
                                                     protocol::RecordingState::Converting {
                                                         size_bytes,
                                                         zip,
+                                                        variants: std::mem::take(variants),
+                                                        options: options.clone(),
+                                                        processed_bytes,
+                                                        total_bytes,
+                                                        remaining_ms,
                                                     };
                                             }
                                             _ => {}
@@ -559,10 +1229,20 @@ async fn main() -> Result<(), anyhow::Error> {
                                 &mut context_guard.shared_recordings_state.recordings,
                                 &mut recordings,
                             );
+                            context_guard.notify_statistics.notify_one();
                             true
                         } else {
                             force_send
                         };
+                        let send = if trash != context_guard.shared_recordings_state.trash {
+                            std::mem::swap(
+                                &mut context_guard.shared_recordings_state.trash,
+                                &mut trash,
+                            );
+                            true
+                        } else {
+                            send
+                        };
                         if send {
                             if let Err(error) = context_guard.broadcast_shared_recordings_state() {
                                 println!(
@@ -577,7 +1257,12 @@ async fn main() -> Result<(), anyhow::Error> {
                             &mut context_guard.shared_recordings_state.recordings,
                             &mut recordings,
                         );
+                        std::mem::swap(
+                            &mut context_guard.shared_recordings_state.trash,
+                            &mut trash,
+                        );
                         context_guard.shared_recordings_state.data_directory = data_directory;
+                        context_guard.notify_statistics.notify_one();
                         if let Err(error) = context_guard.broadcast_shared_recordings_state() {
                             println!(
                                 "{} | broadcast_shared_recordings_state error: {:?}",
@@ -604,30 +1289,171 @@ async fn main() -> Result<(), anyhow::Error> {
         });
     }
 
-    // convert files when requested
-    {
+    // compute the statistics of finalized recordings and requested recordings (one at a time)
+    if args.statistics {
+        let context = context.clone();
+        let notify_statistics = notify_statistics.clone();
+        let notify_recordings_changed = notify_recordings_changed.clone();
+        notify_statistics.notify_one();
+        tokio::spawn(async move {
+            loop {
+                let data_directory_and_name = {
+                    let mut context_guard = context.lock().await;
+                    let data_directory =
+                        context_guard.shared_recordings_state.data_directory.clone();
+                    let mut name = None;
+                    let mut index = 0;
+                    while index < context_guard.statistics_requests.len() {
+                        // busy recordings keep their request until they are released
+                        if context_guard
+                            .busy_recordings
+                            .contains(&context_guard.statistics_requests[index])
+                        {
+                            index += 1;
+                            continue;
+                        }
+                        let requested_name = context_guard
+                            .statistics_requests
+                            .remove(index)
+                            .expect("index is smaller than the number of requests");
+                        if context_guard
+                            .shared_recordings_state
+                            .recordings
+                            .binary_search_by(|recording| {
+                                recording.name.as_str().cmp(&requested_name)
+                            })
+                            .is_ok_and(|index| {
+                                matches!(
+                                    context_guard.shared_recordings_state.recordings[index].state,
+                                    protocol::RecordingState::Complete { .. }
+                                )
+                            })
+                        {
+                            name = Some(requested_name);
+                            break;
+                        }
+                    }
+                    context_guard.statistics_recording = name.clone();
+                    name.map(|name| (data_directory, name))
+                };
+                if let Some((data_directory, name)) = data_directory_and_name {
+                    let result = {
+                        let data_directory = data_directory.clone();
+                        let name = name.clone();
+                        tokio::task::spawn_blocking(move || {
+                            let data_directory = std::path::Path::new(&data_directory);
+                            let statistics = statistics::compute(
+                                data_directory,
+                                &name,
+                                &recordings::Progress::default(),
+                            )?;
+                            statistics::write(data_directory, &name, &statistics)?;
+                            Ok::<_, anyhow::Error>(statistics)
+                        })
+                        .await
+                    };
+                    let error = match result {
+                        Ok(Ok(statistics)) => {
+                            println!(
+                                "{} | computed the statistics of \"{}\"",
+                                now_utc_string(),
+                                name
+                            );
+                            // the summary is updated now so that the next iteration skips it
+                            let mut context_guard = context.lock().await;
+                            context_guard.statistics_recording = None;
+                            if data_directory
+                                == context_guard.shared_recordings_state.data_directory
+                            {
+                                if let Ok(index) = context_guard
+                                    .shared_recordings_state
+                                    .recordings
+                                    .binary_search_by(|recording| {
+                                        recording.name.as_str().cmp(&name)
+                                    })
+                                {
+                                    context_guard.shared_recordings_state.recordings[index]
+                                        .summary = Some(statistics.summary());
+                                }
+                            }
+                            notify_recordings_changed.notify_one();
+                            continue;
+                        }
+                        Ok(Err(error)) => error,
+                        Err(error) => error.into(),
+                    };
+                    let mut context_guard = context.lock().await;
+                    context_guard.statistics_recording = None;
+                    context_guard.shared_client_state.errors.push(format!(
+                        "Computing the statistics of \"{}\" in \"{}\" failed: {}",
+                        name, data_directory, error
+                    ));
+                    if let Err(error) = context_guard.broadcast_shared_client_state() {
+                        println!(
+                            "{} | broadcast_shared_client_state error: {:?}",
+                            now_utc_string(),
+                            error
+                        );
+                    }
+                } else {
+                    notify_statistics.notified().await;
+                }
+            }
+        });
+    }
+
+    // convert files when requested (each worker converts one recording at a time)
+    let convert_workers = if args.convert_workers == 0 {
+        std::thread::available_parallelism().map_or(1, |parallelism| parallelism.get())
+    } else {
+        args.convert_workers
+    };
+    for _ in 0..convert_workers {
         let context = context.clone();
+        let notify_convert = notify_convert.clone();
+        let notify_recordings_changed = notify_recordings_changed.clone();
         tokio::spawn(async move {
             let mut has_work = false;
             loop {
                 if has_work {
-                    let data_directory_and_name: Option<(String, String)> = {
-                        let mut name: Option<String> = None;
+                    let data_directory_name_options_and_cancelled: Option<(
+                        String,
+                        String,
+                        protocol::ConvertOptions,
+                        std::sync::Arc<std::sync::atomic::AtomicBool>,
+                    )> = {
+                        let mut name_and_options: Option<(String, protocol::ConvertOptions)> = None;
                         let mut context_guard = context.lock().await;
                         let mut changed = false;
                         for recording in context_guard.shared_recordings_state.recordings.iter_mut()
                         {
-                            if let protocol::RecordingState::Queued { size_bytes, zip } =
-                                recording.state
+                            if let protocol::RecordingState::Queued {
+                                size_bytes,
+                                zip,
+                                ref mut variants,
+                                ref options,
+                            } = recording.state
                             {
                                 changed = true;
-                                if zip {
-                                    recording.state =
-                                        protocol::RecordingState::Complete { size_bytes, zip };
+                                let options = options.clone();
+                                let variants = std::mem::take(variants);
+                                if options.is_converted(zip, &variants) {
+                                    recording.state = protocol::RecordingState::Complete {
+                                        size_bytes,
+                                        zip,
+                                        variants,
+                                    };
                                 } else {
-                                    recording.state =
-                                        protocol::RecordingState::Converting { size_bytes, zip };
-                                    name = Some(recording.name.clone());
+                                    recording.state = protocol::RecordingState::Converting {
+                                        size_bytes,
+                                        zip,
+                                        variants,
+                                        options: options.clone(),
+                                        processed_bytes: 0,
+                                        total_bytes: 0,
+                                        remaining_ms: None,
+                                    };
+                                    name_and_options = Some((recording.name.clone(), options));
                                     break;
                                 }
                             }
@@ -641,37 +1467,104 @@ async fn main() -> Result<(), anyhow::Error> {
                                 );
                             }
                         }
-                        name.map(|name| {
+                        name_and_options.map(|(name, options)| {
+                            // other recordings may be queued, another worker checks the queue
+                            notify_convert.notify_one();
+                            let cancelled =
+                                std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+                            context_guard
+                                .convert_cancellations
+                                .insert(name.clone(), cancelled.clone());
                             (
                                 context_guard.shared_recordings_state.data_directory.clone(),
                                 name,
+                                options,
+                                cancelled,
                             )
                         })
                     };
-                    if let Some((data_directory, name)) = data_directory_and_name {
-                        let cancelled =
-                            std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+                    if let Some((data_directory, name, options, cancelled)) =
+                        data_directory_name_options_and_cancelled
+                    {
+                        let progress = std::sync::Arc::new(recordings::Progress::default());
+                        let begin = std::time::Instant::now();
+                        let mut progress_interval =
+                            tokio::time::interval(constants::CONVERT_PROGRESS_PERIOD);
                         let mut join_handle = {
                             let data_directory = data_directory.clone();
                             let name = name.clone();
                             let cancelled = cancelled.clone();
+                            let progress = progress.clone();
                             tokio::task::spawn_blocking(move || {
-                                recordings::convert(&data_directory.into(), &name, cancelled)
+                                recordings::convert(
+                                    &data_directory.into(),
+                                    &name,
+                                    &options,
+                                    cancelled,
+                                    progress,
+                                )
                             })
                         };
                         loop {
                             tokio::select! {
-                                _ = notify_convert_cancel.notified() => {
-                                    cancelled.store(true, std::sync::atomic::Ordering::Release);
+                                _ = progress_interval.tick() => {
+                                    let mut context_guard = context.lock().await;
+                                    if data_directory != context_guard.shared_recordings_state.data_directory {
+                                        continue;
+                                    }
+                                    let mut changed = false;
+                                    if let Ok(index) = context_guard
+                                        .shared_recordings_state
+                                        .recordings
+                                        .binary_search_by(|recording| recording.name.as_str().cmp(&name))
+                                    {
+                                        if let protocol::RecordingState::Converting {
+                                            ref mut processed_bytes,
+                                            ref mut total_bytes,
+                                            ref mut remaining_ms,
+                                            ..
+                                        } = context_guard.shared_recordings_state.recordings[index].state
+                                        {
+                                            let new_processed_bytes = progress.processed_bytes();
+                                            let new_total_bytes = progress.total_bytes();
+                                            let new_remaining_ms = progress
+                                                .remaining(begin.elapsed())
+                                                .map(|remaining| remaining.as_millis() as u64);
+                                            changed = *processed_bytes != new_processed_bytes
+                                                || *total_bytes != new_total_bytes;
+                                            *processed_bytes = new_processed_bytes;
+                                            *total_bytes = new_total_bytes;
+                                            *remaining_ms = new_remaining_ms;
+                                        }
+                                    }
+                                    if changed {
+                                        if let Err(error) = context_guard.broadcast_shared_recordings_state() {
+                                            println!(
+                                                "{} | broadcast_shared_recordings_state error: {:?}",
+                                                now_utc_string(),
+                                                error
+                                            );
+                                        }
+                                    }
                                 }
                                 result = &mut join_handle => {
                                     let mut context_guard = context.lock().await;
+                                    context_guard.convert_cancellations.remove(&name);
                                     if data_directory == context_guard.shared_recordings_state.data_directory {
-                                        for recording in context_guard.shared_recordings_state.recordings.iter_mut() {
-                                            if let protocol::RecordingState::Converting { size_bytes, zip } =
+                                        if let Ok(index) = context_guard
+                                            .shared_recordings_state
+                                            .recordings
+                                            .binary_search_by(|recording| recording.name.as_str().cmp(&name))
+                                        {
+                                            let recording = &mut context_guard.shared_recordings_state.recordings[index];
+                                            if let protocol::RecordingState::Converting { size_bytes, zip, ref mut variants, .. } =
                                                 recording.state
                                             {
-                                                recording.state = protocol::RecordingState::Complete { size_bytes, zip };
+                                                recording.state = protocol::RecordingState::Complete {
+                                                    size_bytes,
+                                                    zip,
+                                                    variants: std::mem::take(variants),
+                                                };
                                             }
                                         }
                                     }
@@ -701,12 +1594,8 @@ async fn main() -> Result<(), anyhow::Error> {
                         has_work = false;
                     }
                 } else {
-                    tokio::select! {
-                        _ = notify_convert.notified() => {
-                            has_work = true;
-                        }
-                        _ = notify_convert_cancel.notified() => {}
-                    }
+                    notify_convert.notified().await;
+                    has_work = true;
                 }
             }
         });
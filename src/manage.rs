@@ -0,0 +1,320 @@
+// Delete, rename, and move recordings. Operations move all the files of a recording (recording
+// files, statistics, and conversions) or none of them: destinations are checked first, and the
+// files already moved are moved back if a file cannot be moved.
+// Deleted recordings may be moved to the trash (DATA/trash/ENTRY, with the data directory's
+// layout), from which they can be restored.
+use crate::protocol;
+use crate::recordings;
+use crate::statistics;
+
+pub const TRASH_DIRECTORY_NAME: &str = "trash";
+
+// path of a recording file relative to the data directory (DIRECTORY/NAME.EXTENSION)
+struct RecordingFile {
+    directory: std::path::PathBuf,
+    extension: &'static str,
+}
+
+impl RecordingFile {
+    fn path(&self, data_directory: &std::path::Path, name: &str) -> std::path::PathBuf {
+        data_directory
+            .join(&self.directory)
+            .join(format!("{}{}", name, self.extension))
+    }
+}
+
+pub fn check_name(name: &str) -> Result<(), anyhow::Error> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name
+            .chars()
+            .any(|character| character.is_control() || "/\\".contains(character))
+    {
+        return Err(anyhow::anyhow!(
+            "the recording name \"{}\" is invalid (the name cannot be empty, start with ., or contain / or \\)",
+            name
+        ));
+    }
+    Ok(())
+}
+
+// Returns the files of a complete recording. Ongoing recordings (.write files) and recordings
+// being converted are refused.
+fn recording_files(
+    data_directory: &std::path::Path,
+    name: &str,
+) -> Result<Vec<RecordingFile>, anyhow::Error> {
+    check_name(name)?;
+    let mut files = Vec::new();
+    let mut directories = vec![
+        (
+            std::path::PathBuf::from(recordings::RECORDINGS_DIRECTORY_NAME),
+            recordings::RECORDING_FILES_EXTENSIONS
+                .iter()
+                .copied()
+                .chain(std::iter::once(statistics::FILE_EXTENSION))
+                .collect::<Vec<_>>(),
+        ),
+        (
+            std::path::PathBuf::from(recordings::CONVERTED_RECORDINGS_DIRECTORY_NAME),
+            vec![recordings::ZIP_FILE_EXTENSION],
+        ),
+    ];
+    for (variant, _) in recordings::variants_directories(data_directory)? {
+        directories.push((
+            std::path::PathBuf::from(recordings::CONVERTED_RECORDINGS_DIRECTORY_NAME).join(variant),
+            vec![recordings::ZIP_FILE_EXTENSION],
+        ));
+    }
+    for (directory, extensions) in directories {
+        for extension in extensions {
+            let file = RecordingFile {
+                directory: directory.clone(),
+                extension,
+            };
+            let path = file.path(data_directory, name);
+            let mut write_path = path.clone().into_os_string();
+            write_path.push(".write");
+            if std::path::Path::new(&write_path).exists() {
+                return Err(anyhow::anyhow!(
+                    "\"{}\" is being written (ongoing recording or conversion)",
+                    name
+                ));
+            }
+            if path.is_file() {
+                files.push(file);
+            }
+        }
+    }
+    if !files.iter().any(|file| {
+        file.directory.as_os_str() == recordings::RECORDINGS_DIRECTORY_NAME
+            && file.extension == recordings::RAW_FILE_EXTENSION
+    }) {
+        return Err(anyhow::anyhow!("the recording \"{}\" does not exist", name));
+    }
+    Ok(files)
+}
+
+// Returns true if the file was copied (the target is on another file system).
+fn move_file(source: &std::path::Path, target: &std::path::Path) -> Result<bool, anyhow::Error> {
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::rename(source, target) {
+        Ok(()) => Ok(false),
+        Err(error) if error.kind() == std::io::ErrorKind::CrossesDevices => {
+            // the copy is hidden until it is complete (hidden files are ignored by the scans)
+            let copy_path = target.with_file_name(format!(
+                ".{}",
+                target.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            if let Err(error) = std::fs::copy(source, &copy_path)
+                .and_then(|_| std::fs::File::open(&copy_path)?.sync_all())
+                .and_then(|_| std::fs::rename(&copy_path, target))
+            {
+                let _ = std::fs::remove_file(&copy_path);
+                return Err(error.into());
+            }
+            Ok(true)
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+// Moves all the files or none of them.
+fn move_files(moves: &[(std::path::PathBuf, std::path::PathBuf)]) -> Result<(), anyhow::Error> {
+    for (_, target) in moves {
+        if target.exists() {
+            return Err(anyhow::anyhow!(
+                "\"{}\" already exists",
+                target.to_string_lossy()
+            ));
+        }
+    }
+    let mut moved = Vec::with_capacity(moves.len());
+    for (source, target) in moves {
+        match move_file(source, target) {
+            Ok(copied) => moved.push((source, target, copied)),
+            Err(error) => {
+                for (source, target, copied) in moved.into_iter().rev() {
+                    if copied {
+                        let _ = std::fs::remove_file(target);
+                    } else {
+                        let _ = std::fs::rename(target, source);
+                    }
+                }
+                return Err(error);
+            }
+        }
+    }
+    // copied files are removed once all the files are in place
+    for (source, _, copied) in moved {
+        if copied {
+            std::fs::remove_file(source)?;
+        }
+    }
+    Ok(())
+}
+
+fn move_recording(
+    data_directory: &std::path::Path,
+    name: &str,
+    new_data_directory: &std::path::Path,
+    new_name: &str,
+) -> Result<(), anyhow::Error> {
+    check_name(new_name)?;
+    let files = recording_files(data_directory, name)?;
+    if new_data_directory
+        .join(recordings::RECORDINGS_DIRECTORY_NAME)
+        .join(format!(
+            "{}{}.write",
+            new_name,
+            recordings::RAW_FILE_EXTENSION
+        ))
+        .exists()
+    {
+        return Err(anyhow::anyhow!(
+            "the recording \"{}\" already exists",
+            new_name
+        ));
+    }
+    move_files(
+        &files
+            .iter()
+            .map(|file| {
+                (
+                    file.path(data_directory, name),
+                    file.path(new_data_directory, new_name),
+                )
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+pub fn rename(
+    data_directory: &std::path::Path,
+    name: &str,
+    new_name: &str,
+) -> Result<(), anyhow::Error> {
+    if name == new_name {
+        return Ok(());
+    }
+    move_recording(data_directory, name, data_directory, new_name)
+}
+
+// Moves a recording to another data directory (the directories are created if needed).
+pub fn move_to(
+    data_directory: &std::path::Path,
+    name: &str,
+    new_data_directory: &std::path::Path,
+) -> Result<(), anyhow::Error> {
+    std::fs::create_dir_all(new_data_directory)?;
+    if new_data_directory.canonicalize()? == data_directory.canonicalize()? {
+        return Err(anyhow::anyhow!(
+            "\"{}\" is the recording's data directory",
+            new_data_directory.to_string_lossy()
+        ));
+    }
+    move_recording(data_directory, name, new_data_directory, name)
+}
+
+// Returns the trash entry if `trash` is true.
+pub fn delete(
+    data_directory: &std::path::Path,
+    name: &str,
+    trash: bool,
+) -> Result<Option<String>, anyhow::Error> {
+    if trash {
+        let trash_directory = data_directory.join(TRASH_DIRECTORY_NAME);
+        let mut entry = name.to_owned();
+        let mut suffix = 1;
+        while trash_directory.join(&entry).exists() {
+            entry = format!("{}_{}", name, suffix);
+            suffix += 1;
+        }
+        move_recording(data_directory, name, &trash_directory.join(&entry), name)?;
+        Ok(Some(entry))
+    } else {
+        // the files are moved to a hidden directory first, so that they are deleted together
+        let delete_directory = data_directory.join(format!(".{}.delete", name));
+        if delete_directory.exists() {
+            std::fs::remove_dir_all(&delete_directory)?;
+        }
+        move_recording(data_directory, name, &delete_directory, name)?;
+        std::fs::remove_dir_all(&delete_directory)?;
+        Ok(None)
+    }
+}
+
+// Returns the name of the recording stored in a trash entry.
+fn trashed_recording_name(entry_directory: &std::path::Path) -> Result<String, anyhow::Error> {
+    for entry in entry_directory
+        .join(recordings::RECORDINGS_DIRECTORY_NAME)
+        .read_dir()?
+    {
+        let file_name = entry?.file_name();
+        if let Some(name) = file_name
+            .to_string_lossy()
+            .strip_suffix(recordings::RAW_FILE_EXTENSION)
+        {
+            return Ok(name.to_owned());
+        }
+    }
+    Err(anyhow::anyhow!(
+        "\"{}\" does not contain a recording",
+        entry_directory.to_string_lossy()
+    ))
+}
+
+// Moves a trashed recording back to the data directory and returns its name.
+pub fn restore(data_directory: &std::path::Path, entry: &str) -> Result<String, anyhow::Error> {
+    check_name(entry)?;
+    let entry_directory = data_directory.join(TRASH_DIRECTORY_NAME).join(entry);
+    let name = trashed_recording_name(&entry_directory)?;
+    move_recording(&entry_directory, &name, data_directory, &name)?;
+    std::fs::remove_dir_all(&entry_directory)?;
+    Ok(name)
+}
+
+pub fn empty_trash(data_directory: &std::path::Path, entry: &str) -> Result<(), anyhow::Error> {
+    check_name(entry)?;
+    std::fs::remove_dir_all(data_directory.join(TRASH_DIRECTORY_NAME).join(entry))?;
+    Ok(())
+}
+
+pub fn read_trash<HandleError>(
+    data_directory: &std::path::Path,
+    trash: &mut Vec<protocol::TrashedRecording>,
+    mut handle_error: HandleError,
+) where
+    HandleError: FnMut(anyhow::Error),
+{
+    let trash_directory = data_directory.join(TRASH_DIRECTORY_NAME);
+    if !trash_directory.is_dir() {
+        return;
+    }
+    match trash_directory.read_dir() {
+        Ok(entries) => {
+            for entry in entries {
+                match entry {
+                    Ok(entry) => {
+                        let entry_name = entry.file_name().to_string_lossy().to_string();
+                        if entry_name.starts_with('.') {
+                            continue;
+                        }
+                        // entries without a raw file are not listed (they cannot be restored)
+                        if let Ok(name) = trashed_recording_name(&entry.path()) {
+                            trash.push(protocol::TrashedRecording {
+                                entry: entry_name,
+                                name,
+                            });
+                        }
+                    }
+                    Err(error) => handle_error(error.into()),
+                }
+            }
+        }
+        Err(error) => handle_error(error.into()),
+    }
+    trash.sort_by(|a, b| a.entry.cmp(&b.entry));
+}
@@ -0,0 +1,45 @@
+// Metavision RAW files (Prophesee) start with text header lines ("% key value")
+// followed by the EVT data, hence EVT3 packets can be copied without re-encoding.
+use std::io::Write;
+
+pub const FILE_EXTENSION: &str = ".raw";
+
+// `date` uses the format "%Y-%m-%d %H:%M:%S" and `serial` may be empty.
+pub fn write_evt3_header(
+    output: &mut impl Write,
+    width: u16,
+    height: u16,
+    serial: &str,
+    date: &str,
+) -> std::io::Result<()> {
+    output.write_all(b"% camera_integrator_name Prophesee\n")?;
+    output.write_all(format!("% date {date}\n").as_bytes())?;
+    output.write_all(b"% evt 3.0\n")?;
+    output.write_all(format!("% format EVT3;height={height};width={width}\n").as_bytes())?;
+    output.write_all(format!("% geometry {width}x{height}\n").as_bytes())?;
+    output.write_all(b"% integrator_name Prophesee\n")?;
+    if !serial.is_empty() {
+        output.write_all(format!("% serial_number {serial}\n").as_bytes())?;
+    }
+    output.write_all(b"% end\n")?;
+    Ok(())
+}
+
+// Writes the words that restore the decoder state of a packet copied from the middle of a
+// recording (time, y, and vector base x). The overflow count cannot be encoded.
+pub fn write_evt3_state(
+    output: &mut impl Write,
+    state: &neuromorphic_drivers::adapters::evt3::State,
+) -> std::io::Result<()> {
+    for word in [
+        0x8000 | (state.previous_msb_t & 0xFFF), // EVT_TIME_HIGH
+        0x6000 | (state.previous_lsb_t & 0xFFF), // EVT_TIME_LOW
+        state.y & 0x7FF,                         // EVT_ADDR_Y
+        0x3000
+            | ((matches!(state.polarity, neuromorphic_drivers::types::Polarity::On) as u16) << 11)
+            | (state.x & 0x7FF), // VECT_BASE_X
+    ] {
+        output.write_all(&word.to_le_bytes())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,272 @@
+// NumPy archives (.npz) are zip files that contain .npy arrays
+// (https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html).
+// Arrays are structured (one field per column) and can be loaded with `np.load`.
+use crate::filters;
+use crate::recordings;
+
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+
+pub const FILE_EXTENSION: &str = ".npz";
+
+const EVENTS_FIELDS: [(&str, &str); 4] = [("t", "<u8"), ("x", "<u2"), ("y", "<u2"), ("on", "|b1")];
+
+const TRIGGERS_FIELDS: [(&str, &str); 5] = [
+    ("system_time", "<u8"),
+    ("system_timestamp", "<u8"),
+    ("t", "<u8"),
+    ("id", "|u1"),
+    ("rising", "|b1"),
+];
+
+const SAMPLES_FIELDS: [(&str, &str); 4] = [
+    ("system_time", "<u8"),
+    ("system_timestamp", "<u8"),
+    ("illuminance_lux", "<f4"),
+    ("temperature_celsius", "<f4"),
+];
+
+// appended to the events and triggers fields if the utc option is set
+const UTC_FIELD: (&str, &str) = ("utc", "<u8");
+
+// first_event is the index in the events array of the packet's first event
+const INDEX_FIELDS: [(&str, &str); 5] = [
+    ("system_time", "<u8"),
+    ("system_timestamp", "<u8"),
+    ("first_after_overflow", "|b1"),
+    ("t", "<u8"),
+    ("first_event", "<u8"),
+];
+
+// Writes a version 1.0 header for a one-dimensional structured array.
+fn write_npy_header(
+    output: &mut impl Write,
+    fields: &[(&str, &str)],
+    length: u64,
+) -> std::io::Result<()> {
+    let mut header = String::from("{'descr': [");
+    for (index, (name, dtype)) in fields.iter().enumerate() {
+        if index > 0 {
+            header.push_str(", ");
+        }
+        header.push_str(&format!("('{name}', '{dtype}')"));
+    }
+    header.push_str(&format!(
+        "], 'fortran_order': False, 'shape': ({length},), }}"
+    ));
+    // magic (6 bytes), version (2 bytes), header length (2 bytes), header (padded to 64 bytes)
+    let padding = 63 - (10 + header.len()) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+    output.write_all(b"\x93NUMPY\x01\x00")?;
+    output.write_all(&(header.len() as u16).to_le_bytes())?;
+    output.write_all(header.as_bytes())?;
+    Ok(())
+}
+
+// Writes the events, triggers, samples, and index arrays of the pass's recording to `path`.
+// Returns false if the conversion was cancelled.
+pub fn write(
+    reader: &mut recordings::Reader,
+    pass: &recordings::Pass,
+    path: &std::path::Path,
+) -> Result<bool, anyhow::Error> {
+    let convert_options = pass.convert_options;
+    let selection = &pass.selection;
+    let clock = pass.clock;
+    let progress = pass.progress;
+    let mut npz = zip::ZipWriter::new(std::io::BufWriter::new(std::fs::File::create(path)?));
+    // .npy arrays are stored (not compressed) so that they can be loaded quickly
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true);
+    reader.rewind()?;
+
+    // the .npy header lists the array length, the first pass counts events and triggers
+    // (filters are deterministic, each pass uses a new chain)
+    let mut chain = filters::Chain::new(&convert_options.filters, reader.width, reader.height)?;
+    let mut events = 0u64;
+    let mut triggers = 0u64;
+    while let Some(index_entry) = reader.next_packet()? {
+        progress.add(index_entry.raw_length);
+        reader.convert(
+            |dvs_event| {
+                let t = dvs_event.t; // unpack field
+                let x = dvs_event.x; // unpack field
+                let y = dvs_event.y; // unpack field
+                let on = dvs_event.polarity as u8 == 1; // unpack field
+                if selection.contains_t(t) && chain.filter(t, x, y, on).is_some() {
+                    events += 1;
+                }
+            },
+            |trigger_event| {
+                let t = trigger_event.t; // unpack field
+                if selection.contains_t(t) {
+                    triggers += 1;
+                }
+            },
+        );
+        if pass.cancelled() {
+            return Ok(false);
+        }
+    }
+    reader.rewind()?;
+
+    // events (triggers and index are spilled)
+    let mut events_fields = EVENTS_FIELDS.to_vec();
+    let mut triggers_fields = TRIGGERS_FIELDS.to_vec();
+    if convert_options.utc {
+        events_fields.push(UTC_FIELD);
+        triggers_fields.push(UTC_FIELD);
+    }
+    npz.start_file("events.npy", options)?;
+    write_npy_header(&mut npz, &events_fields, events)?;
+    let mut events_bytes = Vec::new();
+    let mut triggers_bytes = Vec::new();
+    let mut triggers_spill = recordings::Spill::create(path, "triggers")?;
+    let mut index_spill = recordings::Spill::create(path, "index")?;
+    let mut chain = filters::Chain::new(&convert_options.filters, reader.width, reader.height)?;
+    let mut index_entries = 0u64;
+    let mut first_event = 0u64;
+    while let Some(index_entry) = reader.next_packet()? {
+        progress.add(index_entry.raw_length);
+        if convert_options.index {
+            index_spill.write_all(&index_entry.system_time.to_le_bytes())?;
+            index_spill.write_all(&index_entry.system_timestamp.to_le_bytes())?;
+            index_spill.write_all(&[index_entry.first_after_overflow as u8])?;
+            index_spill.write_all(&index_entry.state.t.to_le_bytes())?;
+            index_spill.write_all(&first_event.to_le_bytes())?;
+        }
+        index_entries += 1;
+        reader.convert(
+            |dvs_event| {
+                let t = dvs_event.t; // unpack field
+                let x = dvs_event.x; // unpack field
+                let y = dvs_event.y; // unpack field
+                let on = dvs_event.polarity as u8; // unpack field
+                if !selection.contains_t(t) {
+                    return;
+                }
+                if let Some((x, y)) = chain.filter(t, x, y, on == 1) {
+                    events_bytes.extend_from_slice(&t.to_le_bytes());
+                    events_bytes.extend_from_slice(&x.to_le_bytes());
+                    events_bytes.extend_from_slice(&y.to_le_bytes());
+                    events_bytes.push(on);
+                    if convert_options.utc {
+                        events_bytes.extend_from_slice(&clock.utc(t).to_le_bytes());
+                    }
+                    first_event += 1;
+                }
+            },
+            |trigger_event| {
+                let t = trigger_event.t; // unpack field
+                let id = trigger_event.id; // unpack field
+                let rising = trigger_event.polarity as u8; // unpack field
+                if !convert_options.triggers || !selection.contains_t(t) {
+                    return;
+                }
+                triggers_bytes.extend_from_slice(&index_entry.system_time.to_le_bytes());
+                triggers_bytes.extend_from_slice(&index_entry.system_timestamp.to_le_bytes());
+                triggers_bytes.extend_from_slice(&t.to_le_bytes());
+                triggers_bytes.push(id);
+                triggers_bytes.push(rising);
+                if convert_options.utc {
+                    triggers_bytes.extend_from_slice(&clock.utc(t).to_le_bytes());
+                }
+            },
+        );
+        npz.write_all(&events_bytes)?;
+        triggers_spill.write_all(&triggers_bytes)?;
+        events_bytes.clear();
+        triggers_bytes.clear();
+        if pass.cancelled() {
+            return Ok(false);
+        }
+    }
+    if first_event != events {
+        return Err(anyhow::anyhow!(
+            "the second pass decoded {} events instead of {}",
+            first_event,
+            events
+        ));
+    }
+    if convert_options.triggers {
+        npz.start_file("triggers.npy", options)?;
+        write_npy_header(&mut npz, &triggers_fields, triggers)?;
+        triggers_spill.copy_to(&mut npz)?;
+    }
+    drop(triggers_spill);
+
+    // index
+    if convert_options.index {
+        npz.start_file("index.npy", options)?;
+        write_npy_header(&mut npz, &INDEX_FIELDS, index_entries)?;
+        index_spill.copy_to(&mut npz)?;
+    }
+    drop(index_spill);
+    if pass.cancelled() {
+        return Ok(false);
+    }
+
+    // samples
+    if !convert_options.samples {
+        npz.finish()?;
+        return Ok(true);
+    }
+    let mut samples_file = std::io::BufReader::new(std::fs::File::open(
+        pass.data_directory
+            .join(recordings::RECORDINGS_DIRECTORY_NAME)
+            .join(format!(
+                "{}{}",
+                pass.name,
+                recordings::SAMPLES_FILE_EXTENSION
+            )),
+    )?);
+    let samples_header = recordings::read_header(
+        &mut Vec::new(),
+        &mut samples_file,
+        recordings::SAMPLES_FILE_SIGNATURE.as_bytes(),
+    )?;
+    match samples_header.file_type {
+        // Prophesee EVK4
+        0 => {
+            // a trailing partial sample (interrupted write) is ignored
+            let samples = samples_file
+                .get_ref()
+                .metadata()?
+                .len()
+                .saturating_sub(samples_header.length)
+                / recordings::EVK4_SAMPLE_LENGTH as u64;
+            // the .npy layout matches the samples file layout
+            // the first pass counts the selected samples, the second pass copies them
+            let mut sample_data = [0u8; recordings::EVK4_SAMPLE_LENGTH];
+            let mut selected_samples = 0u64;
+            for _ in 0..samples {
+                samples_file.read_exact(&mut sample_data)?;
+                let sample = recordings::Evk4Sample::from_bytes(&sample_data);
+                if selection.contains_system_time(sample.system_time) {
+                    selected_samples += 1;
+                }
+            }
+            samples_file.seek(std::io::SeekFrom::Start(samples_header.length))?;
+            npz.start_file("samples.npy", options)?;
+            write_npy_header(&mut npz, &SAMPLES_FIELDS, selected_samples)?;
+            for _ in 0..samples {
+                samples_file.read_exact(&mut sample_data)?;
+                let sample = recordings::Evk4Sample::from_bytes(&sample_data);
+                if selection.contains_system_time(sample.system_time) {
+                    npz.write_all(&sample_data)?;
+                }
+            }
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "unsupported samples file type {}",
+                samples_header.file_type
+            ));
+        }
+    }
+    npz.finish()?;
+    Ok(true)
+}
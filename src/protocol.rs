@@ -1,4 +1,5 @@
 use crate::constants;
+use crate::recordings;
 use anyhow::anyhow;
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -59,6 +60,135 @@ impl Default for Autotrigger {
     }
 }
 
+// A recording is split into segments when its duration or size exceeds the maximum
+// (0 disables the corresponding criterion)
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct Segmenting {
+    pub enabled: bool,
+    pub maximum_duration_us: u64,
+    pub maximum_size_bytes: u64,
+}
+
+impl Default for Segmenting {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            maximum_duration_us: 600_000_000,
+            maximum_size_bytes: 2_000_000_000,
+        }
+    }
+}
+
+// Recording files are flushed and synced to disk every `period_ms` milliseconds
+// or every `size_bytes` bytes, whichever comes first (0 disables the corresponding criterion)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Durability {
+    pub period_ms: u64,
+    pub size_bytes: u64,
+}
+
+// Sensor timestamps are in µs, UTC times use RFC 3339 (for instance "2025-01-01T00:00:00.000000Z")
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum TrimRange {
+    Timestamp { begin_t: u64, end_t: u64 },
+    Utc { begin: String, end: String },
+}
+
+// Decay of the pixels in rendered videos (the web renderer's styles)
+// TimeSurface decays linearly over 2 tau, Accumulation shows the events of the last tau
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum VideoStyle {
+    ExponentialDecay,
+    TimeSurface,
+    Accumulation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct VideoOptions {
+    pub frame_duration_us: u64,
+    pub tau_us: u64,
+    pub style: VideoStyle,
+}
+
+impl Default for VideoOptions {
+    fn default() -> Self {
+        Self {
+            frame_duration_us: 20_000,
+            tau_us: 200_000,
+            style: VideoStyle::ExponentialDecay,
+        }
+    }
+}
+
+// Conversion filters, applied in order to polarity events (triggers are not filtered)
+// Roi crops the events (coordinates start at left and top), Polarity keeps on or off events,
+// Time keeps the events in [begin_t, end_t[ (sensor timestamps in µs),
+// BackgroundActivity keeps the events whose neighbours fired less than tau_us before,
+// and Refractory drops the events less than period_us after the pixel's previous event
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum Filter {
+    Roi {
+        left: u16,
+        top: u16,
+        width: u16,
+        height: u16,
+    },
+    Polarity {
+        on: bool,
+    },
+    Time {
+        begin_t: u64,
+        end_t: u64,
+    },
+    BackgroundActivity {
+        tau_us: u64,
+    },
+    Refractory {
+        period_us: u64,
+    },
+}
+
+// The default conversion (empty variant) is written to converted-recordings/NAME.zip,
+// other variants are written to converted-recordings/VARIANT/NAME.zip
+// UTC ranges select whole USB packets, timestamp ranges select events
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ConvertOptions {
+    pub variant: String,
+    pub formats: Vec<recordings::Format>,
+    // deflate level (0 stores files without compression)
+    pub compression_level: u8,
+    pub index: bool,
+    pub samples: bool,
+    pub triggers: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<TrimRange>,
+    // used by the y4m and png formats
+    #[serde(default)]
+    pub video: VideoOptions,
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    // adds a utc column (µs since the Unix epoch, from the clock model) to events and triggers
+    // in the csv, npz, and arrow formats
+    #[serde(default)]
+    pub utc: bool,
+}
+
+impl ConvertOptions {
+    // Returns true if the recording has already been converted to this variant.
+    pub fn is_converted(&self, zip: bool, variants: &[String]) -> bool {
+        if self.variant.is_empty() {
+            zip
+        } else {
+            variants.contains(&self.variant)
+        }
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct Device {
     pub id: u32,
@@ -72,6 +202,8 @@ pub struct Device {
     pub lookback: Lookback,
     pub autostop: Autostop,
     pub autotrigger: Autotrigger,
+    pub durability: Durability,
+    pub segmenting: Segmenting,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -86,22 +218,66 @@ pub struct SharedClientState {
 #[serde(tag = "type")]
 pub enum RecordingState {
     Ongoing,
-    Incomplete { size_bytes: u64 },
-    Complete { size_bytes: u64, zip: bool },
-    Queued { size_bytes: u64, zip: bool },
-    Converting { size_bytes: u64, zip: bool },
+    Incomplete {
+        size_bytes: u64,
+    },
+    Complete {
+        size_bytes: u64,
+        zip: bool,
+        variants: Vec<String>,
+    },
+    Queued {
+        size_bytes: u64,
+        zip: bool,
+        variants: Vec<String>,
+        options: ConvertOptions,
+    },
+    // processed_bytes and total_bytes count raw bytes (once per pass over the raw file)
+    // remaining_ms is None until the first estimate
+    Converting {
+        size_bytes: u64,
+        zip: bool,
+        variants: Vec<String>,
+        options: ConvertOptions,
+        processed_bytes: u64,
+        total_bytes: u64,
+        remaining_ms: Option<u64>,
+    },
+}
+
+// key fields of the recording's statistics (see statistics.rs), times in µs
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Summary {
+    pub on_events: u64,
+    pub off_events: u64,
+    pub triggers: u64,
+    pub duration_us: u64,
+    pub wall_duration_us: u64,
+    pub overflows: u64,
 }
 
+// summary is None until the statistics are computed (complete recordings only)
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
 pub struct Recording {
     pub name: String,
     pub state: RecordingState,
+    pub summary: Option<Summary>,
+}
+
+// recording deleted with `trash`, stored in DATA/trash/ENTRY
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct TrashedRecording {
+    pub entry: String,
+    pub name: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
 pub struct SharedRecordingsState {
     pub data_directory: String,
+    // targets of MoveRecordings (--move-directory)
+    pub move_directories: Vec<String>,
     pub recordings: Vec<Recording>,
+    pub trash: Vec<TrashedRecording>,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -165,6 +341,14 @@ pub enum ClientMessage {
         device_id: u32,
         autotrigger: Autotrigger,
     },
+    UpdateDurability {
+        device_id: u32,
+        durability: Durability,
+    },
+    UpdateSegmenting {
+        device_id: u32,
+        segmenting: Segmenting,
+    },
     StartRecording {
         device_id: u32,
         name: String,
@@ -172,8 +356,47 @@ pub enum ClientMessage {
     StopRecording {
         device_id: u32,
     },
+    // the server's default options are used if `options` is None
     Convert {
         names: Vec<String>,
+        options: Option<ConvertOptions>,
+    },
+    // cancels all the queued and ongoing conversions if `names` is empty
+    CancelConvert {
+        #[serde(default)]
+        names: Vec<String>,
+    },
+    Trim {
+        name: String,
+        range: TrimRange,
+        new_name: String,
+    },
+    // recomputes the statistics of complete recordings (all of them if `names` is empty)
+    ComputeStatistics {
+        #[serde(default)]
+        names: Vec<String>,
+    },
+    // deleted recordings are moved to the trash if `trash` is true
+    DeleteRecordings {
+        names: Vec<String>,
+        #[serde(default)]
+        trash: bool,
+    },
+    RenameRecording {
+        name: String,
+        new_name: String,
+    },
+    // moves recordings to another data directory
+    MoveRecordings {
+        names: Vec<String>,
+        data_directory: String,
+    },
+    RestoreRecordings {
+        entries: Vec<String>,
+    },
+    // permanently deletes trashed recordings (all of them if `entries` is empty)
+    EmptyTrash {
+        #[serde(default)]
+        entries: Vec<String>,
     },
-    CancelConvert,
 }
@@ -1,4 +1,14 @@
+use crate::aedat4;
+use crate::clock;
+use crate::columnar;
+use crate::csv;
+use crate::event_stream;
+use crate::filters;
+use crate::metavision;
+use crate::npz;
 use crate::protocol;
+use crate::statistics;
+use crate::video;
 
 use serde::Serialize;
 use std::io::Read;
@@ -19,9 +29,9 @@ pub const RAW_FILE_SIGNATURE: &'static str = "KAIROS-RAW";
 pub const SAMPLES_FILE_EXTENSION: &'static str = ".samples.kai";
 pub const SAMPLES_FILE_SIGNATURE: &'static str = "KAIROS-SAMPLES";
 pub const METADATA_FILE_EXTENSION: &'static str = ".toml";
-const ZIP_FILE_EXTENSION: &'static str = ".zip";
+pub const ZIP_FILE_EXTENSION: &'static str = ".zip";
 
-const RECORDING_FILES_EXTENSIONS: [&'static str; 4] = [
+pub const RECORDING_FILES_EXTENSIONS: [&'static str; 4] = [
     INDEX_FILE_EXTENSION,
     RAW_FILE_EXTENSION,
     SAMPLES_FILE_EXTENSION,
@@ -30,24 +40,695 @@ const RECORDING_FILES_EXTENSIONS: [&'static str; 4] = [
 
 const CONVERTED_FILES_EXTENSIONS: [&'static str; 1] = [ZIP_FILE_EXTENSION];
 
+// signature, version, type, header length, CRC32C, and the fixed-size description fields
+const V1_HEADER_MINIMUM_LENGTH: usize = 2 + 4 + 4 + 2 + 2 + 8 + 8 + 2 + 2;
+
+pub const EVT3_INDEX_ENTRY_LENGTH: usize = 54;
+pub const EVT3_CHECKSUM_INDEX_ENTRY_LENGTH: usize = EVT3_INDEX_ENTRY_LENGTH + 4;
+pub const EVK4_SAMPLE_LENGTH: usize = 24;
+
+// Event files included in converted recordings
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    Csv,
+    Es,
+    Aedat4,
+    MetavisionRaw,
+    Npz,
+    Arrow,
+    Y4m,
+    Png,
+}
+
 pub const RECORDINGS_DIRECTORY_NAME: &'static str = "recordings";
 pub const CONVERTED_RECORDINGS_DIRECTORY_NAME: &'static str = "converted-recordings";
 
+// v1 headers describe the recording so that raw and index files can be identified
+// without the metadata file (for instance after being renamed or moved separately)
+#[derive(Debug, Clone, Serialize)]
+pub struct Description {
+    pub device_name: String,
+    pub device_serial: String,
+    pub width: u16,
+    pub height: u16,
+    pub start_system_time: u64,
+    // system time (µs since the Unix epoch) of system timestamp 0
+    pub time_reference_system_time: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub version: u8,
+    pub file_type: u8,
+    // offset of the first byte after the header
+    pub length: u64,
+    // None for v0 files
+    pub description: Option<Description>,
+}
+
+#[derive(Serialize)]
+struct HeaderMetadata {
+    version: u8,
+    #[serde(flatten)]
+    description: Description,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Evt3IndexEntry {
+    pub system_time: u64,
+    pub system_timestamp: u64,
+    pub first_after_overflow: bool,
+    pub raw_file_offset: u64,
+    pub raw_length: u64,
+    pub state: neuromorphic_drivers::adapters::evt3::State,
+    // CRC32C of the raw packet (index format 1 only)
+    pub checksum: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl std::fmt::Display for ChecksumMismatch {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            formatter,
+            "Checksum mismatch (the index contains {:#010x} but the raw packet's CRC32C is {:#010x})",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+// Returns the entry length of an index file type, or None if the index file type is not supported
+// or is not compatible with the raw file type.
+// Index file types: 0 is EVT3 and 1 is EVT3 with a CRC32C of each (stored) raw packet.
+// Raw file types: 0 is EVT3 and 1 is EVT3 compressed with LZ4 (one frame per index entry).
+pub fn index_entry_length(index_file_type: u8, raw_file_type: u8) -> Option<usize> {
+    match (index_file_type, raw_file_type) {
+        (0, 0 | 1) => Some(EVT3_INDEX_ENTRY_LENGTH),
+        (1, 0 | 1) => Some(EVT3_CHECKSUM_INDEX_ENTRY_LENGTH),
+        _ => None,
+    }
+}
+
+// Writes an LZ4 frame (uncompressed length as u32 LE followed by an LZ4 block) to `frame`.
+pub fn compress_packet(packet: &[u8], frame: &mut Vec<u8>) {
+    frame.clear();
+    frame.resize(
+        4 + lz4_flex::block::get_maximum_output_size(packet.len()),
+        0u8,
+    );
+    frame[0..4].copy_from_slice(&(packet.len() as u32).to_le_bytes());
+    let length = lz4_flex::block::compress_into(packet, &mut frame[4..])
+        .expect("the frame is larger than the maximum output size");
+    frame.truncate(4 + length);
+}
+
+pub fn decompress_packet(frame: &[u8], packet: &mut Vec<u8>) -> Result<(), anyhow::Error> {
+    if frame.len() < 4 {
+        return Err(anyhow::anyhow!(
+            "the LZ4 frame is too short ({} bytes)",
+            frame.len()
+        ));
+    }
+    let length = u32::from_le_bytes(frame[0..4].try_into().expect("4 bytes")) as usize;
+    // the length is checked before allocating (an LZ4 block expands at most 255 times)
+    if length > (frame.len() - 4) * 255 {
+        return Err(anyhow::anyhow!(
+            "bad LZ4 frame length {} (the frame has {} bytes)",
+            length,
+            frame.len()
+        ));
+    }
+    packet.clear();
+    packet.resize(length, 0u8);
+    let decompressed_length = lz4_flex::block::decompress_into(&frame[4..], packet)?;
+    if decompressed_length != length {
+        return Err(anyhow::anyhow!(
+            "the LZ4 frame decompressed to {} bytes instead of {}",
+            decompressed_length,
+            length
+        ));
+    }
+    Ok(())
+}
+
+impl Evt3IndexEntry {
+    // `data` must be EVT3_INDEX_ENTRY_LENGTH or EVT3_CHECKSUM_INDEX_ENTRY_LENGTH bytes long
+    pub fn from_bytes(data: &[u8]) -> Self {
+        Self {
+            system_time: u64::from_le_bytes(data[0..8].try_into().expect("8 bytes")),
+            system_timestamp: u64::from_le_bytes(data[8..16].try_into().expect("8 bytes")),
+            first_after_overflow: data[16] == 1,
+            raw_file_offset: u64::from_le_bytes(data[17..25].try_into().expect("8 bytes")),
+            raw_length: u64::from_le_bytes(data[25..33].try_into().expect("8 bytes")),
+            state: neuromorphic_drivers::adapters::evt3::State {
+                t: u64::from_le_bytes(data[33..41].try_into().expect("8 bytes")),
+                overflows: u32::from_le_bytes(data[41..45].try_into().expect("4 bytes")),
+                previous_msb_t: u16::from_le_bytes(data[45..47].try_into().expect("2 bytes")),
+                previous_lsb_t: u16::from_le_bytes(data[47..49].try_into().expect("2 bytes")),
+                x: u16::from_le_bytes(data[49..51].try_into().expect("2 bytes")),
+                y: u16::from_le_bytes(data[51..53].try_into().expect("2 bytes")),
+                polarity: if data[53] == 1 {
+                    neuromorphic_drivers::types::Polarity::On
+                } else {
+                    neuromorphic_drivers::types::Polarity::Off
+                },
+            },
+            checksum: if data.len() >= EVT3_CHECKSUM_INDEX_ENTRY_LENGTH {
+                Some(u32::from_le_bytes(
+                    data[54..58].try_into().expect("4 bytes"),
+                ))
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut data = vec![
+            0u8;
+            if self.checksum.is_some() {
+                EVT3_CHECKSUM_INDEX_ENTRY_LENGTH
+            } else {
+                EVT3_INDEX_ENTRY_LENGTH
+            }
+        ];
+        data[0..8].copy_from_slice(&self.system_time.to_le_bytes());
+        data[8..16].copy_from_slice(&self.system_timestamp.to_le_bytes());
+        data[16] = self.first_after_overflow as u8;
+        data[17..25].copy_from_slice(&self.raw_file_offset.to_le_bytes());
+        data[25..33].copy_from_slice(&self.raw_length.to_le_bytes());
+        data[33..41].copy_from_slice(&self.state.t.to_le_bytes());
+        data[41..45].copy_from_slice(&self.state.overflows.to_le_bytes());
+        data[45..47].copy_from_slice(&self.state.previous_msb_t.to_le_bytes());
+        data[47..49].copy_from_slice(&self.state.previous_lsb_t.to_le_bytes());
+        data[49..51].copy_from_slice(&self.state.x.to_le_bytes());
+        data[51..53].copy_from_slice(&self.state.y.to_le_bytes());
+        data[53] = self.state.polarity as u8;
+        if let Some(checksum) = self.checksum {
+            data[54..58].copy_from_slice(&checksum.to_le_bytes());
+        }
+        data
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Evk4Sample {
+    pub system_time: u64,
+    pub system_timestamp: u64,
+    pub illuminance: f32,
+    pub temperature: f32,
+}
+
+impl Evk4Sample {
+    pub fn from_bytes(data: &[u8; EVK4_SAMPLE_LENGTH]) -> Self {
+        Self {
+            system_time: u64::from_le_bytes(data[0..8].try_into().expect("8 bytes")),
+            system_timestamp: u64::from_le_bytes(data[8..16].try_into().expect("8 bytes")),
+            illuminance: f32::from_le_bytes(data[16..20].try_into().expect("4 bytes")),
+            temperature: f32::from_le_bytes(data[20..24].try_into().expect("4 bytes")),
+        }
+    }
+}
+
+// A reader decodes a complete (or partially written) EVT3 recording packet by packet.
+// Each call to `next_packet` reads one index entry and the matching raw bytes,
+// and `convert` decodes the latest packet with the decoder state stored in the index.
+// Seeking uses a binary search over the index entries and restores the stored state,
+// hence decoding can start at any packet without reading the raw file from the beginning.
+pub struct Reader {
+    pub width: u16,
+    pub height: u16,
+    pub index_entries: u64,
+    pub raw_header: Header,
+    pub index_header: Header,
+    index_entry_length: usize,
+    raw_file: std::io::BufReader<std::fs::File>,
+    index_file: std::io::BufReader<std::fs::File>,
+    index_file_position: u64,
+    next_index: u64,
+    // next_packet reads the packets in [begin_index, end_index[
+    begin_index: u64,
+    end_index: u64,
+    peeked_index_entry: Option<Evt3IndexEntry>,
+    raw_file_position: u64,
+    raw_buffer: Vec<u8>,
+    // stored bytes of the latest packet (raw file type 1 only)
+    frame_buffer: Vec<u8>,
+    adapter: Option<neuromorphic_drivers::adapters::evt3::Adapter>,
+    converted: bool,
+}
+
+impl Reader {
+    pub fn open(data_directory: &std::path::Path, name: &str) -> Result<Self, anyhow::Error> {
+        let recordings_directory = data_directory.join(RECORDINGS_DIRECTORY_NAME);
+        let mut file_buffer = Vec::new();
+        let mut raw_file = std::io::BufReader::new(std::fs::File::open(
+            recordings_directory.join(format!("{}{}", name, RAW_FILE_EXTENSION)),
+        )?);
+        let (raw_header, width, height) = read_raw_header(&mut file_buffer, &mut raw_file)?;
+        let index_path = recordings_directory.join(format!("{}{}", name, INDEX_FILE_EXTENSION));
+        let mut index_file = std::io::BufReader::new(std::fs::File::open(&index_path)?);
+        let index_header = read_header(
+            &mut file_buffer,
+            &mut index_file,
+            INDEX_FILE_SIGNATURE.as_bytes(),
+        )?;
+        let index_entry_length = index_entry_length(index_header.file_type, raw_header.file_type)
+            .ok_or(anyhow::anyhow!(
+            "the index and raw types are incompatible (raw is {}, index is {})",
+            raw_header.file_type,
+            index_header.file_type,
+        ))?;
+        match raw_header.file_type {
+            // EVT3 and LZ4-compressed EVT3
+            0 | 1 => {
+                // a trailing partial entry (interrupted write) is ignored
+                let index_entries = index_path
+                    .metadata()?
+                    .len()
+                    .saturating_sub(index_header.length)
+                    / index_entry_length as u64;
+                let raw_file_position = raw_header.length;
+                Ok(Self {
+                    width,
+                    height,
+                    index_entries,
+                    raw_header,
+                    index_header,
+                    index_entry_length,
+                    raw_file,
+                    index_file,
+                    index_file_position: 0,
+                    next_index: 0,
+                    begin_index: 0,
+                    end_index: index_entries,
+                    peeked_index_entry: None,
+                    raw_file_position,
+                    raw_buffer: Vec::new(),
+                    frame_buffer: Vec::new(),
+                    adapter: None,
+                    converted: false,
+                })
+            }
+            _ => Err(anyhow::anyhow!(
+                "unsupported raw file type {}",
+                raw_header.file_type
+            )),
+        }
+    }
+
+    // Reads the index entry with the given index (random access).
+    // Sequential calls do not seek the underlying file.
+    pub fn index_entry(&mut self, index: u64) -> Result<Evt3IndexEntry, anyhow::Error> {
+        if index >= self.index_entries {
+            return Err(anyhow::anyhow!(
+                "the index entry {} is out of range (the index has {} entries)",
+                index,
+                self.index_entries
+            ));
+        }
+        if index != self.index_file_position {
+            self.index_file.seek(std::io::SeekFrom::Start(
+                self.index_header.length + index * self.index_entry_length as u64,
+            ))?;
+        }
+        let mut index_data = [0u8; EVT3_CHECKSUM_INDEX_ENTRY_LENGTH];
+        let index_data = &mut index_data[..self.index_entry_length];
+        let result = self.index_file.read_exact(index_data);
+        // on error, the file position is unknown and the next read must seek
+        self.index_file_position = if result.is_ok() { index + 1 } else { u64::MAX };
+        result?;
+        Ok(Evt3IndexEntry::from_bytes(index_data))
+    }
+
+    // Returns the index entry of the next packet without consuming it.
+    pub fn peek_index_entry(&mut self) -> Result<Option<Evt3IndexEntry>, anyhow::Error> {
+        if self.peeked_index_entry.is_none() && self.next_index < self.end_index {
+            self.peeked_index_entry = Some(self.index_entry(self.next_index)?);
+        }
+        Ok(self.peeked_index_entry)
+    }
+
+    // Reads the next packet and returns its index entry, or None at the end of the recording.
+    // The packet's raw bytes are available with `raw` and its events with `convert`.
+    // A checksum mismatch consumes the packet, hence the caller may skip it and keep reading.
+    pub fn next_packet(&mut self) -> Result<Option<Evt3IndexEntry>, anyhow::Error> {
+        let index_entry = match self.peek_index_entry()? {
+            Some(index_entry) => index_entry,
+            None => return Ok(None),
+        };
+        if index_entry.raw_file_offset != self.raw_file_position {
+            return Err(anyhow::anyhow!(
+                "Position mismatch (the raw file is at position {} but the index points to {})",
+                self.raw_file_position,
+                index_entry.raw_file_offset
+            ));
+        }
+        let adapter = neuromorphic_drivers::adapters::evt3::Adapter::from_dimensions_and_state(
+            self.width,
+            self.height,
+            index_entry.state,
+        );
+        // the previous packet is decoded (if the caller did not convert it) to get its final state
+        if let Some(previous_adapter) = self.adapter.as_mut() {
+            if !self.converted {
+                previous_adapter.convert(&self.raw_buffer, |_| {}, |_| {});
+                self.converted = true;
+            }
+            if adapter.state() != previous_adapter.state() {
+                return Err(anyhow::anyhow!(
+                    "State mismatch (the raw file's state is {:?} but the index contains {:?})",
+                    previous_adapter.state(),
+                    adapter.state()
+                ));
+            }
+        }
+        let stored_buffer = if self.raw_header.file_type == 1 {
+            &mut self.frame_buffer
+        } else {
+            &mut self.raw_buffer
+        };
+        stored_buffer.clear();
+        // the packet is not allocated from the index length (corrupted indexes may store any value)
+        if (&mut self.raw_file)
+            .take(index_entry.raw_length)
+            .read_to_end(stored_buffer)? as u64
+            != index_entry.raw_length
+        {
+            return Err(anyhow::anyhow!(
+                "the raw file ends before the packet at {} ({} bytes)",
+                index_entry.raw_file_offset,
+                index_entry.raw_length
+            ));
+        }
+        self.raw_file_position += index_entry.raw_length;
+        self.peeked_index_entry = None;
+        self.next_index += 1;
+        self.adapter = Some(adapter);
+        self.converted = false;
+        if let Some(checksum) = index_entry.checksum {
+            let raw_checksum = crate::crc32c::checksum(self.stored());
+            if raw_checksum != checksum {
+                // the packet's final state is unknown, hence the next packet is not checked
+                self.raw_buffer.clear();
+                self.adapter = None;
+                return Err(ChecksumMismatch {
+                    expected: checksum,
+                    actual: raw_checksum,
+                }
+                .into());
+            }
+        }
+        if self.raw_header.file_type == 1 {
+            if let Err(error) = decompress_packet(&self.frame_buffer, &mut self.raw_buffer) {
+                self.raw_buffer.clear();
+                self.adapter = None;
+                return Err(error);
+            }
+        }
+        Ok(Some(index_entry))
+    }
+
+    pub fn index_entry_length(&self) -> usize {
+        self.index_entry_length
+    }
+
+    pub fn raw_file_position(&self) -> u64 {
+        self.raw_file_position
+    }
+
+    // Raw EVT3 bytes of the latest packet (decompressed if needed).
+    pub fn raw(&self) -> &[u8] {
+        &self.raw_buffer
+    }
+
+    // Bytes of the latest packet as stored in the raw file.
+    pub fn stored(&self) -> &[u8] {
+        if self.raw_header.file_type == 1 {
+            &self.frame_buffer
+        } else {
+            &self.raw_buffer
+        }
+    }
+
+    // Decodes the latest packet (returned by `next_packet`).
+    pub fn convert<HandleDvsEvent, HandleTriggerEvent>(
+        &mut self,
+        handle_dvs_event: HandleDvsEvent,
+        handle_trigger_event: HandleTriggerEvent,
+    ) where
+        HandleDvsEvent: FnMut(neuromorphic_drivers::types::PolarityEvent<u64, u16, u16>),
+        HandleTriggerEvent: FnMut(neuromorphic_drivers::types::TriggerEvent<u64, u8>),
+    {
+        if self.converted {
+            return;
+        }
+        if let Some(adapter) = self.adapter.as_mut() {
+            adapter.convert(&self.raw_buffer, handle_dvs_event, handle_trigger_event);
+            self.converted = true;
+        }
+    }
+
+    // Restricts the reader to the packets in [begin_index, end_index[ and moves it to begin_index.
+    pub fn set_range(&mut self, begin_index: u64, end_index: u64) -> Result<(), anyhow::Error> {
+        self.begin_index = begin_index.min(self.index_entries);
+        self.end_index = end_index.clamp(self.begin_index, self.index_entries);
+        self.rewind()
+    }
+
+    // Moves the reader to the first packet of its range.
+    pub fn rewind(&mut self) -> Result<(), anyhow::Error> {
+        self.seek_index(self.begin_index)
+    }
+
+    // Number of raw file bytes (as stored) of the packets in the reader's range.
+    pub fn range_length(&mut self) -> Result<u64, anyhow::Error> {
+        if self.begin_index >= self.end_index {
+            return Ok(0);
+        }
+        let begin = self.index_entry(self.begin_index)?.raw_file_offset;
+        let last_index_entry = self.index_entry(self.end_index - 1)?;
+        Ok(last_index_entry.raw_file_offset + last_index_entry.raw_length - begin)
+    }
+
+    // Number of raw file bytes (as stored) of all the packets.
+    pub fn length(&mut self) -> Result<u64, anyhow::Error> {
+        if self.index_entries == 0 {
+            return Ok(0);
+        }
+        let begin = self.index_entry(0)?.raw_file_offset;
+        let last_index_entry = self.index_entry(self.index_entries - 1)?;
+        Ok(last_index_entry.raw_file_offset + last_index_entry.raw_length - begin)
+    }
+
+    // Index of the packet returned by the next call to `next_packet`.
+    pub fn next_index(&self) -> u64 {
+        self.next_index
+    }
+
+    // Moves the reader to the packet with the given index.
+    pub fn seek_index(&mut self, index: u64) -> Result<(), anyhow::Error> {
+        if index >= self.index_entries {
+            self.next_index = self.index_entries;
+            self.peeked_index_entry = None;
+            return Ok(());
+        }
+        let index_entry = self.index_entry(index)?;
+        self.raw_file
+            .seek(std::io::SeekFrom::Start(index_entry.raw_file_offset))?;
+        self.raw_file_position = index_entry.raw_file_offset;
+        self.next_index = index;
+        // next_packet must not return a packet after the end of the range
+        self.peeked_index_entry = (index < self.end_index).then_some(index_entry);
+        self.adapter = None;
+        self.converted = false;
+        Ok(())
+    }
+
+    // Returns the number of entries whose key is smaller than or equal to `target`,
+    // assuming that keys are sorted.
+    fn search<Key>(&mut self, target: u64, key: Key) -> Result<u64, anyhow::Error>
+    where
+        Key: Fn(&Evt3IndexEntry) -> u64,
+    {
+        let mut low = 0;
+        let mut high = self.index_entries;
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if key(&self.index_entry(middle)?) <= target {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        Ok(low)
+    }
+
+    // Moves the reader to the packet that contains the sensor timestamp `t` (µs).
+    // The packet may contain events before `t`, which callers must skip if needed.
+    pub fn seek_t(&mut self, t: u64) -> Result<(), anyhow::Error> {
+        let count = self.search(t, |index_entry| index_entry.state.t)?;
+        self.seek_index(count.saturating_sub(1))
+    }
+
+    // Moves the reader to the packet received at the given system time
+    // (µs since the Unix epoch, UTC).
+    pub fn seek_system_time(&mut self, system_time: u64) -> Result<(), anyhow::Error> {
+        let count = self.search(system_time, |index_entry| index_entry.system_time)?;
+        self.seek_index(count.saturating_sub(1))
+    }
+}
+
+// Conversion progress, updated by the conversion thread and read by the task that reports it
+// Each pass over the raw file counts its bytes
+#[derive(Debug, Default)]
+pub struct Progress {
+    processed_bytes: std::sync::atomic::AtomicU64,
+    total_bytes: std::sync::atomic::AtomicU64,
+}
+
+impl Progress {
+    pub fn processed_bytes(&self) -> u64 {
+        self.processed_bytes
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn add(&self, bytes: u64) {
+        self.processed_bytes
+            .fetch_add(bytes, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    fn set_total_bytes(&self, bytes: u64) {
+        self.total_bytes
+            .store(bytes, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    // Estimates the remaining time assuming a constant processing rate.
+    pub fn remaining(&self, elapsed: std::time::Duration) -> Option<std::time::Duration> {
+        let processed_bytes = self.processed_bytes();
+        let total_bytes = self.total_bytes();
+        if processed_bytes == 0 || total_bytes < processed_bytes {
+            None
+        } else {
+            Some(elapsed.mul_f64((total_bytes - processed_bytes) as f64 / processed_bytes as f64))
+        }
+    }
+}
+
+// Events and samples kept by a conversion (the reader skips the packets outside the range)
+#[derive(Debug, Clone, Copy)]
+pub struct Selection {
+    pub begin_t: u64,
+    pub end_t: u64,
+    pub begin_system_time: u64,
+    pub end_system_time: u64,
+}
+
+impl Selection {
+    pub fn contains_t(&self, t: u64) -> bool {
+        t >= self.begin_t && t <= self.end_t
+    }
+
+    pub fn contains_system_time(&self, system_time: u64) -> bool {
+        system_time >= self.begin_system_time && system_time <= self.end_system_time
+    }
+}
+
+// Restricts the reader to the packets that overlap with `range`.
+pub fn select(
+    reader: &mut Reader,
+    range: Option<&protocol::TrimRange>,
+) -> Result<Selection, anyhow::Error> {
+    let (begin_t, end_t, begin_system_time, end_system_time) = match range {
+        Some(protocol::TrimRange::Timestamp { begin_t, end_t }) => (*begin_t, *end_t, 0, u64::MAX),
+        Some(protocol::TrimRange::Utc { begin, end }) => (
+            0,
+            u64::MAX,
+            crate::trim::parse_utc(begin)?,
+            crate::trim::parse_utc(end)?,
+        ),
+        None => {
+            reader.set_range(0, reader.index_entries)?;
+            return Ok(Selection {
+                begin_t: 0,
+                end_t: u64::MAX,
+                begin_system_time: 0,
+                end_system_time: u64::MAX,
+            });
+        }
+    };
+    if end_t < begin_t || end_system_time < begin_system_time {
+        return Err(anyhow::anyhow!(
+            "the range end is smaller than the range begin"
+        ));
+    }
+    // the selection is empty if the range ends before the first packet
+    let (begin_index, end_index) = match range {
+        Some(protocol::TrimRange::Timestamp { .. }) => {
+            let end_index = reader.search(end_t, |index_entry| index_entry.state.t)?;
+            reader.seek_t(begin_t)?;
+            (reader.next_index(), end_index)
+        }
+        _ => {
+            let end_index =
+                reader.search(end_system_time, |index_entry| index_entry.system_time)?;
+            reader.seek_system_time(begin_system_time)?;
+            (reader.next_index(), end_index)
+        }
+    };
+    reader.set_range(begin_index, end_index)?;
+    // samples are selected with the system times of the first and last packets of timestamp ranges
+    let (begin_system_time, end_system_time) = if matches!(
+        range,
+        Some(protocol::TrimRange::Timestamp { .. })
+    ) && begin_index < end_index
+    {
+        (
+            reader.index_entry(begin_index)?.system_time,
+            reader.index_entry(end_index - 1)?.system_time,
+        )
+    } else {
+        (begin_system_time, end_system_time)
+    };
+    Ok(Selection {
+        begin_t,
+        end_t,
+        begin_system_time,
+        end_system_time,
+    })
+}
+
 #[derive(Clone, Copy)]
 pub enum Action {
     Rename,
     Delete,
 }
 
+// Returns the names of the processed files (without the .write extension).
 pub fn process_write_files(
     directory: &std::path::PathBuf,
     action: Action,
-) -> Result<(), std::io::Error> {
+) -> Result<Vec<String>, std::io::Error> {
+    let mut file_names = Vec::new();
     match directory.read_dir() {
         Ok(entries) => {
             for entry in entries {
                 let entry = entry?;
-                if entry.file_name().to_string_lossy().ends_with(".write") {
+                if let Some(file_name) = entry.file_name().to_string_lossy().strip_suffix(".write")
+                {
                     let path = entry.path();
                     match action {
                         Action::Rename => {
@@ -59,14 +740,22 @@ pub fn process_write_files(
                             std::fs::remove_file(path)?;
                         }
                     }
+                    file_names.push(file_name.to_owned());
                 }
             }
-            Ok(())
+            Ok(file_names)
         }
-        Err(_) => Ok(()),
+        Err(_) => Ok(file_names),
     }
 }
 
+// Returns the recording name of a recording file (without the .write extension).
+pub fn recording_name(file_name: &str) -> Option<&str> {
+    RECORDING_FILES_EXTENSIONS
+        .iter()
+        .find_map(|extension| file_name.strip_suffix(extension))
+}
+
 fn read_stem_to_files_statuses<HandleError, const FILE_TYPES: usize>(
     directory: &std::path::PathBuf,
     extensions: &[&str; FILE_TYPES],
@@ -146,6 +835,68 @@ where
     }
 }
 
+// Variant names are used as directory names.
+pub fn check_variant(variant: &str) -> Result<(), anyhow::Error> {
+    if variant.starts_with('.')
+        || !variant
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || "-_.".contains(character))
+    {
+        return Err(anyhow::anyhow!(
+            "the variant name \"{}\" is invalid (allowed characters are A-Z, a-z, 0-9, -, _, and ., and the name cannot start with .)",
+            variant
+        ));
+    }
+    Ok(())
+}
+
+// Returns the path of a recording file (`extension` is one of RECORDING_FILES_EXTENSIONS),
+// with the .write suffix of files that are being written if `write` is true.
+pub fn recording_path(
+    data_directory: &std::path::Path,
+    name: &str,
+    extension: &str,
+    write: bool,
+) -> std::path::PathBuf {
+    data_directory.join(RECORDINGS_DIRECTORY_NAME).join(format!(
+        "{}{}{}",
+        name,
+        extension,
+        if write { ".write" } else { "" }
+    ))
+}
+
+// Returns the directory of a conversion variant (the empty variant is the default conversion).
+pub fn converted_directory(data_directory: &std::path::Path, variant: &str) -> std::path::PathBuf {
+    let converted_recordings_directory = data_directory.join(CONVERTED_RECORDINGS_DIRECTORY_NAME);
+    if variant.is_empty() {
+        converted_recordings_directory
+    } else {
+        converted_recordings_directory.join(variant)
+    }
+}
+
+// Returns the names and directories of the (non-default) conversion variants.
+pub fn variants_directories(
+    data_directory: &std::path::Path,
+) -> Result<Vec<(String, std::path::PathBuf)>, std::io::Error> {
+    let mut variants_directories = Vec::new();
+    if let Ok(entries) = data_directory
+        .join(CONVERTED_RECORDINGS_DIRECTORY_NAME)
+        .read_dir()
+    {
+        for entry in entries {
+            let entry = entry?;
+            let variant = entry.file_name().to_string_lossy().to_string();
+            if !variant.starts_with('.') && entry.file_type()?.is_dir() {
+                variants_directories.push((variant, entry.path()));
+            }
+        }
+    }
+    variants_directories.sort();
+    Ok(variants_directories)
+}
+
 pub fn read_recordings<HandleError>(
     data_directory: &std::path::PathBuf,
     recordings: &mut Vec<protocol::Recording>,
@@ -165,6 +916,30 @@ pub fn read_recordings<HandleError>(
         false,
         &mut handle_error,
     );
+    let mut stem_to_variants: std::collections::HashMap<String, Vec<String>> =
+        std::collections::HashMap::new();
+    match variants_directories(data_directory) {
+        Ok(variants_directories) => {
+            for (variant, directory) in variants_directories {
+                for (stem, converted_files_statuses) in read_stem_to_files_statuses(
+                    &directory,
+                    &CONVERTED_FILES_EXTENSIONS,
+                    false,
+                    &mut handle_error,
+                ) {
+                    if matches!(converted_files_statuses[0], FileStatus::Complete { .. }) {
+                        stem_to_variants
+                            .entry(stem)
+                            .or_default()
+                            .push(variant.clone());
+                    }
+                }
+            }
+        }
+        Err(error) => {
+            handle_error(error.into());
+        }
+    }
     recordings.reserve(stem_to_recording_files_statuses.len());
     for (name, recording_file_statuses) in stem_to_recording_files_statuses.into_iter() {
         let (mut state, _) = recording_file_statuses.iter().fold(
@@ -172,6 +947,7 @@ pub fn read_recordings<HandleError>(
                 protocol::RecordingState::Complete {
                     size_bytes: 0,
                     zip: false,
+                    variants: Vec::new(),
                 },
                 0,
             ),
@@ -199,6 +975,7 @@ pub fn read_recordings<HandleError>(
                             Complete { .. } => Complete {
                                 size_bytes,
                                 zip: false,
+                                variants: Vec::new(),
                             },
                             _ => unreachable!(),
                         },
@@ -208,16 +985,35 @@ pub fn read_recordings<HandleError>(
             },
         );
         match state {
-            protocol::RecordingState::Complete { ref mut zip, .. } => {
+            protocol::RecordingState::Complete {
+                ref mut zip,
+                ref mut variants,
+                ..
+            } => {
                 if let Some(converted_files_statuses) = stem_to_converted_files_statuses.get(&name)
                 {
                     *zip = matches!(converted_files_statuses[0], FileStatus::Complete { .. });
                 }
+                if let Some(stem_variants) = stem_to_variants.remove(&name) {
+                    *variants = stem_variants;
+                }
             }
-            protocol::RecordingState::Ongoing | protocol::RecordingState::Incomplete {..} => {}
+            protocol::RecordingState::Ongoing | protocol::RecordingState::Incomplete { .. } => {}
             _ => unreachable!(),
         }
-        recordings.push(protocol::Recording { name, state });
+        let summary = match state {
+            // unreadable statistics are treated as missing (and recomputed)
+            protocol::RecordingState::Complete { .. } => statistics::read(data_directory, &name)
+                .ok()
+                .flatten()
+                .map(|statistics| statistics.summary()),
+            _ => None,
+        };
+        recordings.push(protocol::Recording {
+            name,
+            state,
+            summary,
+        });
     }
     recordings.sort_by(|a, b| a.name.cmp(&b.name));
 }
@@ -236,12 +1032,81 @@ fn zip_options(
     options.clone()
 }
 
-fn read_header(
+fn write_string(header: &mut Vec<u8>, value: &str) -> Result<(), std::io::Error> {
+    let length = u16::try_from(value.len()).map_err(|_| {
+        std::io::Error::other(format!("\"{}\" is too long for a file header", value))
+    })?;
+    header.extend_from_slice(&length.to_le_bytes());
+    header.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+// Writes a v1 header and returns its length.
+pub fn write_header(
+    file: &mut impl Write,
+    signature: &str,
+    file_type: u8,
+    description: &Description,
+) -> Result<u64, std::io::Error> {
+    let mut header = Vec::with_capacity(signature.len() + V1_HEADER_MINIMUM_LENGTH + 64);
+    header.extend_from_slice(signature.as_bytes());
+    header.push(1u8); // version
+    header.push(file_type);
+    header.extend_from_slice(&0u32.to_le_bytes()); // header length, set below
+    header.extend_from_slice(&description.width.to_le_bytes());
+    header.extend_from_slice(&description.height.to_le_bytes());
+    header.extend_from_slice(&description.start_system_time.to_le_bytes());
+    header.extend_from_slice(&description.time_reference_system_time.to_le_bytes());
+    write_string(&mut header, &description.device_name)?;
+    write_string(&mut header, &description.device_serial)?;
+    let length = (header.len() + 4) as u32;
+    header[signature.len() + 2..signature.len() + 6].copy_from_slice(&length.to_le_bytes());
+    let checksum = crate::crc32c::checksum(&header);
+    header.extend_from_slice(&checksum.to_le_bytes());
+    file.write_all(&header)?;
+    Ok(header.len() as u64)
+}
+
+fn take<'a>(data: &'a [u8], offset: &mut usize, length: usize) -> Result<&'a [u8], anyhow::Error> {
+    if *offset + length > data.len() {
+        return Err(anyhow::anyhow!("the header is truncated"));
+    }
+    *offset += length;
+    Ok(&data[*offset - length..*offset])
+}
+
+fn parse_description(data: &[u8]) -> Result<Description, anyhow::Error> {
+    let mut offset = 0;
+    let width = u16::from_le_bytes(take(data, &mut offset, 2)?.try_into().expect("2 bytes"));
+    let height = u16::from_le_bytes(take(data, &mut offset, 2)?.try_into().expect("2 bytes"));
+    let start_system_time =
+        u64::from_le_bytes(take(data, &mut offset, 8)?.try_into().expect("8 bytes"));
+    let time_reference_system_time =
+        u64::from_le_bytes(take(data, &mut offset, 8)?.try_into().expect("8 bytes"));
+    let device_name_length =
+        u16::from_le_bytes(take(data, &mut offset, 2)?.try_into().expect("2 bytes"));
+    let device_name =
+        str::from_utf8(take(data, &mut offset, device_name_length as usize)?)?.to_owned();
+    let device_serial_length =
+        u16::from_le_bytes(take(data, &mut offset, 2)?.try_into().expect("2 bytes"));
+    let device_serial =
+        str::from_utf8(take(data, &mut offset, device_serial_length as usize)?)?.to_owned();
+    Ok(Description {
+        device_name,
+        device_serial,
+        width,
+        height,
+        start_system_time,
+        time_reference_system_time,
+    })
+}
+
+// Reads a v0 or v1 header, the file position is the first byte after the header on success.
+pub fn read_header(
     file_buffer: &mut Vec<u8>,
     file: &mut std::io::BufReader<std::fs::File>,
     expected_signature: &[u8],
-    expected_version: u8,
-) -> Result<u8, anyhow::Error> {
+) -> Result<Header, anyhow::Error> {
     file_buffer.clear();
     file_buffer.resize(expected_signature.len() + 2, 0u8);
     file.read_exact(file_buffer)?;
@@ -252,22 +1117,550 @@ fn read_header(
             &file_buffer[..expected_signature.len()],
         ));
     }
-    if file_buffer[expected_signature.len()] != expected_version {
+    let version = file_buffer[expected_signature.len()];
+    let file_type = file_buffer[expected_signature.len() + 1];
+    match version {
+        0 => Ok(Header {
+            version,
+            file_type,
+            length: file_buffer.len() as u64,
+            description: None,
+        }),
+        1 => {
+            file_buffer.resize(expected_signature.len() + 6, 0u8);
+            file.read_exact(&mut file_buffer[expected_signature.len() + 2..])?;
+            let length = u32::from_le_bytes(
+                file_buffer[expected_signature.len() + 2..expected_signature.len() + 6]
+                    .try_into()
+                    .expect("4 bytes"),
+            ) as usize;
+            // the length is checked before allocating (corrupted files may store any value)
+            let file_length = file.get_ref().metadata()?.len();
+            if length < expected_signature.len() + V1_HEADER_MINIMUM_LENGTH
+                || length as u64 > file_length
+            {
+                return Err(anyhow::anyhow!(
+                    "bad header length {} (the file has {} bytes)",
+                    length,
+                    file_length
+                ));
+            }
+            let start = file_buffer.len();
+            file_buffer.resize(length, 0u8);
+            file.read_exact(&mut file_buffer[start..])?;
+            let expected_checksum =
+                u32::from_le_bytes(file_buffer[length - 4..].try_into().expect("4 bytes"));
+            let checksum = crate::crc32c::checksum(&file_buffer[..length - 4]);
+            if checksum != expected_checksum {
+                return Err(anyhow::anyhow!(
+                    "bad header checksum (expected {:#010x}, got {:#010x})",
+                    expected_checksum,
+                    checksum
+                ));
+            }
+            Ok(Header {
+                version,
+                file_type,
+                length: length as u64,
+                description: Some(parse_description(
+                    &file_buffer[expected_signature.len() + 6..length - 4],
+                )?),
+            })
+        }
+        version => Err(anyhow::anyhow!("unsupported version {}", version)),
+    }
+}
+
+// Reads a raw file header and returns the sensor width and height.
+// v0 raw headers store the dimensions after the type, they are included in the header length.
+pub fn read_raw_header(
+    file_buffer: &mut Vec<u8>,
+    file: &mut std::io::BufReader<std::fs::File>,
+) -> Result<(Header, u16, u16), anyhow::Error> {
+    let mut header = read_header(file_buffer, file, RAW_FILE_SIGNATURE.as_bytes())?;
+    if header.file_type > 1 {
         return Err(anyhow::anyhow!(
-            "bad version (expected {}, got {}",
-            expected_version,
-            file_buffer[expected_signature.len()],
+            "unsupported raw file type {}",
+            header.file_type
         ));
     }
-    Ok(file_buffer[expected_signature.len() + 1])
+    match header.description.as_ref() {
+        Some(description) => {
+            let (width, height) = (description.width, description.height);
+            Ok((header, width, height))
+        }
+        None => {
+            // EVT3
+            file_buffer.clear();
+            file_buffer.resize(4, 0u8);
+            file.read_exact(file_buffer)?;
+            header.length += 4;
+            Ok((
+                header,
+                u16::from_le_bytes(file_buffer[0..2].try_into().expect("2 bytes")),
+                u16::from_le_bytes(file_buffer[2..4].try_into().expect("2 bytes")),
+            ))
+        }
+    }
+}
+
+type PartWriter = zip::ZipWriter<std::io::BufWriter<std::fs::File>>;
+
+// Parameters shared by the format passes of a conversion
+// Each pass reads the raw file with its own reader and runs on its own thread
+pub struct Pass<'a> {
+    pub data_directory: &'a std::path::Path,
+    pub name: &'a str,
+    pub convert_options: &'a protocol::ConvertOptions,
+    pub selection: Selection,
+    pub metadata: &'a toml::Value,
+    pub clock: &'a clock::Model,
+    pub options: zip::write::SimpleFileOptions,
+    pub cancelled: &'a std::sync::atomic::AtomicBool,
+    // set by a pass that fails, the other passes stop without cancelling the conversion
+    pub failed: &'a std::sync::atomic::AtomicBool,
+    pub progress: &'a Progress,
+}
+
+impl Pass<'_> {
+    // Returns true if the conversion was cancelled or if another pass failed.
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.load(std::sync::atomic::Ordering::Acquire)
+            || self.failed.load(std::sync::atomic::Ordering::Acquire)
+    }
+}
+
+// Temporary file for the entries written after the events (triggers and index),
+// so that conversions use a constant amount of memory regardless of the recording's duration
+pub struct Spill {
+    path: std::path::PathBuf,
+    file: std::io::BufWriter<std::fs::File>,
+}
+
+impl Spill {
+    // The file is named after the part file (NAME.part-INDEX.SUFFIX.write)
+    pub fn create(part_path: &std::path::Path, suffix: &str) -> Result<Self, anyhow::Error> {
+        let path = part_path.with_extension(format!("{suffix}.write"));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        Ok(Self {
+            path,
+            file: std::io::BufWriter::new(file),
+        })
+    }
+
+    pub fn copy_to(&mut self, output: &mut impl Write) -> Result<(), anyhow::Error> {
+        self.file.flush()?;
+        let file = self.file.get_mut();
+        file.seek(std::io::SeekFrom::Start(0))?;
+        std::io::copy(file, output)?;
+        Ok(())
+    }
+}
+
+impl Write for Spill {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buffer)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// Writes the events, triggers, and index CSV files. Returns false if the conversion was cancelled.
+fn write_csv(
+    zip: &mut PartWriter,
+    reader: &mut Reader,
+    pass: &Pass,
+    part_path: &std::path::Path,
+) -> Result<bool, anyhow::Error> {
+    let name = pass.name;
+    let selection = pass.selection;
+    let convert_options = pass.convert_options;
+
+    // polarity events (triggers and index rows are spilled)
+    zip.start_file(format!("{name}/{name}_events.csv"), pass.options)?;
+    let mut chain = filters::Chain::new(&convert_options.filters, reader.width, reader.height)?;
+    let csv_header = format!(
+        "t,x@{},y@{},on{}\n",
+        chain.width,
+        chain.height,
+        if convert_options.utc { ",utc" } else { "" }
+    );
+    zip.write_all(csv_header.as_bytes())?;
+    let mut triggers_spill = Spill::create(part_path, "triggers")?;
+    let mut index_spill = Spill::create(part_path, "index")?;
+    let mut triggers_bytes = Vec::new();
+    let mut events_bytes = Vec::new();
+    let mut index_bytes = Vec::new();
+    let mut csv_offset = csv_header.len() as u64;
+    reader.rewind()?;
+    while let Some(index_entry) = reader.next_packet()? {
+        pass.progress.add(index_entry.raw_length);
+        let system_time = index_entry.system_time;
+        let system_timestamp = index_entry.system_timestamp;
+        reader.convert(
+            |dvs_event| {
+                let t = dvs_event.t; // unpack field
+                let x = dvs_event.x; // unpack field
+                let y = dvs_event.y; // unpack field
+                let on = dvs_event.polarity as u8; // unpack field
+                if !selection.contains_t(t) {
+                    return;
+                }
+                if let Some((x, y)) = chain.filter(t, x, y, on == 1) {
+                    if convert_options.utc {
+                        csv::push_row(
+                            &mut events_bytes,
+                            &[t, x as u64, y as u64, on as u64, pass.clock.utc(t)],
+                        );
+                    } else {
+                        csv::push_row(&mut events_bytes, &[t, x as u64, y as u64, on as u64]);
+                    }
+                }
+            },
+            |trigger_event| {
+                let t = trigger_event.t; // unpack field
+                let id = trigger_event.id; // unpack field
+                let rising = trigger_event.polarity as u8; // unpack field
+                if !convert_options.triggers || !selection.contains_t(t) {
+                    return;
+                }
+                if convert_options.utc {
+                    csv::push_row(
+                        &mut triggers_bytes,
+                        &[
+                            system_time,
+                            system_timestamp,
+                            t,
+                            id as u64,
+                            rising as u64,
+                            pass.clock.utc(t),
+                        ],
+                    );
+                } else {
+                    csv::push_row(
+                        &mut triggers_bytes,
+                        &[system_time, system_timestamp, t, id as u64, rising as u64],
+                    );
+                }
+            },
+        );
+        zip.write_all(&events_bytes)?;
+        triggers_spill.write_all(&triggers_bytes)?;
+        if convert_options.index {
+            csv::push_row(
+                &mut index_bytes,
+                &[
+                    system_time,
+                    system_timestamp,
+                    index_entry.first_after_overflow as u64,
+                    index_entry.state.t,
+                    csv_offset,
+                ],
+            );
+            index_spill.write_all(&index_bytes)?;
+            index_bytes.clear();
+        }
+        csv_offset += events_bytes.len() as u64;
+        events_bytes.clear();
+        triggers_bytes.clear();
+        if pass.cancelled() {
+            return Ok(false);
+        }
+    }
+
+    // trigger events
+    if convert_options.triggers {
+        zip.start_file(format!("{name}/{name}_triggers.csv"), pass.options)?;
+        zip.write_all(
+            format!(
+                "system_time,system_timestamp,t,id,rising{}\n",
+                if convert_options.utc { ",utc" } else { "" }
+            )
+            .as_bytes(),
+        )?;
+        triggers_spill.copy_to(zip)?;
+    }
+    drop(triggers_spill);
+    if pass.cancelled() {
+        return Ok(false);
+    }
+
+    // index
+    if convert_options.index {
+        zip.start_file(format!("{name}/{name}_index.csv"), pass.options)?;
+        zip.write_all(b"system_time,system_timestamp,first_after_overflow,t,offset\n")?;
+        index_spill.copy_to(zip)?;
+    }
+    Ok(true)
+}
+
+// Writes the Event Stream files. Returns false if the conversion was cancelled.
+fn write_event_stream(
+    zip: &mut PartWriter,
+    reader: &mut Reader,
+    pass: &Pass,
+    part_path: &std::path::Path,
+) -> Result<bool, anyhow::Error> {
+    let name = pass.name;
+    let selection = pass.selection;
+    let convert_options = pass.convert_options;
+    reader.rewind()?;
+    zip.start_file(
+        format!("{name}/{name}_events{}", event_stream::FILE_EXTENSION),
+        pass.options,
+    )?;
+    let mut chain = filters::Chain::new(&convert_options.filters, reader.width, reader.height)?;
+    event_stream::write_dvs_header(zip, chain.width, chain.height)?;
+    let mut dvs_encoder = event_stream::DvsEncoder::new();
+    let mut trigger_encoder = event_stream::GenericEncoder::new();
+    let mut events_bytes = Vec::new();
+    let mut triggers_bytes = Vec::new();
+    let mut triggers_spill = Spill::create(part_path, "triggers")?;
+    event_stream::write_generic_header(&mut triggers_spill)?;
+    while let Some(index_entry) = reader.next_packet()? {
+        pass.progress.add(index_entry.raw_length);
+        reader.convert(
+            |dvs_event| {
+                let t = dvs_event.t; // unpack field
+                let x = dvs_event.x; // unpack field
+                let y = dvs_event.y; // unpack field
+                let on = dvs_event.polarity as u8 == 1; // unpack field
+                if !selection.contains_t(t) {
+                    return;
+                }
+                if let Some((x, y)) = chain.filter(t, x, y, on) {
+                    dvs_encoder.encode(t, x, y, on, &mut events_bytes);
+                }
+            },
+            |trigger_event| {
+                let t = trigger_event.t; // unpack field
+                let id = trigger_event.id; // unpack field
+                let rising = trigger_event.polarity as u8; // unpack field
+                if convert_options.triggers && selection.contains_t(t) {
+                    trigger_encoder.encode(t, &[id, rising], &mut triggers_bytes);
+                }
+            },
+        );
+        zip.write_all(&events_bytes)?;
+        triggers_spill.write_all(&triggers_bytes)?;
+        events_bytes.clear();
+        triggers_bytes.clear();
+        if pass.cancelled() {
+            return Ok(false);
+        }
+    }
+    if convert_options.triggers {
+        zip.start_file(
+            format!("{name}/{name}_triggers{}", event_stream::FILE_EXTENSION),
+            pass.options,
+        )?;
+        triggers_spill.copy_to(zip)?;
+    }
+    Ok(true)
+}
+
+// Writes the AEDAT4 file. Returns false if the conversion was cancelled.
+fn write_aedat4(
+    zip: &mut PartWriter,
+    reader: &mut Reader,
+    pass: &Pass,
+) -> Result<bool, anyhow::Error> {
+    let name = pass.name;
+    let selection = pass.selection;
+    let convert_options = pass.convert_options;
+    reader.rewind()?;
+    // AEDAT4 timestamps are µs since the Unix epoch
+    let ts_offset = match reader.peek_index_entry()? {
+        Some(index_entry) => index_entry.system_time as i64 - index_entry.state.t as i64,
+        None => 0,
+    };
+    let mut chain = filters::Chain::new(&convert_options.filters, reader.width, reader.height)?;
+    zip.start_file(
+        format!("{name}/{name}{}", aedat4::FILE_EXTENSION),
+        pass.options,
+    )?;
+    aedat4::write_header(
+        zip,
+        chain.width,
+        chain.height,
+        &reader.raw_header.description.as_ref().map_or_else(
+            || name.to_owned(),
+            |description| format!("{}_{}", description.device_name, description.device_serial),
+        ),
+        ts_offset,
+    )?;
+    let mut events_packet = aedat4::Packet::events();
+    let mut triggers_packet = aedat4::Packet::triggers();
+    while let Some(index_entry) = reader.next_packet()? {
+        pass.progress.add(index_entry.raw_length);
+        reader.convert(
+            |dvs_event| {
+                let t = dvs_event.t; // unpack field
+                let x = dvs_event.x; // unpack field
+                let y = dvs_event.y; // unpack field
+                let on = dvs_event.polarity as u8 == 1; // unpack field
+                if !selection.contains_t(t) {
+                    return;
+                }
+                if let Some((x, y)) = chain.filter(t, x, y, on) {
+                    events_packet.push_event(t as i64 + ts_offset, x, y, on);
+                }
+            },
+            |trigger_event| {
+                let t = trigger_event.t; // unpack field
+                let rising = trigger_event.polarity as u8 == 1; // unpack field
+                if convert_options.triggers && selection.contains_t(t) {
+                    triggers_packet.push_trigger(t as i64 + ts_offset, rising);
+                }
+            },
+        );
+        events_packet.write_to(zip)?;
+        triggers_packet.write_to(zip)?;
+        if pass.cancelled() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Writes the Metavision RAW file (the EVT3 data is copied). Returns false if the conversion was cancelled.
+fn write_metavision_raw(
+    zip: &mut PartWriter,
+    reader: &mut Reader,
+    pass: &Pass,
+) -> Result<bool, anyhow::Error> {
+    let name = pass.name;
+    // the EVT3 data is not decoded, hence ranges select whole USB packets
+    reader.rewind()?;
+    let serial = pass
+        .metadata
+        .get("device")
+        .and_then(|device| device.get("serial"))
+        .and_then(|serial| serial.as_str())
+        .unwrap_or("");
+    let date = pass
+        .metadata
+        .get("timestamp")
+        .and_then(|timestamp| timestamp.as_str())
+        .and_then(|timestamp| chrono::DateTime::parse_from_rfc3339(timestamp).ok())
+        .map_or_else(String::new, |datetime| {
+            datetime.format("%Y-%m-%d %H:%M:%S").to_string()
+        });
+    zip.start_file(
+        format!("{name}/{name}{}", metavision::FILE_EXTENSION),
+        pass.options,
+    )?;
+    metavision::write_evt3_header(zip, reader.width, reader.height, serial, &date)?;
+    // a selection that starts after the first packet may depend on the state left by the
+    // packets before it (the first packet starts with the camera's initial state)
+    if reader.next_index() > 0 {
+        if let Some(index_entry) = reader.peek_index_entry()? {
+            metavision::write_evt3_state(zip, &index_entry.state)?;
+        }
+    }
+    while let Some(index_entry) = reader.next_packet()? {
+        pass.progress.add(index_entry.raw_length);
+        zip.write_all(reader.raw())?;
+        if pass.cancelled() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Runs the pass of `format`, whose output is a zip file (or a .npz file) at `part_path`.
+// Returns false if the conversion was cancelled.
+fn write_part(
+    format: Format,
+    part_path: &std::path::PathBuf,
+    pass: &Pass,
+) -> Result<bool, anyhow::Error> {
+    let mut reader = Reader::open(pass.data_directory, pass.name)?;
+    select(&mut reader, pass.convert_options.range.as_ref())?;
+    if format == Format::Npz {
+        return npz::write(&mut reader, pass, part_path);
+    }
+    let mut zip = zip::ZipWriter::new(std::io::BufWriter::new(std::fs::File::create(part_path)?));
+    let completed = match format {
+        Format::Csv => write_csv(&mut zip, &mut reader, pass, part_path)?,
+        Format::Es => write_event_stream(&mut zip, &mut reader, pass, part_path)?,
+        Format::Aedat4 => write_aedat4(&mut zip, &mut reader, pass)?,
+        Format::MetavisionRaw => write_metavision_raw(&mut zip, &mut reader, pass)?,
+        Format::Arrow => columnar::write(&mut zip, &mut reader, pass, part_path)?,
+        Format::Y4m | Format::Png => video::write(
+            &mut zip,
+            &mut reader,
+            pass,
+            if format == Format::Y4m {
+                video::Container::Y4m
+            } else {
+                video::Container::Png
+            },
+        )?,
+        Format::Npz => unreachable!(),
+    };
+    zip.finish()?;
+    Ok(completed)
+}
+
+// Copies the parts' files to the converted recording (without decompressing them).
+fn merge_parts(
+    zip: &mut PartWriter,
+    name: &str,
+    formats: &[Format],
+    parts_paths: &[std::path::PathBuf],
+    options: &zip::write::SimpleFileOptions,
+) -> Result<(), anyhow::Error> {
+    for (format, part_path) in formats.iter().zip(parts_paths.iter()) {
+        if *format == Format::Npz {
+            // NumPy archives are zip files, they are stored as is
+            let mut npz_file = std::fs::File::open(part_path)?;
+            zip.start_file(
+                format!("{name}/{name}{}", npz::FILE_EXTENSION),
+                zip_options(
+                    &npz_file.metadata()?,
+                    &options
+                        .compression_method(zip::CompressionMethod::Stored)
+                        .compression_level(None),
+                ),
+            )?;
+            std::io::copy(&mut npz_file, zip)?;
+        } else {
+            let mut archive =
+                zip::ZipArchive::new(std::io::BufReader::new(std::fs::File::open(part_path)?))?;
+            for index in 0..archive.len() {
+                zip.raw_copy_file(archive.by_index_raw(index)?)?;
+            }
+        }
+    }
+    Ok(())
 }
 
 pub fn convert(
     data_directory: &std::path::PathBuf,
     name: &str,
+    convert_options: &protocol::ConvertOptions,
     cancelled: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    progress: std::sync::Arc<Progress>,
 ) -> Result<(), anyhow::Error> {
-    let converted_recordings_directory = data_directory.join(CONVERTED_RECORDINGS_DIRECTORY_NAME);
+    check_variant(&convert_options.variant)?;
+    video::check_options(&convert_options.video)?;
+    let formats = &convert_options.formats;
+    let converted_recordings_directory =
+        converted_directory(data_directory, &convert_options.variant);
     std::fs::create_dir_all(&converted_recordings_directory)?;
     let converted_path =
         converted_recordings_directory.join(format!("{}{}", name, ZIP_FILE_EXTENSION));
@@ -284,22 +1677,66 @@ pub fn convert(
         let mut zip = zip::ZipWriter::new(std::io::BufWriter::new(std::fs::File::create(
             &converted_write_path,
         )?));
-        let options = zip::write::SimpleFileOptions::default()
-            .compression_method(zip::CompressionMethod::Deflated)
-            .compression_level(Some(6))
-            .large_file(true);
+        let options = if convert_options.compression_level == 0 {
+            zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Stored)
+                .compression_level(None)
+        } else {
+            zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated)
+                .compression_level(Some(convert_options.compression_level.min(9) as i64))
+        }
+        .large_file(true);
         zip.add_directory(name, options.clone())?;
+        let mut reader = Reader::open(data_directory, name)?;
+        let selection = select(&mut reader, convert_options.range.as_ref())?;
+        // each format reads the raw file once, except NumPy archives (counting and data passes)
+        let passes = formats
+            .iter()
+            .map(|format| if *format == Format::Npz { 2 } else { 1 })
+            .sum::<u64>();
+        // the statistics describe the whole recording, they are computed first if missing
+        let statistics = statistics::read(data_directory, name).ok().flatten();
+        if statistics.is_none() {
+            progress.set_total_bytes(reader.range_length()? * passes + reader.length()?);
+        } else {
+            progress.set_total_bytes(reader.range_length()? * passes);
+        }
+        let clock = clock::Model::fit(&mut reader)?;
         let mut file_buffer = Vec::new();
 
         // convert metadata file
-        {
+        let metadata = {
             let path = recordings_directory.join(format!("{}{}", name, METADATA_FILE_EXTENSION));
             let file_metadata = path.metadata()?;
             let mut file = std::fs::File::open(recordings_directory.join(&path))?;
             file_buffer.clear();
             file_buffer.reserve(file_metadata.len() as usize);
             file.read_to_end(&mut file_buffer)?;
-            let metadata = toml::from_str::<toml::Value>(str::from_utf8(&file_buffer)?)?;
+            let mut metadata = toml::from_str::<toml::Value>(str::from_utf8(&file_buffer)?)?;
+
+            // v1 raw files describe the device, the description is copied to the JSON file
+            if let (Some(description), Some(table)) = (
+                reader.raw_header.description.clone(),
+                metadata.as_table_mut(),
+            ) {
+                table.insert(
+                    "header".to_owned(),
+                    toml::Value::try_from(HeaderMetadata {
+                        version: reader.raw_header.version,
+                        description,
+                    })?,
+                );
+            }
+
+            // the options are stored with the converted files to tell variants apart
+            if let Some(table) = metadata.as_table_mut() {
+                table.insert(
+                    "conversion".to_owned(),
+                    toml::Value::try_from(convert_options)?,
+                );
+                table.insert("clock".to_owned(), toml::Value::try_from(&clock)?);
+            }
             if cancelled.load(std::sync::atomic::Ordering::Acquire) {
                 return Ok(());
             }
@@ -311,233 +1748,117 @@ pub fn convert(
             let mut serializer = serde_json::Serializer::with_formatter(&mut zip, formatter);
             metadata.serialize(&mut serializer)?;
             zip.write_all(b"\n")?;
-        }
+            metadata
+        };
         if cancelled.load(std::sync::atomic::Ordering::Acquire) {
             return Ok(());
         }
 
-        // convert index file and raw events
+        // convert statistics file
         {
-            let raw_path = recordings_directory.join(format!("{}{}", name, RAW_FILE_EXTENSION));
-            let file_metadata = raw_path.metadata()?;
-            let mut raw_file = std::io::BufReader::new(std::fs::File::open(&raw_path)?);
-            let raw_file_type = read_header(
-                &mut file_buffer,
-                &mut raw_file,
-                RAW_FILE_SIGNATURE.as_bytes(),
-                0u8,
-            )?;
-            let index_path = recordings_directory.join(format!("{}{}", name, INDEX_FILE_EXTENSION));
-            let mut index_file = std::io::BufReader::new(std::fs::File::open(&index_path)?);
-            let index_file_type = read_header(
-                &mut file_buffer,
-                &mut index_file,
-                INDEX_FILE_SIGNATURE.as_bytes(),
-                0u8,
+            let statistics = match statistics {
+                Some(statistics) => statistics,
+                None => {
+                    let statistics = statistics::compute(data_directory, name, &progress)?;
+                    statistics::write(data_directory, name, &statistics)?;
+                    statistics
+                }
+            };
+            let path = statistics::path(data_directory, name);
+            zip.start_file(
+                format!("{name}/{name}_statistics.json"),
+                zip_options(&path.metadata()?, &options),
             )?;
-            if raw_file_type != index_file_type {
-                return Err(anyhow::anyhow!(
-                    "the index and raw types are different (raw is {}, index is {})",
-                    raw_file_type,
-                    index_file_type,
-                ));
-            }
-            match raw_file_type {
-                // EVT3
-                0 => {
-                    file_buffer.clear();
-                    file_buffer.resize(4, 0u8);
-                    raw_file.read_exact(&mut file_buffer)?;
-                    let width = u16::from_le_bytes(file_buffer[0..2].try_into().expect("2 bytes"));
-                    let height = u16::from_le_bytes(file_buffer[2..4].try_into().expect("2 bytes"));
+            let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+            let mut serializer = serde_json::Serializer::with_formatter(&mut zip, formatter);
+            statistics.serialize(&mut serializer)?;
+            zip.write_all(b"\n")?;
+        }
+        if cancelled.load(std::sync::atomic::Ordering::Acquire) {
+            return Ok(());
+        }
 
-                    // polarity events
-                    if cancelled.load(std::sync::atomic::Ordering::Acquire) {
-                        return Ok(());
-                    }
-                    zip.start_file(
-                        format!("{name}/{name}_events.csv"),
-                        zip_options(&file_metadata, &options),
-                    )?;
-                    let csv_header = format!("t,x@{width},y@{height},on\n");
-                    zip.write_all(csv_header.as_bytes())?;
-                    let mut index_data = [0u8; 54];
-                    let mut raw_file_position = RAW_FILE_SIGNATURE.len() as u64 + 6;
-                    let mut previous_adapter: Option<
-                        neuromorphic_drivers::adapters::evt3::Adapter,
-                    > = None;
-                    let mut triggers_bytes = Vec::new();
-                    let mut events_bytes = Vec::new();
-                    let mut csv_offset = csv_header.len() as u64;
-                    let mut csv_offsets = Vec::new();
-                    loop {
-                        match index_file.read_exact(&mut index_data) {
-                            Ok(()) => {
-                                let system_time = u64::from_le_bytes(
-                                    index_data[0..8].try_into().expect("8 bytes"),
-                                );
-                                let system_timestamp = u64::from_le_bytes(
-                                    index_data[8..16].try_into().expect("8 bytes"),
-                                );
-                                let _first_after_overflow = index_data[16] == 1;
-                                let raw_file_offset = u64::from_le_bytes(
-                                    index_data[17..25].try_into().expect("8 bytes"),
-                                );
-                                let raw_length = u64::from_le_bytes(
-                                    index_data[25..33].try_into().expect("8 bytes"),
-                                );
-                                let state = neuromorphic_drivers::adapters::evt3::State {
-                                    t: u64::from_le_bytes(
-                                        index_data[33..41].try_into().expect("8 bytes"),
-                                    ),
-                                    overflows: u32::from_le_bytes(
-                                        index_data[41..45].try_into().expect("4 bytes"),
-                                    ),
-                                    previous_msb_t: u16::from_le_bytes(
-                                        index_data[45..47].try_into().expect("2 bytes"),
-                                    ),
-                                    previous_lsb_t: u16::from_le_bytes(
-                                        index_data[47..49].try_into().expect("2 bytes"),
-                                    ),
-                                    x: u16::from_le_bytes(
-                                        index_data[49..51].try_into().expect("2 bytes"),
-                                    ),
-                                    y: u16::from_le_bytes(
-                                        index_data[51..53].try_into().expect("2 bytes"),
-                                    ),
-                                    polarity: if index_data[53] == 1 {
-                                        neuromorphic_drivers::types::Polarity::On
-                                    } else {
-                                        neuromorphic_drivers::types::Polarity::Off
-                                    },
-                                };
-                                if raw_file_offset != raw_file_position {
-                                    return Err(anyhow::anyhow!(
-                                        "Position mismatch (the raw file is at position {} but the index points to {})",
-                                        raw_file_position,
-                                        raw_file_offset
-                                    ));
-                                }
-                                let mut adapter = neuromorphic_drivers::adapters::evt3::Adapter::from_dimensions_and_state(width, height, state);
-                                if let Some(previous_adapter) = previous_adapter.as_ref() {
-                                    if adapter.state() != previous_adapter.state() {
-                                        return Err(anyhow::anyhow!(
-                                            "State mismatch (the raw file's state is {:?} but the index contains {:?})",
-                                            previous_adapter.state(),
-                                            adapter.state()
-                                        ));
-                                    }
-                                }
-                                file_buffer.clear();
-                                file_buffer.resize(raw_length as usize, 0u8);
-                                raw_file.read_exact(&mut file_buffer)?;
-                                raw_file_position += raw_length;
-                                adapter.convert(
-                                    &file_buffer,
-                                    |dvs_event| {
-                                        let t = dvs_event.t; // unpack field
-                                        let x = dvs_event.x; // unpack field
-                                        let y = dvs_event.y; // unpack field
-                                        let on = dvs_event.polarity as u8; // unpack field
-                                        events_bytes
-                                            .extend(format!("{t},{x},{y},{on}\n").as_bytes());
-                                    },
-                                    |trigger_event| {
-                                        let t = trigger_event.t; // unpack field
-                                        let id = trigger_event.id; // unpack field
-                                        let rising = trigger_event.polarity as u8; // unpack field
-                                        triggers_bytes.extend(
-                                            format!("{system_time},{system_timestamp},{t},{id},{rising}\n")
-                                                .as_bytes(),
-                                        );
-                                    },
-                                );
-                                zip.write_all(&events_bytes)?;
-                                csv_offsets.push(csv_offset);
-                                csv_offset += events_bytes.len() as u64;
-                                events_bytes.clear();
-                                previous_adapter = Some(adapter);
-                            }
-                            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
-                                break;
-                            }
-                            Err(error) => {
-                                return Err(error.into());
-                            }
-                        }
-                        if cancelled.load(std::sync::atomic::Ordering::Acquire) {
-                            return Ok(());
+        // events, triggers, and index (one thread per format, compression runs in parallel)
+        // each thread writes a temporary zip file, whose compressed files are then copied
+        let raw_file_metadata = recordings_directory
+            .join(format!("{}{}", name, RAW_FILE_EXTENSION))
+            .metadata()?;
+        let pass = Pass {
+            data_directory,
+            name,
+            convert_options,
+            selection,
+            metadata: &metadata,
+            clock: &clock,
+            options: zip_options(&raw_file_metadata, &options),
+            cancelled: &cancelled,
+            failed: &std::sync::atomic::AtomicBool::new(false),
+            progress: &progress,
+        };
+        let parts_paths = (0..formats.len())
+            .map(|index| {
+                converted_recordings_directory.join(format!("{}.part-{}.write", name, index))
+            })
+            .collect::<Vec<_>>();
+        let results = std::thread::scope(|scope| {
+            let handles = formats
+                .iter()
+                .zip(parts_paths.iter())
+                .map(|(format, part_path)| {
+                    let pass = &pass;
+                    scope.spawn(move || {
+                        let result = write_part(*format, part_path, pass);
+                        if result.is_err() {
+                            // the other passes are stopped since the conversion failed
+                            pass.failed
+                                .store(true, std::sync::atomic::Ordering::Release);
                         }
-                    }
-
-                    // trigger events
-                    if cancelled.load(std::sync::atomic::Ordering::Acquire) {
-                        return Ok(());
-                    }
-                    zip.start_file(
-                        format!("{name}/{name}_triggers.csv"),
-                        zip_options(&file_metadata, &options),
-                    )?;
-                    zip.write_all(
-                        format!("system_time,system_timestamp,t,id,rising\n").as_bytes(),
-                    )?;
-                    zip.write_all(&triggers_bytes)?;
-                    drop(triggers_bytes);
-
-                    // index
-                    if cancelled.load(std::sync::atomic::Ordering::Acquire) {
-                        return Ok(());
-                    }
-                    zip.start_file(
-                        format!("{name}/{name}_index.csv"),
-                        zip_options(&file_metadata, &options),
-                    )?;
-                    zip.write_all(
-                        format!("system_time,system_timestamp,first_after_overflow,t,offset\n")
-                            .as_bytes(),
-                    )?;
-                    index_file.seek(std::io::SeekFrom::Start(
-                        INDEX_FILE_SIGNATURE.len() as u64 + 2,
-                    ))?;
-                    for csv_offset in csv_offsets.into_iter() {
-                        index_file.read_exact(&mut index_data)?;
-                        let system_time =
-                            u64::from_le_bytes(index_data[0..8].try_into().expect("8 bytes"));
-                        let system_timestamp =
-                            u64::from_le_bytes(index_data[8..16].try_into().expect("8 bytes"));
-                        let first_after_overflow = index_data[16];
-                        let t = u64::from_le_bytes(index_data[33..41].try_into().expect("8 bytes"));
-                        zip.write_all(
-                            format!("{system_time},{system_timestamp},{first_after_overflow},{t},{csv_offset}\n")
-                                .as_bytes(),
-                        )?;
-                    }
+                        result
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("a conversion thread panicked")))
+                })
+                .collect::<Vec<_>>()
+        });
+        let result = results
+            .into_iter()
+            .try_fold(true, |completed, result| {
+                result.map(|part_completed| completed && part_completed)
+            })
+            .and_then(|completed| -> Result<bool, anyhow::Error> {
+                if completed {
+                    merge_parts(&mut zip, name, formats, &parts_paths, &options)?;
                 }
-                _ => {
-                    return Err(anyhow::anyhow!(
-                        "unsupported raw file type {}",
-                        raw_file_type
-                    ));
-                }
-            }
+                Ok(completed)
+            });
+        for part_path in parts_paths.iter() {
+            let _ = std::fs::remove_file(part_path);
+        }
+        if !result? {
+            return Ok(());
         }
         if cancelled.load(std::sync::atomic::Ordering::Acquire) {
             return Ok(());
         }
 
         // convert samples file
-        {
+        if convert_options.samples {
             let samples_path =
                 recordings_directory.join(format!("{}{}", name, SAMPLES_FILE_EXTENSION));
             let file_metadata = samples_path.metadata()?;
             let mut samples_file = std::io::BufReader::new(std::fs::File::open(&samples_path)?);
-            let samples_file_type = read_header(
+            let samples_header = read_header(
                 &mut file_buffer,
                 &mut samples_file,
                 SAMPLES_FILE_SIGNATURE.as_bytes(),
-                0u8,
             )?;
-            match samples_file_type {
+            match samples_header.file_type {
                 // Prophesee EVK4
                 0 => {
                     if cancelled.load(std::sync::atomic::Ordering::Acquire) {
@@ -548,34 +1869,27 @@ pub fn convert(
                         zip_options(&file_metadata, &options),
                     )?;
                     zip.write_all(
-                        format!(
-                            "system_time,system_timestamp,illuminance_lux,temperature_celsius\n"
-                        )
-                        .as_bytes(),
+                        b"system_time,system_timestamp,illuminance_lux,temperature_celsius\n",
                     )?;
-                    file_buffer.clear();
-                    file_buffer.resize(24, 0u8);
+                    let mut sample_data = [0u8; EVK4_SAMPLE_LENGTH];
+                    let mut sample_bytes = Vec::new();
                     loop {
-                        match samples_file.read_exact(&mut file_buffer) {
+                        match samples_file.read_exact(&mut sample_data) {
                             Ok(()) => {
-                                zip.write_all(
-                                    format!(
-                                        "{},{},{},{}\n",
-                                        u64::from_le_bytes(
-                                            file_buffer[0..8].try_into().expect("8 bytes")
-                                        ),
-                                        u64::from_le_bytes(
-                                            file_buffer[8..16].try_into().expect("8 bytes")
-                                        ),
-                                        f32::from_le_bytes(
-                                            file_buffer[16..20].try_into().expect("4 bytes")
-                                        ),
-                                        f32::from_le_bytes(
-                                            file_buffer[20..24].try_into().expect("4 bytes")
-                                        ),
-                                    )
-                                    .as_bytes(),
-                                )?;
+                                let sample = Evk4Sample::from_bytes(&sample_data);
+                                if !selection.contains_system_time(sample.system_time) {
+                                    continue;
+                                }
+                                sample_bytes.clear();
+                                csv::push_u64(&mut sample_bytes, sample.system_time);
+                                sample_bytes.push(b',');
+                                csv::push_u64(&mut sample_bytes, sample.system_timestamp);
+                                sample_bytes.push(b',');
+                                csv::push_f32(&mut sample_bytes, sample.illuminance);
+                                sample_bytes.push(b',');
+                                csv::push_f32(&mut sample_bytes, sample.temperature);
+                                sample_bytes.push(b'\n');
+                                zip.write_all(&sample_bytes)?;
                             }
                             Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
                                 break;
@@ -589,7 +1903,7 @@ pub fn convert(
                 _ => {
                     return Err(anyhow::anyhow!(
                         "unsupported samples file type {}",
-                        samples_file_type
+                        samples_header.file_type
                     ));
                 }
             }
@@ -597,8 +1911,553 @@ pub fn convert(
         if cancelled.load(std::sync::atomic::Ordering::Acquire) {
             return Ok(());
         }
+
         zip.finish()?;
     }
     std::fs::rename(&converted_write_path, &converted_path)?;
     Ok(())
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub const WIDTH: u16 = 64;
+    pub const HEIGHT: u16 = 48;
+    pub const PACKET_DURATION_US: u64 = 1000;
+    pub const START_SYSTEM_TIME: u64 = 1745000000000000;
+
+    // Temporary data directory, removed on drop (including when a test panics)
+    pub struct DataDirectory {
+        pub path: std::path::PathBuf,
+    }
+
+    impl DataDirectory {
+        pub fn new(test: &str) -> Self {
+            static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+            let path = std::env::temp_dir().join(format!(
+                "kairos-test-{}-{}-{}",
+                test,
+                std::process::id(),
+                COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+            ));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(path.join(RECORDINGS_DIRECTORY_NAME))
+                .expect("creating the test directory failed");
+            Self { path }
+        }
+    }
+
+    impl Drop for DataDirectory {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    // EVT3 words of packet `index`, with four DVS events and one trigger event.
+    // Like camera data, TIME_HIGH and ADDR_Y words are only written when their value changes,
+    // hence most packets can only be decoded with the state at the end of the previous packet.
+    pub fn packet(index: u64) -> Vec<u8> {
+        let mut words: Vec<u16> = Vec::new();
+        if index.is_multiple_of(2) {
+            words.push(((index / 2) % HEIGHT as u64) as u16); // EVT_ADDR_Y
+        }
+        for event in 0..4u64 {
+            let t = index * PACKET_DURATION_US + event * 200 + 100;
+            // the previous event is 400 µs before the first event of a packet, 200 µs otherwise
+            let previous_t = t.saturating_sub(if event == 0 { 400 } else { 200 });
+            if t == 100 || (t >> 12) != (previous_t >> 12) {
+                words.push(0x8000 | ((t >> 12) & 0xFFF) as u16); // EVT_TIME_HIGH
+            }
+            words.push(0x6000 | (t & 0xFFF) as u16); // EVT_TIME_LOW
+            words.push(
+                0x2000 | (((event % 2) as u16) << 11) | ((index * 4 + event) % WIDTH as u64) as u16,
+            ); // EVT_ADDR_X
+        }
+        words.push(0xA000 | (index % 2) as u16); // EXT_TRIGGER
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    pub fn initial_state() -> neuromorphic_drivers::adapters::evt3::State {
+        neuromorphic_drivers::adapters::evt3::State {
+            t: 0,
+            overflows: 0,
+            previous_msb_t: 0,
+            previous_lsb_t: 0,
+            x: 0,
+            y: 0,
+            polarity: neuromorphic_drivers::types::Polarity::Off,
+        }
+    }
+
+    // Writes a complete recording (raw, index, samples, and metadata files) with `packets_count` packets
+    // and returns the index entries. Packet i is received at system timestamp i * PACKET_DURATION_US.
+    pub fn write_recording(
+        data_directory: &std::path::Path,
+        name: &str,
+        packets_count: u64,
+        packet_checksums: bool,
+        raw_compression: bool,
+    ) -> Vec<Evt3IndexEntry> {
+        let recordings_directory = data_directory.join(RECORDINGS_DIRECTORY_NAME);
+        let description = Description {
+            device_name: neuromorphic_drivers::devices::Type::PropheseeEvk4
+                .name()
+                .to_owned(),
+            device_serial: "00000000".to_owned(),
+            width: WIDTH,
+            height: HEIGHT,
+            start_system_time: START_SYSTEM_TIME,
+            time_reference_system_time: START_SYSTEM_TIME,
+        };
+        let mut raw_file =
+            std::fs::File::create(recordings_directory.join(format!("{name}{RAW_FILE_EXTENSION}")))
+                .unwrap();
+        let mut raw_file_offset = write_header(
+            &mut raw_file,
+            RAW_FILE_SIGNATURE,
+            raw_compression as u8,
+            &description,
+        )
+        .unwrap();
+        let mut index_file = std::fs::File::create(
+            recordings_directory.join(format!("{name}{INDEX_FILE_EXTENSION}")),
+        )
+        .unwrap();
+        write_header(
+            &mut index_file,
+            INDEX_FILE_SIGNATURE,
+            packet_checksums as u8,
+            &description,
+        )
+        .unwrap();
+        let mut samples_file = std::fs::File::create(
+            recordings_directory.join(format!("{name}{SAMPLES_FILE_EXTENSION}")),
+        )
+        .unwrap();
+        samples_file
+            .write_all(format!("{SAMPLES_FILE_SIGNATURE}\0\0").as_bytes())
+            .unwrap();
+        let mut adapter = neuromorphic_drivers::adapters::evt3::Adapter::from_dimensions_and_state(
+            WIDTH,
+            HEIGHT,
+            initial_state(),
+        );
+        let mut frame = Vec::new();
+        let mut index_entries = Vec::new();
+        for index in 0..packets_count {
+            let packet = packet(index);
+            let stored: &[u8] = if raw_compression {
+                compress_packet(&packet, &mut frame);
+                &frame
+            } else {
+                &packet
+            };
+            let index_entry = Evt3IndexEntry {
+                system_time: START_SYSTEM_TIME + index * PACKET_DURATION_US,
+                system_timestamp: index * PACKET_DURATION_US,
+                first_after_overflow: false,
+                raw_file_offset,
+                raw_length: stored.len() as u64,
+                state: *adapter.state(),
+                checksum: packet_checksums.then(|| crate::crc32c::checksum(stored)),
+            };
+            raw_file.write_all(stored).unwrap();
+            raw_file_offset += stored.len() as u64;
+            index_file.write_all(&index_entry.to_bytes()).unwrap();
+            adapter.convert(&packet, |_| {}, |_| {});
+            samples_file
+                .write_all(&index_entry.system_time.to_le_bytes())
+                .unwrap();
+            samples_file
+                .write_all(&index_entry.system_timestamp.to_le_bytes())
+                .unwrap();
+            samples_file
+                .write_all(&(index as f32).to_le_bytes())
+                .unwrap();
+            samples_file.write_all(&25.0f32.to_le_bytes()).unwrap();
+            index_entries.push(index_entry);
+        }
+        let mut metadata = toml::Table::new();
+        let mut device = toml::Table::new();
+        device.insert(
+            "name".to_owned(),
+            toml::Value::String(description.device_name.clone()),
+        );
+        device.insert(
+            "serial".to_owned(),
+            toml::Value::String(description.device_serial.clone()),
+        );
+        metadata.insert("device".to_owned(), toml::Value::Table(device));
+        metadata.insert(
+            "configuration".to_owned(),
+            toml::Value::try_from(neuromorphic_drivers::prophesee_evk4::DEFAULT_CONFIGURATION)
+                .unwrap(),
+        );
+        std::fs::write(
+            recordings_directory.join(format!("{name}{METADATA_FILE_EXTENSION}")),
+            toml::to_string(&metadata).unwrap(),
+        )
+        .unwrap();
+        index_entries
+    }
+
+    // Overwrites an index entry of a recording written by `write_recording`
+    pub fn rewrite_index_entry<Update>(
+        data_directory: &std::path::Path,
+        name: &str,
+        index: u64,
+        update: Update,
+    ) where
+        Update: FnOnce(&mut Evt3IndexEntry),
+    {
+        let mut reader = Reader::open(data_directory, name).unwrap();
+        let mut index_entry = reader.index_entry(index).unwrap();
+        update(&mut index_entry);
+        let mut index_file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(
+                data_directory
+                    .join(RECORDINGS_DIRECTORY_NAME)
+                    .join(format!("{name}{INDEX_FILE_EXTENSION}")),
+            )
+            .unwrap();
+        index_file
+            .seek(std::io::SeekFrom::Start(
+                reader.index_header.length + index * reader.index_entry_length() as u64,
+            ))
+            .unwrap();
+        index_file.write_all(&index_entry.to_bytes()).unwrap();
+    }
+
+    // Converts a test recording and returns the converted file with the given extension
+    pub fn convert_file(
+        data_directory: &std::path::Path,
+        name: &str,
+        formats: Vec<Format>,
+        range: Option<protocol::TrimRange>,
+        extension: &str,
+    ) -> Vec<u8> {
+        let convert_options = protocol::ConvertOptions {
+            variant: String::new(),
+            formats,
+            compression_level: 0,
+            index: true,
+            samples: true,
+            triggers: true,
+            range,
+            video: Default::default(),
+            filters: Vec::new(),
+            utc: false,
+        };
+        convert(
+            &data_directory.to_path_buf(),
+            name,
+            &convert_options,
+            std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            std::sync::Arc::new(Progress::default()),
+        )
+        .unwrap();
+        let converted_path =
+            converted_directory(data_directory, "").join(format!("{name}{ZIP_FILE_EXTENSION}"));
+        let mut archive =
+            zip::ZipArchive::new(std::fs::File::open(&converted_path).unwrap()).unwrap();
+        let mut file = archive
+            .by_name(&format!("{name}/{name}{extension}"))
+            .unwrap();
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).unwrap();
+        drop(file);
+        std::fs::remove_file(converted_path).unwrap();
+        bytes
+    }
+
+    fn read_indices(reader: &mut Reader) -> Vec<u64> {
+        let mut indices = Vec::new();
+        while let Some(index_entry) = reader.next_packet().unwrap() {
+            indices.push(index_entry.system_timestamp / PACKET_DURATION_US);
+        }
+        indices
+    }
+
+    #[test]
+    fn reader_reads_packets() {
+        let data_directory = DataDirectory::new("reader-packets");
+        for (name, packet_checksums, raw_compression) in
+            [("plain", false, false), ("compressed", true, true)]
+        {
+            let index_entries = write_recording(
+                &data_directory.path,
+                name,
+                10,
+                packet_checksums,
+                raw_compression,
+            );
+            let mut reader = Reader::open(&data_directory.path, name).unwrap();
+            assert_eq!((reader.width, reader.height), (WIDTH, HEIGHT));
+            assert_eq!(reader.index_entries, 10);
+            for (index, expected_index_entry) in index_entries.iter().enumerate() {
+                let index_entry = reader.next_packet().unwrap().unwrap();
+                assert_eq!(
+                    index_entry.raw_file_offset,
+                    expected_index_entry.raw_file_offset
+                );
+                assert_eq!(index_entry.state, expected_index_entry.state);
+                assert_eq!(reader.raw(), packet(index as u64));
+                let mut dvs_events = 0;
+                let mut trigger_events = 0;
+                reader.convert(|_| dvs_events += 1, |_| trigger_events += 1);
+                assert_eq!((dvs_events, trigger_events), (4, 1));
+            }
+            assert!(reader.next_packet().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn reader_seeks() {
+        let data_directory = DataDirectory::new("reader-seek");
+        let index_entries = write_recording(&data_directory.path, "recording", 10, false, false);
+        let mut reader = Reader::open(&data_directory.path, "recording").unwrap();
+        reader.seek_index(5).unwrap();
+        assert_eq!(reader.next_index(), 5);
+        assert_eq!(
+            reader.next_packet().unwrap().unwrap().state,
+            index_entries[5].state
+        );
+        assert_eq!(reader.raw(), packet(5));
+        // decoding continues from the stored state
+        assert_eq!(read_indices(&mut reader), vec![6, 7, 8, 9]);
+
+        for t in [0, 1, 2500, 3700, 3701, 8999, 100_000] {
+            reader.seek_t(t).unwrap();
+            let expected_index = index_entries
+                .iter()
+                .rposition(|index_entry| index_entry.state.t <= t)
+                .unwrap();
+            assert_eq!(reader.next_index(), expected_index as u64);
+        }
+        reader.seek_system_time(0).unwrap();
+        assert_eq!(reader.next_index(), 0);
+        reader
+            .seek_system_time(START_SYSTEM_TIME + 7 * PACKET_DURATION_US + 1)
+            .unwrap();
+        assert_eq!(reader.next_index(), 7);
+        reader.seek_index(100).unwrap();
+        assert!(reader.next_packet().unwrap().is_none());
+        reader.rewind().unwrap();
+        assert_eq!(read_indices(&mut reader), (0..10).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn set_range_restricts_packets() {
+        let data_directory = DataDirectory::new("reader-range");
+        let index_entries = write_recording(&data_directory.path, "recording", 10, false, true);
+        let mut reader = Reader::open(&data_directory.path, "recording").unwrap();
+        reader.set_range(2, 5).unwrap();
+        assert_eq!(read_indices(&mut reader), vec![2, 3, 4]);
+        assert_eq!(
+            reader.range_length().unwrap(),
+            index_entries[2..5]
+                .iter()
+                .map(|index_entry| index_entry.raw_length)
+                .sum::<u64>()
+        );
+        reader.rewind().unwrap();
+        assert_eq!(read_indices(&mut reader), vec![2, 3, 4]);
+        reader.set_range(8, 100).unwrap();
+        assert_eq!(read_indices(&mut reader), vec![8, 9]);
+        reader.set_range(7, 3).unwrap();
+        assert!(reader.next_packet().unwrap().is_none());
+        assert_eq!(reader.range_length().unwrap(), 0);
+        assert_eq!(
+            reader.length().unwrap(),
+            index_entries
+                .iter()
+                .map(|index_entry| index_entry.raw_length)
+                .sum::<u64>()
+        );
+    }
+
+    #[test]
+    fn select_restricts_the_reader() {
+        let data_directory = DataDirectory::new("select");
+        let index_entries = write_recording(&data_directory.path, "recording", 10, false, false);
+        let mut reader = Reader::open(&data_directory.path, "recording").unwrap();
+        let selection = select(
+            &mut reader,
+            Some(&protocol::TrimRange::Timestamp {
+                begin_t: 2500,
+                end_t: 4500,
+            }),
+        )
+        .unwrap();
+        assert_eq!(read_indices(&mut reader), vec![2, 3, 4]);
+        assert_eq!(selection.begin_system_time, index_entries[2].system_time);
+        assert_eq!(selection.end_system_time, index_entries[4].system_time);
+        assert!(select(
+            &mut reader,
+            Some(&protocol::TrimRange::Timestamp {
+                begin_t: 4500,
+                end_t: 2500,
+            }),
+        )
+        .is_err());
+
+        // START_SYSTEM_TIME is 2025-04-18T18:13:20Z
+        select(
+            &mut reader,
+            Some(&protocol::TrimRange::Utc {
+                begin: "2025-04-18T18:13:20.003Z".to_owned(),
+                end: "2025-04-18T18:13:20.005Z".to_owned(),
+            }),
+        )
+        .unwrap();
+        assert_eq!(read_indices(&mut reader), vec![3, 4, 5]);
+
+        // a range that ends before the first packet selects nothing
+        select(
+            &mut reader,
+            Some(&protocol::TrimRange::Utc {
+                begin: "2025-04-18T18:00:00Z".to_owned(),
+                end: "2025-04-18T18:10:00Z".to_owned(),
+            }),
+        )
+        .unwrap();
+        assert!(reader.next_packet().unwrap().is_none());
+        assert_eq!(reader.range_length().unwrap(), 0);
+
+        select(&mut reader, None).unwrap();
+        assert_eq!(read_indices(&mut reader), (0..10).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn next_packet_checks_continuity() {
+        let data_directory = DataDirectory::new("reader-continuity");
+        write_recording(&data_directory.path, "position", 4, false, false);
+        rewrite_index_entry(&data_directory.path, "position", 2, |index_entry| {
+            index_entry.raw_file_offset += 2;
+        });
+        let mut reader = Reader::open(&data_directory.path, "position").unwrap();
+        assert!(reader.next_packet().unwrap().is_some());
+        assert!(reader.next_packet().unwrap().is_some());
+        let error = reader.next_packet().unwrap_err();
+        assert!(
+            error.to_string().starts_with("Position mismatch"),
+            "{error}"
+        );
+
+        write_recording(&data_directory.path, "state", 4, false, false);
+        rewrite_index_entry(&data_directory.path, "state", 2, |index_entry| {
+            index_entry.state.t += 1;
+        });
+        let mut reader = Reader::open(&data_directory.path, "state").unwrap();
+        assert!(reader.next_packet().unwrap().is_some());
+        assert!(reader.next_packet().unwrap().is_some());
+        let error = reader.next_packet().unwrap_err();
+        assert!(error.to_string().starts_with("State mismatch"), "{error}");
+
+        // seeking restores the stored state without checking the previous packet
+        reader.seek_index(2).unwrap();
+        assert_eq!(read_indices(&mut reader), vec![2, 3]);
+    }
+
+    #[test]
+    fn next_packet_detects_checksum_mismatches() {
+        let data_directory = DataDirectory::new("reader-checksums");
+        let index_entries = write_recording(&data_directory.path, "flipped", 4, true, false);
+        let raw_path = data_directory
+            .path
+            .join(RECORDINGS_DIRECTORY_NAME)
+            .join(format!("flipped{RAW_FILE_EXTENSION}"));
+        let mut bytes = std::fs::read(&raw_path).unwrap();
+        bytes[index_entries[1].raw_file_offset as usize] ^= 0x01;
+        std::fs::write(&raw_path, bytes).unwrap();
+        let mut reader = Reader::open(&data_directory.path, "flipped").unwrap();
+        assert!(reader.next_packet().unwrap().is_some());
+        let error = reader.next_packet().unwrap_err();
+        assert!(error.is::<ChecksumMismatch>(), "{error}");
+
+        // the corrupted packet is consumed and the next packets are read without a state check
+        let index_entry = reader.next_packet().unwrap().unwrap();
+        assert_eq!(
+            index_entry.raw_file_offset,
+            index_entries[2].raw_file_offset
+        );
+        assert_eq!(reader.raw(), packet(2));
+        assert!(reader.next_packet().unwrap().is_some());
+        assert!(reader.next_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn metavision_raw_copies_packets() {
+        let data_directory = DataDirectory::new("metavision-raw");
+        write_recording(&data_directory.path, "recording", 8, false, false);
+        let split_header = |bytes: &[u8]| {
+            let end = bytes
+                .windows(6)
+                .position(|window| window == b"% end\n")
+                .unwrap();
+            bytes[end + 6..].to_vec()
+        };
+        let payload = (0..8).flat_map(packet).collect::<Vec<u8>>();
+
+        // a full-range export is byte-identical to the stored payload
+        let bytes = convert_file(
+            &data_directory.path,
+            "recording",
+            vec![Format::MetavisionRaw],
+            None,
+            metavision::FILE_EXTENSION,
+        );
+        assert_eq!(split_header(&bytes), payload);
+
+        // later selections restore the decoder state before the first packet
+        let bytes = convert_file(
+            &data_directory.path,
+            "recording",
+            vec![Format::MetavisionRaw],
+            Some(protocol::TrimRange::Timestamp {
+                begin_t: 3 * PACKET_DURATION_US,
+                end_t: 8 * PACKET_DURATION_US,
+            }),
+            metavision::FILE_EXTENSION,
+        );
+        let mut reader = Reader::open(&data_directory.path, "recording").unwrap();
+        let first_index_entry = reader.index_entry(0).unwrap();
+        let index_entry = reader.index_entry(3).unwrap();
+        let mut expected = Vec::new();
+        metavision::write_evt3_state(&mut expected, &index_entry.state).unwrap();
+        expected.extend_from_slice(
+            &payload[(index_entry.raw_file_offset - first_index_entry.raw_file_offset) as usize..],
+        );
+        assert_eq!(split_header(&bytes), expected);
+    }
+
+    #[test]
+    fn samples_csv_lists_the_selected_samples() {
+        let data_directory = DataDirectory::new("samples-csv");
+        write_recording(&data_directory.path, "recording", 8, false, false);
+        let bytes = convert_file(
+            &data_directory.path,
+            "recording",
+            vec![Format::Csv],
+            Some(protocol::TrimRange::Timestamp {
+                begin_t: 3 * PACKET_DURATION_US,
+                end_t: 8 * PACKET_DURATION_US,
+            }),
+            "_samples.csv",
+        );
+        let mut expected =
+            "system_time,system_timestamp,illuminance_lux,temperature_celsius\n".to_owned();
+        for index in 3..8 {
+            expected.push_str(&format!(
+                "{},{},{},{}\n",
+                START_SYSTEM_TIME + index * PACKET_DURATION_US,
+                index * PACKET_DURATION_US,
+                index as f32,
+                25.0f32
+            ));
+        }
+        assert_eq!(str::from_utf8(&bytes).unwrap(), expected);
+    }
+}
@@ -0,0 +1,324 @@
+use crate::device;
+use crate::recordings;
+
+use std::io::Read;
+
+struct Reader {
+    inner: recordings::Reader,
+    start: Option<std::time::Instant>,
+    finished: bool,
+}
+
+struct SamplesReader {
+    samples_file: std::io::BufReader<std::fs::File>,
+    next_sample: Option<recordings::Evk4Sample>,
+    illuminance: f32,
+    temperature: f32,
+}
+
+impl SamplesReader {
+    fn read_sample(&mut self) -> Result<Option<recordings::Evk4Sample>, std::io::Error> {
+        let mut sample_data = [0u8; recordings::EVK4_SAMPLE_LENGTH];
+        match self.samples_file.read_exact(&mut sample_data) {
+            Ok(()) => Ok(Some(recordings::Evk4Sample::from_bytes(&sample_data))),
+            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+// A replay reads a complete recording and emits its raw buffers as if they came from a camera.
+// Buffers are paced with the recorded system timestamps (divided by `speed`),
+// or emitted as fast as possible if `speed` is zero.
+// The samples file is read by the sampler thread, which uses the system timestamp
+// of the latest replayed buffer to pick the matching sample.
+pub struct Replay {
+    pub name: String,
+    pub properties: device::Properties,
+    pub configuration: neuromorphic_drivers::Configuration,
+    pub width: u16,
+    pub height: u16,
+    speed: f64,
+    first_system_timestamp: u64,
+    first_state: neuromorphic_drivers::adapters::evt3::State,
+    reader: std::sync::Mutex<Reader>,
+    samples_reader: std::sync::Mutex<SamplesReader>,
+    system_timestamp: std::sync::atomic::AtomicU64,
+}
+
+impl Replay {
+    pub fn open(
+        data_directory: &std::path::Path,
+        name: &str,
+        speed: f64,
+    ) -> Result<Self, anyhow::Error> {
+        if speed.is_nan() || speed < 0.0 {
+            return Err(anyhow::anyhow!(
+                "the replay speed must be positive or zero (got {})",
+                speed
+            ));
+        }
+        let recordings_directory = data_directory.join(recordings::RECORDINGS_DIRECTORY_NAME);
+        let mut file_buffer = Vec::new();
+
+        // metadata file
+        let metadata = toml::from_str::<toml::Value>(&std::fs::read_to_string(
+            recordings_directory.join(format!("{}{}", name, recordings::METADATA_FILE_EXTENSION)),
+        )?)?;
+        let device_name = metadata
+            .get("device")
+            .and_then(|device| device.get("name"))
+            .and_then(|name| name.as_str())
+            .ok_or(anyhow::anyhow!("the metadata file has no device name"))?
+            .to_owned();
+        let serial = metadata
+            .get("device")
+            .and_then(|device| device.get("serial"))
+            .and_then(|serial| serial.as_str())
+            .unwrap_or("")
+            .to_owned();
+        let configuration = device::configuration_from_toml(
+            &device_name,
+            metadata
+                .get("configuration")
+                .cloned()
+                .ok_or(anyhow::anyhow!("the metadata file has no configuration"))?,
+        )?;
+
+        // raw and index files
+        let mut inner = recordings::Reader::open(data_directory, name)?;
+        let (first_system_timestamp, first_state) = match inner.peek_index_entry()? {
+            Some(index_entry) => (index_entry.system_timestamp, index_entry.state),
+            None => return Err(anyhow::anyhow!("the index file is empty")),
+        };
+        let width = inner.width;
+        let height = inner.height;
+        let reader = Reader {
+            inner,
+            start: None,
+            finished: false,
+        };
+
+        // samples file
+        let mut samples_file = std::io::BufReader::new(std::fs::File::open(
+            recordings_directory.join(format!("{}{}", name, recordings::SAMPLES_FILE_EXTENSION)),
+        )?);
+        let samples_header = recordings::read_header(
+            &mut file_buffer,
+            &mut samples_file,
+            recordings::SAMPLES_FILE_SIGNATURE.as_bytes(),
+        )?;
+        if samples_header.file_type != 0 {
+            return Err(anyhow::anyhow!(
+                "unsupported samples file type {}",
+                samples_header.file_type
+            ));
+        }
+        let mut samples_reader = SamplesReader {
+            samples_file,
+            next_sample: None,
+            illuminance: f32::NAN,
+            temperature: f32::NAN,
+        };
+        samples_reader.next_sample = samples_reader.read_sample()?;
+
+        Ok(Self {
+            name: name.to_owned(),
+            properties: device::Properties {
+                name: device_name,
+                serial,
+                speed: "Replay".to_owned(),
+                bus_number: 0,
+                address: 0,
+            },
+            configuration,
+            width,
+            height,
+            speed,
+            first_system_timestamp,
+            first_state,
+            reader: std::sync::Mutex::new(reader),
+            samples_reader: std::sync::Mutex::new(samples_reader),
+            system_timestamp: std::sync::atomic::AtomicU64::new(first_system_timestamp),
+        })
+    }
+
+    pub fn create_adapter(&self) -> neuromorphic_drivers::Adapter {
+        neuromorphic_drivers::Adapter::Evt3(
+            neuromorphic_drivers::adapters::evt3::Adapter::from_dimensions_and_state(
+                self.width,
+                self.height,
+                self.first_state,
+            ),
+        )
+    }
+
+    // Copies the next raw buffer into `buffer` and returns its system time, instant and overflow flag.
+    // Returns None if no buffer is due before the timeout, or if the replay is finished.
+    pub fn next_with_timeout(
+        &self,
+        timeout: &std::time::Duration,
+        buffer: &mut Vec<u8>,
+    ) -> Option<(std::time::SystemTime, std::time::Instant, bool)> {
+        let mut reader = self.reader.lock().expect("replay reader mutex is poisoned");
+        let index_entry = match reader.inner.peek_index_entry() {
+            Ok(Some(index_entry)) if !reader.finished => index_entry,
+            result => {
+                if !reader.finished {
+                    if let Err(error) = result {
+                        println!(
+                            "{} | reading the replay \"{}\" failed ({})",
+                            crate::now_utc_string(),
+                            self.name,
+                            error
+                        );
+                    }
+                    println!(
+                        "{} | the replay \"{}\" is finished",
+                        crate::now_utc_string(),
+                        self.name
+                    );
+                    reader.finished = true;
+                }
+                drop(reader);
+                std::thread::sleep(*timeout);
+                return None;
+            }
+        };
+        let now = std::time::Instant::now();
+        let start = *reader.start.get_or_insert(now);
+        if self.speed > 0.0 {
+            let deadline = start
+                + std::time::Duration::from_secs_f64(
+                    index_entry
+                        .system_timestamp
+                        .saturating_sub(self.first_system_timestamp) as f64
+                        / 1e6
+                        / self.speed,
+                );
+            if deadline > now {
+                if deadline - now > *timeout {
+                    drop(reader);
+                    std::thread::sleep(*timeout);
+                    return None;
+                }
+                std::thread::sleep(deadline - now);
+            }
+        }
+        if let Err(error) = reader.inner.next_packet() {
+            println!(
+                "{} | reading the replay \"{}\" failed ({})",
+                crate::now_utc_string(),
+                self.name,
+                error
+            );
+            println!(
+                "{} | the replay \"{}\" is finished",
+                crate::now_utc_string(),
+                self.name
+            );
+            reader.finished = true;
+            return None;
+        }
+        buffer.clear();
+        buffer.extend_from_slice(reader.inner.raw());
+        self.system_timestamp.store(
+            index_entry.system_timestamp,
+            std::sync::atomic::Ordering::Release,
+        );
+        Some((
+            std::time::SystemTime::now(),
+            std::time::Instant::now(),
+            index_entry.first_after_overflow,
+        ))
+    }
+
+    // Returns the illuminance and temperature of the latest sample recorded
+    // before the latest replayed buffer.
+    pub fn sample(&self) -> (f32, f32) {
+        let system_timestamp = self
+            .system_timestamp
+            .load(std::sync::atomic::Ordering::Acquire);
+        let mut samples_reader = self
+            .samples_reader
+            .lock()
+            .expect("replay samples reader mutex is poisoned");
+        while let Some(sample) = samples_reader.next_sample {
+            if sample.system_timestamp > system_timestamp {
+                break;
+            }
+            samples_reader.illuminance = sample.illuminance;
+            samples_reader.temperature = sample.temperature;
+            samples_reader.next_sample = samples_reader.read_sample().ok().flatten();
+        }
+        (samples_reader.illuminance, samples_reader.temperature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recordings::tests;
+
+    #[test]
+    fn replay_emits_the_recorded_packets() {
+        let data_directory = tests::DataDirectory::new("replay-packets");
+        let index_entries =
+            tests::write_recording(&data_directory.path, "recording", 5, true, true);
+        let replay = Replay::open(&data_directory.path, "recording", 0.0).unwrap();
+        assert_eq!(replay.width, tests::WIDTH);
+        assert_eq!(replay.height, tests::HEIGHT);
+        assert!(matches!(
+            replay.configuration,
+            neuromorphic_drivers::Configuration::PropheseeEvk4(_)
+        ));
+        let timeout = std::time::Duration::from_millis(10);
+        let mut buffer = Vec::new();
+        let mut adapter = match replay.create_adapter() {
+            neuromorphic_drivers::Adapter::Evt3(adapter) => adapter,
+            _ => panic!("replays use EVT3 adapters"),
+        };
+        for (index, index_entry) in index_entries.iter().enumerate() {
+            assert!(replay.next_with_timeout(&timeout, &mut buffer).is_some());
+            assert_eq!(buffer, tests::packet(index as u64));
+            assert_eq!(*adapter.state(), index_entry.state);
+            adapter.convert(&buffer, |_| {}, |_| {});
+            // samples are picked with the system timestamp of the latest buffer
+            assert_eq!(replay.sample(), (index as f32, 25.0));
+        }
+        assert!(replay.next_with_timeout(&timeout, &mut buffer).is_none());
+        assert!(replay.next_with_timeout(&timeout, &mut buffer).is_none());
+    }
+
+    #[test]
+    fn replay_paces_packets() {
+        let data_directory = tests::DataDirectory::new("replay-pacing");
+        tests::write_recording(&data_directory.path, "recording", 21, false, false);
+        // 20 packets after the first one, 1 ms apart, replayed at half speed
+        let replay = Replay::open(&data_directory.path, "recording", 0.5).unwrap();
+        let timeout = std::time::Duration::from_millis(100);
+        let mut buffer = Vec::new();
+        let mut packets = 0;
+        let start = std::time::Instant::now();
+        while packets < 21 {
+            if replay.next_with_timeout(&timeout, &mut buffer).is_some() {
+                packets += 1;
+            }
+            assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        }
+        assert!(start.elapsed() >= std::time::Duration::from_millis(40));
+
+        // buffers that are not due before the timeout are not emitted
+        let replay = Replay::open(&data_directory.path, "recording", 0.001).unwrap();
+        assert!(replay.next_with_timeout(&timeout, &mut buffer).is_some());
+        assert!(replay.next_with_timeout(&timeout, &mut buffer).is_none());
+    }
+
+    #[test]
+    fn replay_rejects_negative_speeds() {
+        let data_directory = tests::DataDirectory::new("replay-speed");
+        tests::write_recording(&data_directory.path, "recording", 1, false, false);
+        assert!(Replay::open(&data_directory.path, "recording", -1.0).is_err());
+        assert!(Replay::open(&data_directory.path, "recording", f64::NAN).is_err());
+    }
+}
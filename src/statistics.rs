@@ -0,0 +1,316 @@
+// Summary of a recording's events, stored next to the recording files (NAME.statistics.json)
+// and in converted recordings. The recordings state lists the key fields (protocol::Summary).
+use crate::protocol;
+use crate::recordings;
+
+pub const FILE_EXTENSION: &str = ".statistics.json";
+
+// event rates are computed over bins of RATE_BIN_DURATION_US (sensor time)
+const RATE_BIN_DURATION_US: u64 = 100_000;
+// 64 MiB of bins (about 19 days), events after the last bin (or after a corrupted timestamp)
+// are counted but do not contribute to the rates
+const MAXIMUM_RATE_BINS: usize = 1 << 24;
+const RATE_PERCENTILES: [u8; 7] = [1, 5, 25, 50, 75, 95, 99];
+const HOT_PIXELS: usize = 16;
+
+// distinguishes the temporary files of concurrent writers (background task and conversions)
+static WRITE_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Triggers {
+    pub id: u8,
+    pub rising: u64,
+    pub falling: u64,
+}
+
+// events per second
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RatePercentile {
+    pub percentile: u8,
+    pub events_per_second: f64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HotPixel {
+    pub x: u16,
+    pub y: u16,
+    pub events: u64,
+}
+
+// sensor times are in µs (t), system times are in µs since the Unix epoch (packets reception)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Statistics {
+    pub on_events: u64,
+    pub off_events: u64,
+    pub triggers: Vec<Triggers>,
+    pub begin_t: u64,
+    pub end_t: u64,
+    pub duration_us: u64,
+    pub begin_system_time: u64,
+    pub end_system_time: u64,
+    pub wall_duration_us: u64,
+    pub packets: u64,
+    pub overflows: u64,
+    pub rate_bin_duration_us: u64,
+    pub maximum_events_per_second: f64,
+    pub rate_percentiles: Vec<RatePercentile>,
+    pub hot_pixels: Vec<HotPixel>,
+}
+
+impl Statistics {
+    pub fn summary(&self) -> protocol::Summary {
+        protocol::Summary {
+            on_events: self.on_events,
+            off_events: self.off_events,
+            triggers: self
+                .triggers
+                .iter()
+                .map(|triggers| triggers.rising + triggers.falling)
+                .sum(),
+            duration_us: self.duration_us,
+            wall_duration_us: self.wall_duration_us,
+            overflows: self.overflows,
+        }
+    }
+}
+
+pub fn path(data_directory: &std::path::Path, name: &str) -> std::path::PathBuf {
+    data_directory
+        .join(recordings::RECORDINGS_DIRECTORY_NAME)
+        .join(format!("{}{}", name, FILE_EXTENSION))
+}
+
+// Returns None if the statistics have not been computed yet.
+pub fn read(
+    data_directory: &std::path::Path,
+    name: &str,
+) -> Result<Option<Statistics>, anyhow::Error> {
+    match std::fs::read(path(data_directory, name)) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+pub fn write(
+    data_directory: &std::path::Path,
+    name: &str,
+    statistics: &Statistics,
+) -> Result<(), anyhow::Error> {
+    let path = path(data_directory, name);
+    // the startup scan renames .write files in the recordings directory (partial recordings),
+    // hence the temporary file is hidden instead (each writer uses its own temporary file)
+    let write_path = data_directory
+        .join(recordings::RECORDINGS_DIRECTORY_NAME)
+        .join(format!(
+            ".{}{}.{}-{}",
+            name,
+            FILE_EXTENSION,
+            std::process::id(),
+            WRITE_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+    // the temporary file is removed if any step fails (including panics)
+    let write_path = RemoveOnDrop(write_path);
+    let mut file = std::io::BufWriter::new(std::fs::File::create(&write_path.0)?);
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut file, formatter);
+    serde::Serialize::serialize(statistics, &mut serializer)?;
+    std::io::Write::write_all(&mut file, b"\n")?;
+    std::io::Write::flush(&mut file)?;
+    drop(file);
+    std::fs::rename(&write_path.0, &path)?;
+    Ok(())
+}
+
+struct RemoveOnDrop(std::path::PathBuf);
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        // the file does not exist anymore if it was renamed
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// Reads all the packets of the recording.
+pub fn compute(
+    data_directory: &std::path::Path,
+    name: &str,
+    progress: &recordings::Progress,
+) -> Result<Statistics, anyhow::Error> {
+    let mut reader = recordings::Reader::open(data_directory, name)?;
+    let width = reader.width as usize;
+    let mut pixels_events = vec![0u64; width * reader.height as usize];
+    let mut on_events = 0u64;
+    let mut off_events = 0u64;
+    let mut triggers: Vec<Triggers> = Vec::new();
+    let mut begin_t = None;
+    let mut end_t = 0u64;
+    let mut begin_system_time = None;
+    let mut end_system_time = 0u64;
+    let mut packets = 0u64;
+    let mut overflows = 0u64;
+    let mut rate_bins: Vec<u32> = Vec::new();
+    while let Some(index_entry) = reader.next_packet()? {
+        progress.add(index_entry.raw_length);
+        packets += 1;
+        if index_entry.first_after_overflow {
+            overflows += 1;
+        }
+        begin_system_time.get_or_insert(index_entry.system_time);
+        end_system_time = index_entry.system_time;
+        reader.convert(
+            |dvs_event| {
+                let t = dvs_event.t; // unpack field
+                let x = dvs_event.x; // unpack field
+                let y = dvs_event.y; // unpack field
+                let on = dvs_event.polarity as u8 == 1; // unpack field
+                if on {
+                    on_events += 1;
+                } else {
+                    off_events += 1;
+                }
+                let first_t = *begin_t.get_or_insert(t);
+                end_t = t;
+                let bin = t.saturating_sub(first_t) / RATE_BIN_DURATION_US;
+                if bin < MAXIMUM_RATE_BINS as u64 {
+                    let bin = bin as usize;
+                    if bin >= rate_bins.len() {
+                        rate_bins.resize(bin + 1, 0);
+                    }
+                    rate_bins[bin] += 1;
+                }
+                if let Some(pixel_events) = pixels_events.get_mut(x as usize + y as usize * width) {
+                    *pixel_events += 1;
+                }
+            },
+            |trigger_event| {
+                let id = trigger_event.id; // unpack field
+                let rising = trigger_event.polarity as u8 == 1; // unpack field
+                let index = match triggers.binary_search_by_key(&id, |triggers| triggers.id) {
+                    Ok(index) => index,
+                    Err(index) => {
+                        triggers.insert(
+                            index,
+                            Triggers {
+                                id,
+                                rising: 0,
+                                falling: 0,
+                            },
+                        );
+                        index
+                    }
+                };
+                if rising {
+                    triggers[index].rising += 1;
+                } else {
+                    triggers[index].falling += 1;
+                }
+            },
+        );
+    }
+    let begin_t = begin_t.unwrap_or(0);
+    let begin_system_time = begin_system_time.unwrap_or(0);
+
+    // the last bin is partial, hence it is not used for the rates
+    if rate_bins.len() > 1 {
+        rate_bins.pop();
+    }
+    rate_bins.sort_unstable();
+    let bin_to_rate = |events: u32| events as f64 * 1e6 / RATE_BIN_DURATION_US as f64;
+    let rate_percentiles = if rate_bins.is_empty() {
+        Vec::new()
+    } else {
+        RATE_PERCENTILES
+            .iter()
+            .map(|percentile| RatePercentile {
+                percentile: *percentile,
+                events_per_second: bin_to_rate(
+                    rate_bins[((rate_bins.len() - 1) * *percentile as usize + 50) / 100],
+                ),
+            })
+            .collect()
+    };
+
+    let mut hot_pixels = pixels_events
+        .iter()
+        .enumerate()
+        .filter(|(_, events)| **events > 0)
+        .map(|(index, events)| HotPixel {
+            x: (index % width) as u16,
+            y: (index / width) as u16,
+            events: *events,
+        })
+        .collect::<Vec<_>>();
+    hot_pixels.sort_by_key(|hot_pixel| std::cmp::Reverse(hot_pixel.events));
+    hot_pixels.truncate(HOT_PIXELS);
+
+    Ok(Statistics {
+        on_events,
+        off_events,
+        triggers,
+        begin_t,
+        end_t,
+        duration_us: end_t.saturating_sub(begin_t),
+        begin_system_time,
+        end_system_time,
+        // the host clock may be adjusted during the recording
+        wall_duration_us: end_system_time.saturating_sub(begin_system_time),
+        packets,
+        overflows,
+        rate_bin_duration_us: RATE_BIN_DURATION_US,
+        maximum_events_per_second: rate_bins.last().map_or(0.0, |events| bin_to_rate(*events)),
+        rate_percentiles,
+        hot_pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recordings::tests::{write_recording, DataDirectory, PACKET_DURATION_US};
+
+    #[test]
+    fn compute_and_write() {
+        let data_directory = DataDirectory::new("statistics");
+        write_recording(&data_directory.path, "recording", 10, false, false);
+        let statistics = compute(
+            &data_directory.path,
+            "recording",
+            &recordings::Progress::default(),
+        )
+        .unwrap();
+        assert_eq!(statistics.on_events, 20);
+        assert_eq!(statistics.off_events, 20);
+        assert_eq!(statistics.packets, 10);
+        assert_eq!(statistics.overflows, 0);
+        assert_eq!(statistics.begin_t, 100);
+        assert_eq!(statistics.end_t, 9 * PACKET_DURATION_US + 700);
+        assert_eq!(statistics.triggers.len(), 1);
+        assert_eq!(
+            (
+                statistics.triggers[0].rising,
+                statistics.triggers[0].falling
+            ),
+            (5, 5)
+        );
+
+        write(&data_directory.path, "recording", &statistics).unwrap();
+        let read_statistics = read(&data_directory.path, "recording").unwrap().unwrap();
+        assert_eq!(read_statistics.summary(), statistics.summary());
+
+        // a failed write does not leave its temporary file behind
+        let directory = path(&data_directory.path, "failed");
+        std::fs::create_dir_all(directory.join("not-empty")).unwrap();
+        assert!(write(&data_directory.path, "failed", &statistics).is_err());
+        let mut names = std::fs::read_dir(
+            data_directory
+                .path
+                .join(recordings::RECORDINGS_DIRECTORY_NAME),
+        )
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+        names.sort();
+        assert!(names.iter().all(|name| !name.starts_with('.')), "{names:?}");
+    }
+}
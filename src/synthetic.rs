@@ -0,0 +1,459 @@
+use crate::device;
+
+// synthetic cameras emulate a Prophesee EVK4 (configuration and EVT3 data)
+pub const DEVICE_NAME: &str = "Synthetic";
+
+const SLICE_DURATION_US: u64 = 1000;
+const MAXIMUM_SLICE_DURATION_US: u64 = 100_000;
+const BAR_COUNT: u64 = 4;
+const BAR_PERIOD_US: f64 = 2e6;
+const DISC_PERIOD_US: f64 = 1e6;
+const ILLUMINANCE: f32 = 100.0;
+const TEMPERATURE: f32 = 25.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Pattern {
+    Bars,
+    Disc,
+    Noise,
+}
+
+#[derive(Debug, Clone)]
+pub struct Parameters {
+    pub width: u16,
+    pub height: u16,
+    pub event_rate: f64,
+    pub patterns: Vec<Pattern>,
+    pub trigger_period_us: u64,
+}
+
+// splitmix64 (https://prng.di.unimi.it/splitmix64.c)
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // exponential inter-arrival time of a Poisson process (rate in events per µs)
+    fn next_interval(&mut self, rate: f64) -> f64 {
+        -(1.0 - self.next_f64()).ln() / rate
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Event {
+    Dvs {
+        t: u64,
+        x: u16,
+        y: u16,
+        on: bool,
+    },
+    Trigger {
+        t: u64,
+        id: u8,
+        rising: bool,
+    },
+}
+
+impl Event {
+    fn t(&self) -> u64 {
+        match self {
+            Event::Dvs { t, .. } => *t,
+            Event::Trigger { t, .. } => *t,
+        }
+    }
+}
+
+struct Encoder {
+    time_high: Option<u64>,
+    time_low: Option<u64>,
+    y: Option<u16>,
+}
+
+impl Encoder {
+    fn push_word(buffer: &mut Vec<u8>, word: u16) {
+        buffer.extend_from_slice(&word.to_le_bytes());
+    }
+
+    fn encode_time(&mut self, t: u64, buffer: &mut Vec<u8>) {
+        let time_high = (t >> 12) & 0xFFF;
+        if self.time_high != Some(time_high) {
+            Self::push_word(buffer, 0x8000 | time_high as u16); // EVT_TIME_HIGH
+            self.time_high = Some(time_high);
+            self.time_low = None;
+        }
+        let time_low = t & 0xFFF;
+        if self.time_low != Some(time_low) {
+            Self::push_word(buffer, 0x6000 | time_low as u16); // EVT_TIME_LOW
+            self.time_low = Some(time_low);
+        }
+    }
+
+    fn encode(&mut self, event: &Event, buffer: &mut Vec<u8>) {
+        match *event {
+            Event::Dvs { t, x, y, on } => {
+                self.encode_time(t, buffer);
+                if self.y != Some(y) {
+                    Self::push_word(buffer, y & 0x7FF); // EVT_ADDR_Y
+                    self.y = Some(y);
+                }
+                Self::push_word(buffer, 0x2000 | ((on as u16) << 11) | (x & 0x7FF)); // EVT_ADDR_X
+            }
+            Event::Trigger { t, id, rising } => {
+                self.encode_time(t, buffer);
+                Self::push_word(buffer, 0xA000 | (((id & 0xF) as u16) << 8) | rising as u16); // EXT_TRIGGER
+            }
+        }
+    }
+
+    // forces a time high word so that the decoder's clock advances even without events
+    fn encode_time_high(&mut self, t: u64, buffer: &mut Vec<u8>) {
+        let time_high = (t >> 12) & 0xFFF;
+        Self::push_word(buffer, 0x8000 | time_high as u16); // EVT_TIME_HIGH
+        self.time_high = Some(time_high);
+        self.time_low = None;
+    }
+}
+
+struct Generator {
+    start: Option<std::time::Instant>,
+    t: u64,
+    random: Random,
+    next_ts: Vec<f64>,
+    next_trigger_t: u64,
+    next_trigger_rising: bool,
+    encoder: Encoder,
+    events: Vec<Event>,
+    pending: Vec<u8>,
+    pending_offset: usize,
+}
+
+// A synthetic camera generates EVT3 words in real-time from procedural patterns.
+// Each pattern is a Poisson process whose rate is an equal share of the total event rate.
+// Generated words are split into buffers no larger than the EVK4 USB buffer length.
+pub struct Synthetic {
+    pub parameters: Parameters,
+    pub properties: device::Properties,
+    pub configuration: neuromorphic_drivers::Configuration,
+    buffer_length: usize,
+    generator: std::sync::Mutex<Generator>,
+}
+
+impl Synthetic {
+    pub fn new(parameters: Parameters) -> Result<Self, anyhow::Error> {
+        if parameters.width == 0
+            || parameters.height == 0
+            || parameters.width > 2048
+            || parameters.height > 2048
+        {
+            return Err(anyhow::anyhow!(
+                "the synthetic camera dimensions must be in the range [1, 2048] (got {}x{})",
+                parameters.width,
+                parameters.height
+            ));
+        }
+        if parameters.event_rate.is_nan() || parameters.event_rate < 0.0 {
+            return Err(anyhow::anyhow!(
+                "the synthetic event rate must be positive or zero (got {})",
+                parameters.event_rate
+            ));
+        }
+        // the period is split into a rising and a falling half
+        if parameters.trigger_period_us == 1 {
+            return Err(anyhow::anyhow!(
+                "the synthetic trigger period must be 0 (no triggers) or at least 2 µs (got {})",
+                parameters.trigger_period_us
+            ));
+        }
+        let next_ts = vec![0.0; parameters.patterns.len()];
+        Ok(Self {
+            properties: device::Properties {
+                name: DEVICE_NAME.to_owned(),
+                serial: format!(
+                    "synthetic-{}x{}-{}",
+                    parameters.width, parameters.height, parameters.event_rate
+                ),
+                speed: "Synthetic".to_owned(),
+                bus_number: 0,
+                address: 0,
+            },
+            parameters,
+            configuration: neuromorphic_drivers::Configuration::PropheseeEvk4(
+                neuromorphic_drivers::prophesee_evk4::DEFAULT_CONFIGURATION,
+            ),
+            buffer_length: neuromorphic_drivers::prophesee_evk4::DEFAULT_USB_CONFIGURATION
+                .buffer_length,
+            generator: std::sync::Mutex::new(Generator {
+                start: None,
+                t: 0,
+                random: Random {
+                    state: 0x4B4149524F53, // "KAIROS"
+                },
+                next_ts,
+                next_trigger_t: 0,
+                next_trigger_rising: true,
+                encoder: Encoder {
+                    time_high: None,
+                    time_low: None,
+                    y: None,
+                },
+                events: Vec::new(),
+                pending: Vec::new(),
+                pending_offset: 0,
+            }),
+        })
+    }
+
+    pub fn create_adapter(&self) -> neuromorphic_drivers::Adapter {
+        neuromorphic_drivers::Adapter::Evt3(
+            neuromorphic_drivers::adapters::evt3::Adapter::from_dimensions_and_state(
+                self.parameters.width,
+                self.parameters.height,
+                neuromorphic_drivers::adapters::evt3::State {
+                    t: 0,
+                    overflows: 0,
+                    previous_msb_t: 0,
+                    previous_lsb_t: 0,
+                    x: 0,
+                    y: 0,
+                    polarity: neuromorphic_drivers::types::Polarity::Off,
+                },
+            ),
+        )
+    }
+
+    pub fn sample(&self) -> (f32, f32) {
+        (ILLUMINANCE, TEMPERATURE)
+    }
+
+    fn generate(&self, generator: &mut Generator, end_t: u64) {
+        let Generator {
+            t,
+            random,
+            next_ts,
+            next_trigger_t,
+            next_trigger_rising,
+            encoder,
+            events,
+            pending,
+            pending_offset,
+            ..
+        } = generator;
+        let width = self.parameters.width as f64;
+        let height = self.parameters.height as f64;
+        events.clear();
+        if !self.parameters.patterns.is_empty() {
+            let rate = self.parameters.event_rate / 1e6 / self.parameters.patterns.len() as f64;
+            for (pattern, next_t) in self.parameters.patterns.iter().zip(next_ts.iter_mut()) {
+                if rate <= 0.0 {
+                    break;
+                }
+                if *next_t < *t as f64 {
+                    *next_t = *t as f64 + random.next_interval(rate);
+                }
+                while *next_t < end_t as f64 {
+                    let event_t = *next_t as u64;
+                    let (x, y, on) = match pattern {
+                        Pattern::Bars => {
+                            let spacing = width / BAR_COUNT as f64;
+                            let bar_width = spacing / 4.0;
+                            let offset = (event_t as f64 / BAR_PERIOD_US).fract() * width;
+                            let bar = (random.next_f64() * BAR_COUNT as f64).floor();
+                            let on = random.next_f64() < 0.5;
+                            let x = offset
+                                + bar * spacing
+                                + if on { bar_width } else { 0.0 }
+                                + random.next_f64();
+                            (x % width, random.next_f64() * height, on)
+                        }
+                        Pattern::Disc => {
+                            let angle = (event_t as f64 / DISC_PERIOD_US).fract()
+                                * std::f64::consts::TAU;
+                            let orbit = width.min(height) / 3.0;
+                            let radius = width.min(height) / 20.0;
+                            let point_angle = random.next_f64() * std::f64::consts::TAU;
+                            let point_radius = random.next_f64().sqrt() * radius;
+                            let dx = point_radius * point_angle.cos();
+                            let dy = point_radius * point_angle.sin();
+                            // the disc moves along (-sin, cos), points ahead of the disc's center turn on
+                            let on = -dx * angle.sin() + dy * angle.cos() > 0.0;
+                            (
+                                width / 2.0 + orbit * angle.cos() + dx,
+                                height / 2.0 + orbit * angle.sin() + dy,
+                                on,
+                            )
+                        }
+                        Pattern::Noise => (
+                            random.next_f64() * width,
+                            random.next_f64() * height,
+                            random.next_f64() < 0.5,
+                        ),
+                    };
+                    if x >= 0.0 && x < width && y >= 0.0 && y < height {
+                        events.push(Event::Dvs {
+                            t: event_t,
+                            x: x as u16,
+                            y: y as u16,
+                            on,
+                        });
+                    }
+                    *next_t += random.next_interval(rate);
+                }
+            }
+        }
+        if self.parameters.trigger_period_us > 0 {
+            while *next_trigger_t < end_t {
+                events.push(Event::Trigger {
+                    t: *next_trigger_t,
+                    id: 0,
+                    rising: *next_trigger_rising,
+                });
+                *next_trigger_t += self.parameters.trigger_period_us / 2;
+                *next_trigger_rising = !*next_trigger_rising;
+            }
+        }
+        events.sort_by_key(|event| event.t());
+        if *pending_offset >= pending.len() {
+            pending.clear();
+            *pending_offset = 0;
+        }
+        encoder.encode_time_high(*t, pending);
+        for event in events.iter() {
+            encoder.encode(event, pending);
+        }
+        encoder.encode_time_high(end_t, pending);
+        *t = end_t;
+    }
+
+    // Copies the next buffer into `buffer` and returns its system time and instant.
+    // Returns None if the next slice is not due before the timeout.
+    pub fn next_with_timeout(
+        &self,
+        timeout: &std::time::Duration,
+        buffer: &mut Vec<u8>,
+    ) -> Option<(std::time::SystemTime, std::time::Instant)> {
+        let mut generator = self
+            .generator
+            .lock()
+            .expect("synthetic generator mutex is poisoned");
+        if generator.pending_offset >= generator.pending.len() {
+            let now = std::time::Instant::now();
+            let start = *generator.start.get_or_insert(now);
+            let deadline = start + std::time::Duration::from_micros(generator.t + SLICE_DURATION_US);
+            if deadline > now {
+                if deadline - now > *timeout {
+                    drop(generator);
+                    std::thread::sleep(*timeout);
+                    return None;
+                }
+                std::thread::sleep(deadline - now);
+            }
+            let elapsed_us = start.elapsed().as_micros() as u64;
+            let end_t = elapsed_us.min(generator.t + MAXIMUM_SLICE_DURATION_US);
+            self.generate(&mut generator, end_t);
+        }
+        let length = (generator.pending.len() - generator.pending_offset).min(self.buffer_length);
+        buffer.clear();
+        buffer.extend_from_slice(
+            &generator.pending[generator.pending_offset..generator.pending_offset + length],
+        );
+        generator.pending_offset += length;
+        Some((std::time::SystemTime::now(), std::time::Instant::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters(patterns: Vec<Pattern>, trigger_period_us: u64) -> Parameters {
+        Parameters {
+            width: 64,
+            height: 48,
+            event_rate: 1e6,
+            patterns,
+            trigger_period_us,
+        }
+    }
+
+    #[test]
+    fn new_validates_parameters() {
+        let mut invalid = parameters(vec![Pattern::Noise], 0);
+        invalid.width = 0;
+        assert!(Synthetic::new(invalid).is_err());
+        let mut invalid = parameters(vec![Pattern::Noise], 0);
+        invalid.event_rate = -1.0;
+        assert!(Synthetic::new(invalid).is_err());
+        assert!(Synthetic::new(parameters(vec![Pattern::Noise], 1)).is_err());
+        assert!(Synthetic::new(parameters(vec![], 2)).is_ok());
+    }
+
+    #[test]
+    fn generated_events_are_valid() {
+        let synthetic = Synthetic::new(parameters(
+            vec![Pattern::Bars, Pattern::Disc, Pattern::Noise],
+            1000,
+        ))
+        .unwrap();
+        let mut adapter = match synthetic.create_adapter() {
+            neuromorphic_drivers::Adapter::Evt3(adapter) => adapter,
+            _ => panic!("synthetic cameras use EVT3 adapters"),
+        };
+        let timeout = std::time::Duration::from_millis(100);
+        let mut buffer = Vec::new();
+        let mut dvs_events = 0;
+        let mut previous_t = 0;
+        let mut triggers = Vec::new();
+        let start = std::time::Instant::now();
+        while start.elapsed() < std::time::Duration::from_millis(50) {
+            if synthetic.next_with_timeout(&timeout, &mut buffer).is_some() {
+                assert!(buffer.len() <= synthetic.buffer_length);
+                assert_eq!(buffer.len() % 2, 0);
+                adapter.convert(
+                    &buffer,
+                    |dvs_event| {
+                        let t = dvs_event.t; // unpack field
+                        let x = dvs_event.x; // unpack field
+                        let y = dvs_event.y; // unpack field
+                        assert!(x < 64 && y < 48);
+                        assert!(t >= previous_t);
+                        previous_t = t;
+                        dvs_events += 1;
+                    },
+                    |trigger_event| triggers.push(trigger_event),
+                );
+            }
+        }
+        // about 1 event per µs
+        assert!(dvs_events > 1000);
+        assert!(triggers.len() > 10);
+        for (index, trigger_event) in triggers.iter().enumerate() {
+            let t = trigger_event.t; // unpack field
+            let id = trigger_event.id; // unpack field
+            let polarity = trigger_event.polarity; // unpack field
+            assert_eq!(t, index as u64 * 500);
+            assert_eq!(id, 0);
+            assert_eq!(
+                polarity,
+                if index % 2 == 0 {
+                    neuromorphic_drivers::types::TriggerPolarity::Rising
+                } else {
+                    neuromorphic_drivers::types::TriggerPolarity::Falling
+                }
+            );
+        }
+        assert_eq!(synthetic.sample(), (ILLUMINANCE, TEMPERATURE));
+    }
+}
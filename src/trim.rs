@@ -0,0 +1,347 @@
+use crate::manage;
+use crate::protocol;
+use crate::recordings;
+
+use std::io::Read;
+use std::io::Write;
+
+pub fn parse_utc(utc: &str) -> Result<u64, anyhow::Error> {
+    let datetime = chrono::DateTime::parse_from_rfc3339(utc)
+        .map_err(|error| anyhow::anyhow!("parsing \"{}\" failed ({})", utc, error))?;
+    Ok(datetime.timestamp_micros().max(0) as u64)
+}
+
+fn copy_header(
+    path: &std::path::Path,
+    length: u64,
+    file: &mut std::io::BufWriter<std::fs::File>,
+) -> Result<(), anyhow::Error> {
+    let mut header = vec![0u8; length as usize];
+    std::fs::File::open(path)?.read_exact(&mut header)?;
+    file.write_all(&header)?;
+    Ok(())
+}
+
+// Replaces the configuration with the one active at `begin_system_time`
+// and keeps the configuration updates in ]begin_system_time, end_system_time].
+fn trim_metadata(
+    metadata: &mut toml::Table,
+    begin_system_time: u64,
+    end_system_time: u64,
+) -> Result<(), anyhow::Error> {
+    if let Some(toml::Value::Array(configuration_updates)) =
+        metadata.remove("configuration_updates")
+    {
+        let mut new_configuration_updates = Vec::new();
+        for configuration_update in configuration_updates {
+            let system_time = parse_utc(
+                configuration_update
+                    .get("timestamp")
+                    .and_then(|timestamp| timestamp.as_str())
+                    .ok_or(anyhow::anyhow!("a configuration update has no timestamp"))?,
+            )?;
+            if system_time <= begin_system_time {
+                if let Some(configuration) = configuration_update.get("configuration") {
+                    metadata.insert("configuration".to_owned(), configuration.clone());
+                }
+            } else if system_time <= end_system_time {
+                new_configuration_updates.push(configuration_update);
+            }
+        }
+        if !new_configuration_updates.is_empty() {
+            metadata.insert(
+                "configuration_updates".to_owned(),
+                toml::Value::Array(new_configuration_updates),
+            );
+        }
+    }
+    Ok(())
+}
+
+// Copies the packets of `name` that overlap with `range` into a new recording.
+// The new raw file starts at a packet boundary, hence the first and last packets may contain
+// events outside the range. The first packet's decoder state is read from the index,
+// so the source recording is not decoded. Returns the new recording's name.
+pub fn trim(
+    data_directory: &std::path::Path,
+    name: &str,
+    range: &protocol::TrimRange,
+    new_name: &str,
+) -> Result<String, anyhow::Error> {
+    manage::check_name(name)?;
+    if recordings::recording_path(data_directory, name, recordings::RAW_FILE_EXTENSION, true)
+        .exists()
+    {
+        return Err(anyhow::anyhow!("the recording \"{}\" is ongoing", name));
+    }
+    let mut reader = recordings::Reader::open(data_directory, name)?;
+    let (key, begin, end): (fn(&recordings::Evt3IndexEntry) -> u64, u64, u64) = match range {
+        protocol::TrimRange::Timestamp { begin_t, end_t } => (
+            |index_entry: &recordings::Evt3IndexEntry| index_entry.state.t,
+            *begin_t,
+            *end_t,
+        ),
+        protocol::TrimRange::Utc { begin, end } => (
+            |index_entry: &recordings::Evt3IndexEntry| index_entry.system_time,
+            parse_utc(begin)?,
+            parse_utc(end)?,
+        ),
+    };
+    if end < begin {
+        return Err(anyhow::anyhow!(
+            "the range end ({}) is smaller than the range begin ({})",
+            end,
+            begin
+        ));
+    }
+    match range {
+        protocol::TrimRange::Timestamp { .. } => reader.seek_t(begin)?,
+        protocol::TrimRange::Utc { .. } => reader.seek_system_time(begin)?,
+    }
+    match reader.peek_index_entry()? {
+        Some(index_entry) if key(&index_entry) <= end => {}
+        _ => {
+            return Err(anyhow::anyhow!(
+                "the range [{}, {}] does not overlap with \"{}\"",
+                begin,
+                end,
+                name
+            ))
+        }
+    }
+    let new_name = if new_name.is_empty() {
+        format!("{}_trim_{}-{}", name, begin, end)
+    } else {
+        new_name.to_owned()
+    };
+    manage::check_name(&new_name)?;
+    for extension in [
+        recordings::RAW_FILE_EXTENSION,
+        recordings::INDEX_FILE_EXTENSION,
+        recordings::SAMPLES_FILE_EXTENSION,
+        recordings::METADATA_FILE_EXTENSION,
+    ] {
+        for write in [false, true] {
+            if recordings::recording_path(data_directory, &new_name, extension, write).exists() {
+                return Err(anyhow::anyhow!(
+                    "the recording \"{}\" already exists",
+                    new_name
+                ));
+            }
+        }
+    }
+    let result = write_trimmed(
+        data_directory,
+        name,
+        &new_name,
+        &mut reader,
+        key,
+        end,
+        range,
+    );
+    for extension in [
+        recordings::RAW_FILE_EXTENSION,
+        recordings::INDEX_FILE_EXTENSION,
+        recordings::SAMPLES_FILE_EXTENSION,
+        recordings::METADATA_FILE_EXTENSION,
+    ] {
+        let write_path = recordings::recording_path(data_directory, &new_name, extension, true);
+        if result.is_ok() {
+            std::fs::rename(
+                write_path,
+                recordings::recording_path(data_directory, &new_name, extension, false),
+            )?;
+        } else {
+            let _ = std::fs::remove_file(write_path);
+        }
+    }
+    result.map(|_| new_name)
+}
+
+fn write_trimmed(
+    data_directory: &std::path::Path,
+    name: &str,
+    new_name: &str,
+    reader: &mut recordings::Reader,
+    key: fn(&recordings::Evt3IndexEntry) -> u64,
+    end: u64,
+    range: &protocol::TrimRange,
+) -> Result<(), anyhow::Error> {
+    let first_index_entry = reader
+        .peek_index_entry()?
+        .ok_or(anyhow::anyhow!("the range does not contain any packet"))?;
+    let create = |extension: &str| -> Result<std::io::BufWriter<std::fs::File>, anyhow::Error> {
+        Ok(std::io::BufWriter::new(std::fs::File::create(
+            recordings::recording_path(data_directory, new_name, extension, true),
+        )?))
+    };
+
+    // raw and index files
+    // v1 headers are rewritten with the new start time, v0 headers are copied
+    let mut raw_file = create(recordings::RAW_FILE_EXTENSION)?;
+    let mut index_file = create(recordings::INDEX_FILE_EXTENSION)?;
+    let mut raw_file_offset = match reader.raw_header.description.as_ref() {
+        Some(description) => {
+            let description = recordings::Description {
+                start_system_time: first_index_entry.system_time,
+                ..description.clone()
+            };
+            recordings::write_header(
+                &mut index_file,
+                recordings::INDEX_FILE_SIGNATURE,
+                reader.index_header.file_type,
+                &description,
+            )?;
+            recordings::write_header(
+                &mut raw_file,
+                recordings::RAW_FILE_SIGNATURE,
+                reader.raw_header.file_type,
+                &description,
+            )?
+        }
+        None => {
+            copy_header(
+                &recordings::recording_path(
+                    data_directory,
+                    name,
+                    recordings::RAW_FILE_EXTENSION,
+                    false,
+                ),
+                reader.raw_header.length,
+                &mut raw_file,
+            )?;
+            copy_header(
+                &recordings::recording_path(
+                    data_directory,
+                    name,
+                    recordings::INDEX_FILE_EXTENSION,
+                    false,
+                ),
+                reader.index_header.length,
+                &mut index_file,
+            )?;
+            reader.raw_header.length
+        }
+    };
+    let mut last_index_entry = first_index_entry;
+    while let Some(index_entry) = reader.peek_index_entry()? {
+        if key(&index_entry) > end {
+            break;
+        }
+        reader.next_packet()?;
+        raw_file.write_all(reader.stored())?;
+        index_file.write_all(
+            &recordings::Evt3IndexEntry {
+                raw_file_offset,
+                ..index_entry
+            }
+            .to_bytes(),
+        )?;
+        raw_file_offset += index_entry.raw_length;
+        last_index_entry = index_entry;
+    }
+    raw_file.flush()?;
+    index_file.flush()?;
+
+    // samples file
+    {
+        let samples_path = recordings::recording_path(
+            data_directory,
+            name,
+            recordings::SAMPLES_FILE_EXTENSION,
+            false,
+        );
+        let mut source_samples_file = std::io::BufReader::new(std::fs::File::open(&samples_path)?);
+        let mut file_buffer = Vec::new();
+        let samples_header = recordings::read_header(
+            &mut file_buffer,
+            &mut source_samples_file,
+            recordings::SAMPLES_FILE_SIGNATURE.as_bytes(),
+        )?;
+        if samples_header.file_type != 0 {
+            return Err(anyhow::anyhow!(
+                "unsupported samples file type {}",
+                samples_header.file_type
+            ));
+        }
+        let mut samples_file = create(recordings::SAMPLES_FILE_EXTENSION)?;
+        samples_file.write_all(&file_buffer)?;
+        let mut sample_data = [0u8; recordings::EVK4_SAMPLE_LENGTH];
+        loop {
+            match source_samples_file.read_exact(&mut sample_data) {
+                Ok(()) => {
+                    let sample = recordings::Evk4Sample::from_bytes(&sample_data);
+                    if sample.system_timestamp > last_index_entry.system_timestamp {
+                        break;
+                    }
+                    if sample.system_timestamp >= first_index_entry.system_timestamp {
+                        samples_file.write_all(&sample_data)?;
+                    }
+                }
+                Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    break;
+                }
+                Err(error) => {
+                    return Err(error.into());
+                }
+            }
+        }
+        samples_file.flush()?;
+    }
+
+    // metadata file
+    {
+        let mut metadata =
+            toml::from_str::<toml::Table>(&std::fs::read_to_string(recordings::recording_path(
+                data_directory,
+                name,
+                recordings::METADATA_FILE_EXTENSION,
+                false,
+            ))?)?;
+        trim_metadata(
+            &mut metadata,
+            first_index_entry.system_time,
+            last_index_entry.system_time,
+        )?;
+        let source_timestamp = metadata.remove("timestamp");
+        let datetime =
+            chrono::DateTime::from_timestamp_micros(first_index_entry.system_time as i64)
+                .ok_or(anyhow::anyhow!(
+                    "the system time {} is out of range",
+                    first_index_entry.system_time
+                ))?
+                .with_timezone(&chrono::Local);
+        metadata.insert(
+            "timestamp".to_owned(),
+            toml::Value::String(crate::utc_string(&datetime)),
+        );
+        let mut trim = toml::Table::new();
+        trim.insert("source".to_owned(), toml::Value::String(name.to_owned()));
+        if let Some(source_timestamp) = source_timestamp {
+            trim.insert("source_timestamp".to_owned(), source_timestamp);
+        }
+        match range {
+            protocol::TrimRange::Timestamp { begin_t, end_t } => {
+                trim.insert("begin_t".to_owned(), toml::Value::Integer(*begin_t as i64));
+                trim.insert("end_t".to_owned(), toml::Value::Integer(*end_t as i64));
+            }
+            protocol::TrimRange::Utc { begin, end } => {
+                trim.insert("begin".to_owned(), toml::Value::String(begin.clone()));
+                trim.insert("end".to_owned(), toml::Value::String(end.clone()));
+            }
+        }
+        trim.insert(
+            "first_t".to_owned(),
+            toml::Value::Integer(first_index_entry.state.t as i64),
+        );
+        trim.insert(
+            "last_t".to_owned(),
+            toml::Value::Integer(last_index_entry.state.t as i64),
+        );
+        metadata.insert("trim".to_owned(), toml::Value::Table(trim));
+        let mut metadata_file = create(recordings::METADATA_FILE_EXTENSION)?;
+        metadata_file.write_all(toml::to_string(&metadata)?.as_bytes())?;
+        metadata_file.flush()?;
+    }
+    Ok(())
+}
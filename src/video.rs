@@ -0,0 +1,257 @@
+// Rendered previews of the events, written as a Y4M video or a sequence of PNG frames.
+// Frames use the web renderer's styles and colours (on events are light, off events are blue).
+use crate::filters;
+use crate::protocol;
+use crate::recordings;
+
+use std::io::Write;
+
+pub const Y4M_FILE_EXTENSION: &str = ".y4m";
+pub const PNG_FILE_EXTENSION: &str = ".png";
+
+// shorter frames would create more files than events (PNG) or unplayable videos (Y4M)
+pub const MINIMUM_FRAME_DURATION_US: u64 = 1000;
+
+// ends and middle of the default colormap (De sterrennacht)
+const BACKGROUND_COLOR: [f32; 3] = [25.0, 25.0, 25.0];
+const ON_COLOR: [f32; 3] = [249.0, 255.0, 231.0];
+const OFF_COLOR: [f32; 3] = [0.0, 130.0, 169.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Y4m,
+    Png,
+}
+
+// `frame_t` is the end of the frame (exclusive), frames include the events before `frame_t`
+struct Renderer {
+    width: u16,
+    height: u16,
+    options: protocol::VideoOptions,
+    ts: Vec<u64>,
+    ons: Vec<bool>,
+    frame_t: Option<u64>,
+    frame: Vec<u8>,
+}
+
+impl Renderer {
+    fn new(width: u16, height: u16, options: protocol::VideoOptions) -> Self {
+        let pixels = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            options: protocol::VideoOptions {
+                tau_us: options.tau_us.max(1),
+                ..options
+            },
+            ts: vec![u64::MAX; pixels],
+            ons: vec![false; pixels],
+            frame_t: None,
+            frame: vec![0u8; pixels * 3],
+        }
+    }
+
+    // Renders the frames that end before `t` (handle_frame is called once per frame),
+    // then stores the event.
+    fn push<HandleFrame>(
+        &mut self,
+        t: u64,
+        x: u16,
+        y: u16,
+        on: bool,
+        mut handle_frame: HandleFrame,
+    ) -> Result<(), anyhow::Error>
+    where
+        HandleFrame: FnMut(&[u8]) -> Result<(), anyhow::Error>,
+    {
+        let mut frame_t = self.frame_t.unwrap_or(t + self.options.frame_duration_us);
+        while frame_t <= t {
+            self.render(frame_t);
+            handle_frame(&self.frame)?;
+            frame_t += self.options.frame_duration_us;
+        }
+        self.frame_t = Some(frame_t);
+        if x < self.width && y < self.height {
+            let index = x as usize + y as usize * self.width as usize;
+            self.ts[index] = t;
+            self.ons[index] = on;
+        }
+        Ok(())
+    }
+
+    // Renders the last (partial) frame, if any.
+    fn flush<HandleFrame>(&mut self, mut handle_frame: HandleFrame) -> Result<(), anyhow::Error>
+    where
+        HandleFrame: FnMut(&[u8]) -> Result<(), anyhow::Error>,
+    {
+        if let Some(frame_t) = self.frame_t.take() {
+            self.render(frame_t);
+            handle_frame(&self.frame)?;
+        }
+        Ok(())
+    }
+
+    fn render(&mut self, frame_t: u64) {
+        let tau = self.options.tau_us as f32;
+        for (index, (t, on)) in self.ts.iter().zip(self.ons.iter()).enumerate() {
+            let lambda = if *t == u64::MAX || *t >= frame_t {
+                0.0f32
+            } else {
+                let delta_t = (frame_t - t) as f32;
+                match self.options.style {
+                    protocol::VideoStyle::ExponentialDecay => (-delta_t / tau).exp(),
+                    protocol::VideoStyle::TimeSurface => {
+                        if delta_t < tau * 2.0 {
+                            1.0 - delta_t / (tau * 2.0)
+                        } else {
+                            0.0
+                        }
+                    }
+                    protocol::VideoStyle::Accumulation => {
+                        if delta_t < tau {
+                            1.0
+                        } else {
+                            0.0
+                        }
+                    }
+                }
+            };
+            let color = if *on { ON_COLOR } else { OFF_COLOR };
+            for channel in 0..3 {
+                self.frame[index * 3 + channel] = (BACKGROUND_COLOR[channel] * (1.0 - lambda)
+                    + color[channel] * lambda)
+                    .round() as u8;
+            }
+        }
+    }
+}
+
+// Writes a YUV 4:4:4 frame (BT.601, limited range) from RGB pixels.
+fn write_y4m_frame(
+    output: &mut impl Write,
+    frame: &[u8],
+    planes: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    let pixels = frame.len() / 3;
+    planes.clear();
+    planes.resize(pixels * 3, 0);
+    for (index, rgb) in frame.chunks_exact(3).enumerate() {
+        let r = rgb[0] as f32;
+        let g = rgb[1] as f32;
+        let b = rgb[2] as f32;
+        planes[index] = (16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8;
+        planes[pixels + index] = (128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8;
+        planes[pixels * 2 + index] = (128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8;
+    }
+    output.write_all(b"FRAME\n")?;
+    output.write_all(planes)?;
+    Ok(())
+}
+
+fn encode_png(
+    width: u16,
+    height: u16,
+    frame: &[u8],
+    png_bytes: &mut Vec<u8>,
+) -> Result<(), anyhow::Error> {
+    png_bytes.clear();
+    let mut encoder = png::Encoder::new(&mut *png_bytes, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(frame)?;
+    writer.finish()?;
+    Ok(())
+}
+
+pub fn check_options(options: &protocol::VideoOptions) -> Result<(), anyhow::Error> {
+    if options.frame_duration_us < MINIMUM_FRAME_DURATION_US {
+        return Err(anyhow::anyhow!(
+            "the video frame duration must be at least {} µs (got {})",
+            MINIMUM_FRAME_DURATION_US,
+            options.frame_duration_us
+        ));
+    }
+    Ok(())
+}
+
+// Writes the video (Y4M) or the frames (PNG) of `name` to the zip archive.
+// PNG files are already compressed, hence they are stored.
+// Returns false if the conversion was cancelled.
+pub fn write<W: std::io::Write + std::io::Seek>(
+    zip: &mut zip::ZipWriter<W>,
+    reader: &mut recordings::Reader,
+    pass: &recordings::Pass,
+    container: Container,
+) -> Result<bool, anyhow::Error> {
+    let name = pass.name;
+    let options = pass.options;
+    let convert_options = pass.convert_options;
+    let selection = &pass.selection;
+    reader.rewind()?;
+    let mut chain = filters::Chain::new(&convert_options.filters, reader.width, reader.height)?;
+    let width = chain.width;
+    let height = chain.height;
+    let mut renderer = Renderer::new(width, height, convert_options.video);
+    let mut frame_index = 0u64;
+    let mut planes = Vec::new();
+    let mut png_bytes = Vec::new();
+    let png_options = options
+        .compression_method(zip::CompressionMethod::Stored)
+        .compression_level(None);
+    let mut handle_frame =
+        |zip: &mut zip::ZipWriter<W>, frame: &[u8]| -> Result<(), anyhow::Error> {
+            match container {
+                Container::Y4m => write_y4m_frame(zip, frame, &mut planes)?,
+                Container::Png => {
+                    encode_png(width, height, frame, &mut png_bytes)?;
+                    zip.start_file(
+                        format!("{name}/{name}_frames/{frame_index:06}{PNG_FILE_EXTENSION}"),
+                        png_options,
+                    )?;
+                    zip.write_all(&png_bytes)?;
+                }
+            }
+            frame_index += 1;
+            Ok(())
+        };
+    if container == Container::Y4m {
+        zip.start_file(format!("{name}/{name}{Y4M_FILE_EXTENSION}"), options)?;
+        zip.write_all(
+            format!(
+                "YUV4MPEG2 W{width} H{height} F1000000:{} Ip A1:1 C444\n",
+                renderer.options.frame_duration_us
+            )
+            .as_bytes(),
+        )?;
+    }
+
+    // events are buffered per packet since the callbacks cannot return errors
+    let mut events = Vec::new();
+    while let Some(index_entry) = reader.next_packet()? {
+        pass.progress.add(index_entry.raw_length);
+        reader.convert(
+            |dvs_event| {
+                let t = dvs_event.t; // unpack field
+                let x = dvs_event.x; // unpack field
+                let y = dvs_event.y; // unpack field
+                let on = dvs_event.polarity as u8 == 1; // unpack field
+                if !selection.contains_t(t) {
+                    return;
+                }
+                if let Some((x, y)) = chain.filter(t, x, y, on) {
+                    events.push((t, x, y, on));
+                }
+            },
+            |_| {},
+        );
+        for (t, x, y, on) in events.drain(..) {
+            renderer.push(t, x, y, on, |frame| handle_frame(zip, frame))?;
+        }
+        if pass.cancelled() {
+            return Ok(false);
+        }
+    }
+    renderer.flush(|frame| handle_frame(zip, frame))?;
+    Ok(true)
+}
@@ -1,5 +1,6 @@
 import type { Layout } from "./constants";
 import type { Configuration } from "./deviceConfiguration";
+import type { ConvertOptions } from "./protocol.svelte";
 
 import * as utilities from "./utilities";
 import * as constants from "./constants";
@@ -34,6 +35,17 @@ export interface Autotrigger {
     threshold: number;
 }
 
+export interface Segmenting {
+    enabled: boolean;
+    maximum_duration_us: number;
+    maximum_size_bytes: number;
+}
+
+export interface Durability {
+    period_ms: number;
+    size_bytes: number;
+}
+
 interface Device {
     id: number;
     name: string;
@@ -46,6 +58,8 @@ interface Device {
     lookback: Lookback;
     autostop: Autostop;
     autotrigger: Autotrigger;
+    durability: Durability;
+    segmenting: Segmenting;
 }
 
 interface SharedState {
@@ -150,6 +164,7 @@ export interface RecordState {
         name: string;
         duration_us: bigint;
         size_bytes: bigint;
+        durable_offset: bigint;
     } | null;
 }
 
@@ -165,26 +180,53 @@ type RecordingState =
           type: "Complete";
           size_bytes: number;
           zip: boolean;
+          variants: string[];
       }
     | {
           type: "Queued";
           size_bytes: number;
           zip: boolean;
+          variants: string[];
+          options: ConvertOptions;
       }
     | {
           type: "Converting";
           size_bytes: number;
           zip: boolean;
+          variants: string[];
+          options: ConvertOptions;
+          processed_bytes: number;
+          total_bytes: number;
+          remaining_ms: number | null;
       };
 
+// times in µs
+export interface Summary {
+    on_events: number;
+    off_events: number;
+    triggers: number;
+    duration_us: number;
+    wall_duration_us: number;
+    overflows: number;
+}
+
 export interface Recording {
     name: string;
     state: RecordingState;
+    summary: Summary | null;
+}
+
+// recording deleted with `trash`, stored in DATA/trash/ENTRY
+export interface TrashedRecording {
+    entry: string;
+    name: string;
 }
 
 interface SharedRecordingsState {
     data_directory: string;
+    move_directories: string[];
     recordings: Recording[];
+    trash: TrashedRecording[];
 }
 
 interface AppState {
@@ -203,7 +245,9 @@ const appState: AppState = $state({
     },
     sharedRecordings: {
         data_directory: "",
+        move_directories: [],
         recordings: [],
+        trash: [],
     },
     local: {
         connectionStatus: "connecting",
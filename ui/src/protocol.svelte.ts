@@ -1,10 +1,12 @@
 import type {
     Autostop,
     Autotrigger,
+    Durability,
     EventDisplayProperties,
     Lookback,
     RecordState,
     SampleDisplayProperties,
+    Segmenting,
 } from "./appState.svelte";
 import type { Configuration } from "./deviceConfiguration";
 
@@ -174,10 +176,11 @@ transportWorker.addEventListener("message", ({ data }) => {
                         ? null
                         : {
                               name: decoder.decode(
-                                  new Uint8Array(data.buffer, 42, size - 42),
+                                  new Uint8Array(data.buffer, 50, size - 50),
                               ),
                               duration_us: dataView.getBigUint64(26, true),
                               size_bytes: dataView.getBigUint64(34, true),
+                              durable_offset: dataView.getBigUint64(42, true),
                           },
             };
             transportWorker.postMessage(
@@ -569,16 +572,145 @@ export function updateAutotrigger(deviceId: number, autotrigger: Autotrigger) {
     });
 }
 
-export function convert(names: string[]) {
+export function updateDurability(deviceId: number, durability: Durability) {
+    sendMessageToServer({
+        type: "UpdateDurability",
+        device_id: deviceId,
+        durability,
+    });
+}
+
+export function updateSegmenting(deviceId: number, segmenting: Segmenting) {
+    sendMessageToServer({
+        type: "UpdateSegmenting",
+        device_id: deviceId,
+        segmenting,
+    });
+}
+
+export type TrimRange =
+    | {
+          type: "Timestamp";
+          begin_t: number;
+          end_t: number;
+      }
+    | {
+          type: "Utc";
+          begin: string;
+          end: string;
+      };
+
+export function trim(name: string, range: TrimRange, newName: string) {
+    sendMessageToServer({
+        type: "Trim",
+        name,
+        range,
+        new_name: newName,
+    });
+}
+
+// recomputes the statistics of all the complete recordings if `names` is empty
+export function computeStatistics(names: string[] = []) {
+    sendMessageToServer({
+        type: "ComputeStatistics",
+        names,
+    });
+}
+
+// deleted recordings are moved to the trash if `trash` is true
+export function deleteRecordings(names: string[], trash = true) {
+    sendMessageToServer({
+        type: "DeleteRecordings",
+        names,
+        trash,
+    });
+}
+
+export function renameRecording(name: string, newName: string) {
+    sendMessageToServer({
+        type: "RenameRecording",
+        name,
+        new_name: newName,
+    });
+}
+
+// moves recordings to another data directory (one of sharedRecordings.move_directories)
+export function moveRecordings(names: string[], dataDirectory: string) {
+    sendMessageToServer({
+        type: "MoveRecordings",
+        names,
+        data_directory: dataDirectory,
+    });
+}
+
+export function restoreRecordings(entries: string[]) {
+    sendMessageToServer({
+        type: "RestoreRecordings",
+        entries,
+    });
+}
+
+// permanently deletes all the trashed recordings if `entries` is empty
+export function emptyTrash(entries: string[] = []) {
+    sendMessageToServer({
+        type: "EmptyTrash",
+        entries,
+    });
+}
+
+export type ConvertFormat =
+    | "csv"
+    | "es"
+    | "aedat4"
+    | "metavision-raw"
+    | "npz"
+    | "arrow"
+    | "y4m"
+    | "png";
+
+export type VideoStyle = "exponential-decay" | "time-surface" | "accumulation";
+
+// filters are applied in order to polarity events (triggers are not filtered)
+export type Filter =
+    | { type: "Roi"; left: number; top: number; width: number; height: number }
+    | { type: "Polarity"; on: boolean }
+    | { type: "Time"; begin_t: number; end_t: number }
+    | { type: "BackgroundActivity"; tau_us: number }
+    | { type: "Refractory"; period_us: number };
+
+export interface VideoOptions {
+    frame_duration_us: number;
+    tau_us: number;
+    style: VideoStyle;
+}
+
+export interface ConvertOptions {
+    variant: string;
+    formats: ConvertFormat[];
+    compression_level: number;
+    index: boolean;
+    samples: boolean;
+    triggers: boolean;
+    range?: TrimRange;
+    video: VideoOptions;
+    filters: Filter[];
+    utc: boolean;
+}
+
+// the server uses its default options if `options` is null
+export function convert(names: string[], options: ConvertOptions | null = null) {
     sendMessageToServer({
         type: "Convert",
         names,
+        options,
     });
 }
 
-export function cancelConvert() {
+// cancels all the conversions if `names` is empty
+export function cancelConvert(names: string[] = []) {
     sendMessageToServer({
         type: "CancelConvert",
+        names,
     });
 }
 